@@ -0,0 +1,624 @@
+//! A libretro core over the CHIP-8 machine, so Chippy can run inside
+//! RetroArch and other libretro frontends. It's a crate of its own,
+//! since the core's library can't be a cdylib without std.
+//!
+//! ROMs are played with the keyboard in the usual layout (1234, QWER,
+//! ASDF, ZXCV), and the d-pad and A button cover 2/4/6/8 and 5, which
+//! most games use to move and act. The quirks are core options, and
+//! battery-backed memory is the game's save RAM.
+
+use std::ffi::{ c_void, CStr };
+use std::os::raw::{ c_char, c_uint };
+use std::sync::Mutex;
+
+use chippy::analysis;
+use chippy::chip8::{ Chip8, VIDEO_HEIGHT, VIDEO_WIDTH };
+use chippy::quirks::{ MemoryRange, Random };
+use chippy::state::MachineState;
+
+// the parts of libretro.h the core uses //
+
+const RETRO_API_VERSION: c_uint = 1;
+
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_KEYBOARD: c_uint = 3;
+
+const RETRO_DEVICE_ID_JOYPAD_B: c_uint = 0;
+const RETRO_DEVICE_ID_JOYPAD_UP: c_uint = 4;
+const RETRO_DEVICE_ID_JOYPAD_DOWN: c_uint = 5;
+const RETRO_DEVICE_ID_JOYPAD_LEFT: c_uint = 6;
+const RETRO_DEVICE_ID_JOYPAD_RIGHT: c_uint = 7;
+const RETRO_DEVICE_ID_JOYPAD_A: c_uint = 8;
+
+const RETRO_REGION_NTSC: c_uint = 0;
+
+const RETRO_MEMORY_SAVE_RAM: c_uint = 0;
+
+type EnvironmentFn = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+type VideoRefreshFn = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleFn = unsafe extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = unsafe extern "C" fn();
+type InputStateFn = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+// the core //
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: f64 = 44100.0;
+const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / FPS) as usize;
+
+/// The pitch of the buzzer, and how loud it is.
+const BUZZER_FREQUENCY: f64 = 440.0;
+const BUZZER_AMPLITUDE: i16 = 0x1000;
+
+/// How many bits of an XO-CHIP audio pattern play a second at pitch 64.
+const PATTERN_RATE: f64 = 4000.0;
+
+/// Save states are a length followed by a binary state, padded out to
+/// this size since libretro wants it known up front. Binary states are
+/// compressed and come to well under half of this.
+const SERIALIZE_SIZE: usize = 16 * 1024;
+
+const ON_PIXEL: u32 = 0x005E48E8;
+const OFF_PIXEL: u32 = 0x0048B2E8;
+
+/// The keypad key under every keyboard key, in the layout of the
+/// keypad: 1234, QWER, ASDF, ZXCV. Keyboard ids are ASCII.
+const KEYBOARD: [(u8, usize); 16] = [
+    (b'1', 0x1), (b'2', 0x2), (b'3', 0x3), (b'4', 0xC),
+    (b'q', 0x4), (b'w', 0x5), (b'e', 0x6), (b'r', 0xD),
+    (b'a', 0x7), (b's', 0x8), (b'd', 0x9), (b'f', 0xE),
+    (b'z', 0xA), (b'x', 0x0), (b'c', 0xB), (b'v', 0xF),
+];
+
+const JOYPAD: [(c_uint, usize); 6] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0),
+];
+
+const FX0A_ON_PRESS: &[u8] = b"chippy_fx0a_on_press\0";
+const FX1E_OVERFLOW_VF: &[u8] = b"chippy_fx1e_overflow_vf\0";
+const BATTERY_RAM: &[u8] = b"chippy_battery_ram\0";
+const SPRITES_PER_FRAME: &[u8] = b"chippy_sprites_per_frame\0";
+const RANDOM: &[u8] = b"chippy_random\0";
+
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+struct Core {
+    chippy: Chip8,
+    // as many as the ROM's platform usually needs.
+    instructions_per_frame: u32,
+    // the machine as it was loaded, for resets.
+    boot_state: MachineState,
+    frame: Vec<u32>,
+    audio: Vec<i16>,
+    buzzer_phase: f64,
+    // how far into the XO-CHIP audio pattern it is, in bits.
+    pattern_position: f64,
+}
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+/// A pointer to a NUL-terminated byte string, for handing to C.
+fn c_str(bytes: &'static [u8]) -> *const c_char {
+    debug_assert_eq!(bytes.last(), Some(&0));
+    bytes.as_ptr() as *const c_char
+}
+
+fn callbacks() -> std::sync::MutexGuard<'static, Callbacks> {
+    CALLBACKS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn with_core<T>(f: impl FnOnce(&mut Core) -> T) -> Option<T> {
+    let mut core = CORE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    core.as_mut().map(f)
+}
+
+/// Reads a core option's value, if the frontend has one.
+fn option(environment: EnvironmentFn, key: &'static [u8]) -> Option<String> {
+    let mut variable = Variable { key: c_str(key), value: std::ptr::null() };
+
+    // safety: GET_VARIABLE takes a `retro_variable` and fills in its value.
+    let found = unsafe { environment(RETRO_ENVIRONMENT_GET_VARIABLE, &mut variable as *mut _ as *mut c_void) };
+    if !found || variable.value.is_null() {
+        return None;
+    }
+    // safety: the frontend hands back a NUL-terminated string.
+    Some(unsafe { CStr::from_ptr(variable.value) }.to_string_lossy().into_owned())
+}
+
+fn option_enabled(environment: EnvironmentFn, key: &'static [u8]) -> bool {
+    option(environment, key).as_deref() == Some("enabled")
+}
+
+fn apply_options(chippy: &mut Chip8) {
+    if let Some(environment) = callbacks().environment {
+        chippy.quirks.fx0a_on_press = option_enabled(environment, FX0A_ON_PRESS);
+        chippy.quirks.fx1e_overflow_vf = option_enabled(environment, FX1E_OVERFLOW_VF);
+        chippy.quirks.battery_ram = Some(MemoryRange::BATTERY).filter(|_| option_enabled(environment, BATTERY_RAM));
+        chippy.quirks.sprites_per_frame = option(environment, SPRITES_PER_FRAME).and_then(|value| value.parse().ok());
+        chippy.quirks.random = match option(environment, RANDOM).as_deref() {
+            Some("vip") => Random::Vip,
+            Some("sequence") => Random::Sequence,
+            _ => Random::Prng,
+        };
+    }
+}
+
+impl Core {
+    fn read_input(&mut self) {
+        let callbacks = callbacks();
+        let (poll, state) = match (callbacks.input_poll, callbacks.input_state) {
+            (Some(poll), Some(state)) => (poll, state),
+            _ => return,
+        };
+
+        // safety: the frontend's callbacks are valid while the core is loaded.
+        unsafe {
+            poll();
+
+            self.chippy.keypad = [false; 16];
+            for &(key, pad) in KEYBOARD.iter() {
+                if state(0, RETRO_DEVICE_KEYBOARD, 0, key as c_uint) != 0 {
+                    self.chippy.keypad[pad] = true;
+                }
+            }
+            for &(button, pad) in JOYPAD.iter() {
+                if state(0, RETRO_DEVICE_JOYPAD, 0, button) != 0 {
+                    self.chippy.keypad[pad] = true;
+                }
+            }
+        }
+    }
+
+    fn run_frame(&mut self) {
+        for _ in 0..self.instructions_per_frame {
+            // there's no debugger to hand traps to, the machine just stops.
+            if self.chippy.cycle().is_err() {
+                break;
+            }
+        }
+        self.chippy.tick_timers();
+    }
+
+    fn present(&mut self) {
+        for (pixel, &lit) in self.frame.iter_mut().zip(self.chippy.display_memory.iter()) {
+            *pixel = if lit != 0 { ON_PIXEL } else { OFF_PIXEL };
+        }
+
+        self.audio.clear();
+        for _ in 0..SAMPLES_PER_FRAME {
+            let high = match self.chippy.audio_pattern() {
+                _ if !self.chippy.is_beeping() => None,
+                Some(pattern) => {
+                    // 4000 bits a second at pitch 64, doubling every 48 up.
+                    let rate = PATTERN_RATE * 2f64.powf((self.chippy.pitch() as f64 - 64.0) / 48.0);
+                    let bit = self.pattern_position as usize;
+                    self.pattern_position = (self.pattern_position + rate / SAMPLE_RATE) % 128.0;
+                    Some(pattern[bit / 8] & 0x80 >> (bit % 8) != 0)
+                },
+                None => Some(self.buzzer_phase < 0.5),
+            };
+            let sample = match high {
+                None => 0,
+                Some(true) => BUZZER_AMPLITUDE,
+                Some(false) => -BUZZER_AMPLITUDE,
+            };
+            self.buzzer_phase = (self.buzzer_phase + BUZZER_FREQUENCY / SAMPLE_RATE).fract();
+            self.audio.extend_from_slice(&[sample, sample]);
+        }
+
+        let callbacks = callbacks();
+        // safety: both buffers outlive the calls, and are the sizes given.
+        unsafe {
+            if let Some(video_refresh) = callbacks.video_refresh {
+                video_refresh(self.frame.as_ptr() as *const c_void, VIDEO_WIDTH, VIDEO_HEIGHT,
+                    VIDEO_WIDTH as usize * std::mem::size_of::<u32>());
+            }
+            if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+                audio_sample_batch(self.audio.as_ptr(), SAMPLES_PER_FRAME);
+            }
+        }
+    }
+}
+
+// the libretro API //
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: EnvironmentFn) {
+    callbacks().environment = Some(environment);
+
+    let variables = [
+        Variable { key: c_str(FX0A_ON_PRESS), value: c_str(b"FX0A completes on key press; disabled|enabled\0") },
+        Variable { key: c_str(FX1E_OVERFLOW_VF), value: c_str(b"FX1E sets VF on overflow; disabled|enabled\0") },
+        Variable { key: c_str(BATTERY_RAM), value: c_str(b"Battery-backed memory at 0xE00-0xFFF; disabled|enabled\0") },
+        Variable { key: c_str(SPRITES_PER_FRAME), value: c_str(b"Sprites drawn a frame; unlimited|1|2|4|8|16\0") },
+        Variable { key: c_str(RANDOM), value: c_str(b"Random numbers; prng|vip|sequence\0") },
+        Variable { key: std::ptr::null(), value: std::ptr::null() },
+    ];
+    // safety: SET_VARIABLES takes a null-terminated array of `retro_variable`s.
+    unsafe {
+        environment(RETRO_ENVIRONMENT_SET_VARIABLES, variables.as_ptr() as *mut c_void);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: VideoRefreshFn) {
+    callbacks().video_refresh = Some(video_refresh);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: AudioSampleBatchFn) {
+    callbacks().audio_sample_batch = Some(audio_sample_batch);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: InputPollFn) {
+    callbacks().input_poll = Some(input_poll);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: InputStateFn) {
+    callbacks().input_state = Some(input_state);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+/// # Safety
+///
+/// `info` has to point at a `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: c_str(b"Chippy\0"),
+        library_version: c_str(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()),
+        valid_extensions: c_str(b"ch8|c8\0"),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` has to point at a `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: VIDEO_WIDTH,
+            base_height: VIDEO_HEIGHT,
+            max_width: VIDEO_WIDTH,
+            max_height: VIDEO_HEIGHT,
+            aspect_ratio: VIDEO_WIDTH as f32 / VIDEO_HEIGHT as f32,
+        },
+        timing: SystemTiming { fps: FPS, sample_rate: SAMPLE_RATE },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// Puts the machine back the way it was loaded, but for the save RAM,
+/// which outlives a reset, and the core options as they are now.
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core(|core| {
+        let battery = core.chippy.battery_ram().map(<[u8]>::to_vec);
+        core.chippy.load_state(&core.boot_state);
+        apply_options(&mut core.chippy);
+        if let Some(battery) = battery {
+            core.chippy.restore_battery_ram(&battery);
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let environment = callbacks().environment;
+    with_core(|core| {
+        if let Some(environment) = environment {
+            let mut updated = false;
+            // safety: GET_VARIABLE_UPDATE takes a bool to set.
+            unsafe {
+                environment(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut _ as *mut c_void);
+            }
+            if updated {
+                apply_options(&mut core.chippy);
+            }
+        }
+
+        core.read_input();
+        core.run_frame();
+        core.present();
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    SERIALIZE_SIZE
+}
+
+/// # Safety
+///
+/// `data` has to point at `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let state = match with_core(|core| core.chippy.save_state().to_bytes()) {
+        Some(state) => state,
+        None => return false,
+    };
+    if size < SERIALIZE_SIZE || state.len() + 4 > SERIALIZE_SIZE {
+        return false;
+    }
+
+    let buffer = std::slice::from_raw_parts_mut(data as *mut u8, size);
+    buffer.fill(0);
+    buffer[..4].copy_from_slice(&(state.len() as u32).to_le_bytes());
+    buffer[4..4 + state.len()].copy_from_slice(&state);
+    true
+}
+
+/// # Safety
+///
+/// `data` has to point at `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let buffer = std::slice::from_raw_parts(data as *const u8, size);
+    if size < 4 {
+        return false;
+    }
+
+    let length = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+    let state = match buffer.get(4..4 + length).map(MachineState::from_bytes) {
+        Some(Ok(state)) => state,
+        _ => return false,
+    };
+
+    with_core(|core| core.chippy.load_state(&state)).is_some()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+///
+/// `game` has to point at a `retro_game_info` with the ROM loaded.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let rom = std::slice::from_raw_parts((*game).data as *const u8, (*game).size);
+
+    let environment = match callbacks().environment {
+        Some(environment) => environment,
+        None => return false,
+    };
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut _ as *mut c_void) {
+        return false;
+    }
+
+    let mut chippy = match Chip8::initialize(rom) {
+        Ok(chippy) => chippy,
+        Err(_) => return false,
+    };
+    apply_options(&mut chippy);
+
+    *CORE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Core {
+        boot_state: chippy.save_state(),
+        chippy,
+        instructions_per_frame: analysis::analyze(rom).platform.instructions_per_frame(),
+        frame: vec![OFF_PIXEL; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize],
+        audio: Vec::with_capacity(SAMPLES_PER_FRAME * 2),
+        buzzer_phase: 0.0,
+        pattern_position: 0.0,
+    });
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// The save RAM is battery-backed memory, in place, when the option is
+/// on. Frontends ask for it once the game's loaded, so turning the option
+/// on or off takes a reload.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    if id != RETRO_MEMORY_SAVE_RAM {
+        return std::ptr::null_mut();
+    }
+    with_core(|core| core.chippy.battery_ram_mut().map(|memory| memory.as_mut_ptr() as *mut c_void))
+        .flatten()
+        .unwrap_or(std::ptr::null_mut())
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    if id != RETRO_MEMORY_SAVE_RAM {
+        return 0;
+    }
+    with_core(|core| core.chippy.battery_ram().map_or(0, <[u8]>::len)).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the tests share the one core.
+    static CORE_IN_USE: Mutex<()> = Mutex::new(());
+
+    fn load(chippy: Chip8) {
+        *CORE.lock().unwrap() = Some(Core {
+            boot_state: chippy.save_state(),
+            chippy,
+            instructions_per_frame: 0,
+            frame: Vec::new(),
+            audio: Vec::new(),
+            buzzer_phase: 0.0,
+            pattern_position: 0.0,
+        });
+    }
+
+    #[test]
+    fn save_states_round_trip_through_the_frontend_buffer() {
+        let _core = CORE_IN_USE.lock().unwrap();
+        load(Chip8::new());
+        let mut buffer = vec![0xAA; retro_serialize_size()];
+
+        unsafe {
+            assert!(retro_serialize(buffer.as_mut_ptr() as *mut c_void, buffer.len()));
+            with_core(|core| core.chippy.display_memory[0] = 0xFF);
+            assert!(retro_unserialize(buffer.as_ptr() as *const c_void, buffer.len()));
+        }
+
+        assert_eq!(with_core(|core| core.chippy.display_memory[0]), Some(0));
+        assert!(unsafe { !retro_unserialize(buffer.as_ptr() as *const c_void, 3) });
+    }
+
+    #[test]
+    fn save_ram_is_the_battery_backed_memory() {
+        let _core = CORE_IN_USE.lock().unwrap();
+        load(Chip8::new());
+        assert_eq!(retro_get_memory_size(RETRO_MEMORY_SAVE_RAM), 0);
+        assert!(retro_get_memory_data(RETRO_MEMORY_SAVE_RAM).is_null());
+
+        let mut chippy = Chip8::new();
+        chippy.quirks.battery_ram = Some(MemoryRange::BATTERY);
+        load(chippy);
+        assert_eq!(retro_get_memory_size(RETRO_MEMORY_SAVE_RAM), 0x200);
+        // safety: the save RAM is as big as its size says.
+        unsafe { *(retro_get_memory_data(RETRO_MEMORY_SAVE_RAM) as *mut u8) = 0x42 };
+
+        assert_eq!(with_core(|core| core.chippy.battery_ram().unwrap()[0]), Some(0x42));
+    }
+
+    /// A frontend with battery-backed memory and FX1E's VF quirk on.
+    unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+        if cmd != RETRO_ENVIRONMENT_GET_VARIABLE {
+            return false;
+        }
+        let variable = &mut *(data as *mut Variable);
+        match CStr::from_ptr(variable.key).to_bytes_with_nul() {
+            BATTERY_RAM | FX1E_OVERFLOW_VF => variable.value = c_str(b"enabled\0"),
+            _ => return false,
+        }
+        true
+    }
+
+    #[test]
+    fn resets_keep_the_save_ram_and_the_options() {
+        let _core = CORE_IN_USE.lock().unwrap();
+        callbacks().environment = Some(environment);
+        // loaded before FX1E's quirk was turned on
+        let mut chippy = Chip8::new();
+        chippy.quirks.battery_ram = Some(MemoryRange::BATTERY);
+        load(chippy);
+        // safety: the save RAM is as big as its size says.
+        unsafe { *(retro_get_memory_data(RETRO_MEMORY_SAVE_RAM) as *mut u8) = 0x42 };
+
+        retro_reset();
+        callbacks().environment = None;
+
+        assert_eq!(with_core(|core| core.chippy.battery_ram().unwrap()[0]), Some(0x42));
+        assert_eq!(with_core(|core| core.chippy.quirks.fx1e_overflow_vf), Some(true));
+    }
+}
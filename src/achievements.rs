@@ -0,0 +1,196 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use chippy::chip8::Chip8;
+
+/// Where achievement definitions are looked for, relative to the working
+/// directory: a file for each game, named after its SHA-1 (which
+/// `chippy info` prints), e.g. `achievements/5f5180...0563.toml`.
+pub const ACHIEVEMENTS_DIR: &str = "achievements";
+
+/// The achievements for a game, each won the first time all of its
+/// conditions on memory hold at the end of a frame:
+///
+/// ```toml
+/// [[achievement]]
+/// name = "Cleared 10 lines!"
+/// conditions = ["0x3A0 >= 10"]
+///
+/// [[achievement]]
+/// name = "Perfect start"
+/// conditions = ["0x3A0 >= 1", "0x3A2 == 0"]
+/// ```
+pub struct Achievements {
+    achievements: Vec<Achievement>,
+}
+
+#[derive(Deserialize)]
+struct Definitions {
+    #[serde(default, rename = "achievement")]
+    achievements: Vec<Definition>,
+}
+
+#[derive(Deserialize)]
+struct Definition {
+    name: String,
+    conditions: Vec<String>,
+}
+
+struct Achievement {
+    name: String,
+    conditions: Vec<Condition>,
+    won: bool,
+}
+
+/// A byte of memory compared with a value, like `0x3A0 >= 10`.
+#[derive(Debug, PartialEq)]
+pub struct Condition {
+    address: usize,
+    comparison: Comparison,
+    value: u8,
+}
+
+#[derive(Debug, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Achievements {
+    /// Loads the achievements for a game, if it has any. Broken files are
+    /// reported and leave the game without achievements.
+    pub fn load(game: &str) -> Self {
+        let path = Path::new(ACHIEVEMENTS_DIR).join(game).with_extension("toml");
+        let none = Self { achievements: Vec::new() };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).unwrap_or_else(|e| {
+                eprintln!("chippy: failed to read {}: {}", path.display(), e);
+                none
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => none,
+            Err(e) => {
+                eprintln!("chippy: failed to read {}: {}", path.display(), e);
+                none
+            },
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let definitions: Definitions = toml::from_str(text).map_err(|e| e.to_string())?;
+
+        let achievements = definitions.achievements.into_iter()
+            .map(|definition| {
+                let conditions = definition.conditions.iter()
+                    .map(|condition| condition.parse())
+                    .collect::<Result<Vec<Condition>, String>>()
+                    .map_err(|e| format!("{:?}: {}", definition.name, e))?;
+                Ok(Achievement { name: definition.name, conditions, won: false })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { achievements })
+    }
+
+    /// The achievements won since the last check, by name.
+    pub fn check(&mut self, chippy: &Chip8) -> Vec<String> {
+        let mut won = Vec::new();
+        for achievement in self.achievements.iter_mut().filter(|achievement| !achievement.won) {
+            if achievement.conditions.iter().all(|condition| condition.holds(chippy)) {
+                achievement.won = true;
+                won.push(achievement.name.clone());
+            }
+        }
+        won
+    }
+}
+
+impl Condition {
+    fn holds(&self, chippy: &Chip8) -> bool {
+        let byte = chippy.peek(self.address);
+        match self.comparison {
+            Comparison::Equal => byte == self.value,
+            Comparison::NotEqual => byte != self.value,
+            Comparison::Less => byte < self.value,
+            Comparison::LessOrEqual => byte <= self.value,
+            Comparison::Greater => byte > self.value,
+            Comparison::GreaterOrEqual => byte >= self.value,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (address, comparison, value) = match text.split_whitespace().collect::<Vec<_>>()[..] {
+            [address, comparison, value] => (address, comparison, value),
+            _ => return Err(format!("expected an address, a comparison and a value in {:?}", text)),
+        };
+
+        let address = number(address).filter(|&address| address < 4096)
+            .ok_or_else(|| format!("{:?} isn't an address in memory", address))?;
+        let comparison = match comparison {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => return Err(format!("{:?} isn't a comparison, expected ==, !=, <, <=, > or >=", comparison)),
+        };
+        let value = number(value).and_then(|value| u8::try_from(value).ok())
+            .ok_or_else(|| format!("{:?} isn't a byte", value))?;
+
+        Ok(Self { address, comparison, value })
+    }
+}
+
+/// Reads a number in decimal, or in hex after `0x`.
+fn number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn achievements_are_won_once_every_condition_holds() {
+        let mut achievements = Achievements::parse(r#"
+            [[achievement]]
+            name = "Ten lines"
+            conditions = ["0x3A0 >= 10", "0x3A1 == 0"]
+        "#).unwrap();
+        let mut chippy = Chip8::initialize(&[]).unwrap();
+
+        assert!(achievements.check(&chippy).is_empty());
+
+        let mut state = chippy.save_state();
+        state.memory[0x3A0] = 10;
+        chippy.load_state(&state);
+        assert_eq!(achievements.check(&chippy), vec!["Ten lines".to_string()]);
+        assert!(achievements.check(&chippy).is_empty());
+    }
+
+    #[test]
+    fn conditions_are_checked_as_they_are_read() {
+        assert_eq!("0x3A0 >= 10".parse(), Ok(Condition { address: 0x3A0, comparison: Comparison::GreaterOrEqual, value: 10 }));
+        assert!("0x3A0 >= 256".parse::<Condition>().is_err());
+        assert!("0x1000 == 1".parse::<Condition>().is_err());
+        assert!("0x3A0 => 1".parse::<Condition>().is_err());
+        assert!("0x3A0>=1".parse::<Condition>().is_err());
+    }
+}
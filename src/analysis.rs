@@ -0,0 +1,191 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::chip8::{ MAX_ROM_SIZE, ROM_START_ADDRESS };
+use crate::opcode::{ self, Op, OpcodeDoc, REFERENCE };
+use crate::platform::Platform;
+use crate::quirks::MemoryRange;
+
+const MEMORY_SIZE: usize = 4096;
+
+/// What can be told about a ROM without running it, by following every
+/// path from the entry point through the instructions.
+///
+/// Jumps through V0 (BNNN) go somewhere only known while the program
+/// runs, so code only reached through them looks unreachable here; a
+/// run of the ROM (see `Chip8::is_executed`) fills such gaps in.
+#[derive(Debug, PartialEq)]
+pub struct Analysis {
+    /// The memory the ROM was loaded into.
+    pub rom: MemoryRange,
+    /// Every byte that's part of an instruction reachable from the entry
+    /// point, by address.
+    pub reachable: Vec<bool>,
+    /// How many reachable instructions of each kind there are, most first.
+    pub histogram: Vec<(Op, usize)>,
+    /// The newest platform a reachable instruction is from.
+    pub platform: Platform,
+    /// Where the reachable jumps through V0 are, which couldn't be followed.
+    pub computed_jumps: Vec<usize>,
+    /// Reachable opcodes that aren't instructions, and where they are.
+    pub invalid: Vec<(usize, u16)>,
+    /// Paths that leave the ROM, from where to where, e.g. into memory
+    /// the program writes code to.
+    pub outside: Vec<(usize, usize)>,
+    /// Unreachable parts of the ROM that look like data: I points into
+    /// them, or most of them doesn't decode.
+    pub data: Vec<MemoryRange>,
+    /// Unreachable parts of the ROM that decode as instructions.
+    pub unreachable: Vec<MemoryRange>,
+}
+
+/// Follows the program in `rom` from the entry point, as it would be
+/// loaded. Instructions can be at odd addresses, and both bytes of each
+/// count as reachable.
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let start = ROM_START_ADDRESS as usize;
+    let end = start + rom.len().min(MAX_ROM_SIZE);
+    let in_rom = |address: usize| (start..end).contains(&address);
+    // past the end of the ROM, memory is zero.
+    let byte = |address: usize| if in_rom(address) { rom[address - start] } else { 0 };
+
+    let mut reachable = vec![false; MEMORY_SIZE];
+    let mut visited = vec![false; MEMORY_SIZE];
+    let mut counts = vec![0; REFERENCE.len()];
+    let mut platform = Platform::Chip8;
+    let mut computed_jumps = Vec::new();
+    let mut invalid = Vec::new();
+    let mut outside = Vec::new();
+    // where LD I points, which is usually a sprite or a table.
+    let mut pointers = Vec::new();
+
+    let mut pending = if in_rom(start) { vec![start] } else { Vec::new() };
+    while let Some(address) = pending.pop() {
+        if visited[address] {
+            continue;
+        }
+        visited[address] = true;
+        reachable[address] = true;
+        if address + 1 < MEMORY_SIZE {
+            reachable[address + 1] = true;
+        }
+
+        let opcode = (byte(address) as u16) << 8 | byte(address + 1) as u16;
+        let op = match opcode::decode(opcode) {
+            Some(op) => op,
+            None => {
+                invalid.push((address, opcode));
+                continue;
+            },
+        };
+        if let Some(i) = REFERENCE.iter().position(|doc| doc.op == op) {
+            counts[i] += 1;
+        }
+        platform = platform.max(OpcodeDoc::of(op).platform);
+
+        let (next, target) = (address + 2, (opcode & 0x0FFF) as usize);
+        let successors = match op {
+            Op::Ret => vec![],
+            Op::Jp => vec![target],
+            Op::Call => vec![target, next],
+            Op::SeVxByte | Op::SneVxByte | Op::SeVxVy | Op::SneVxVy | Op::Skp | Op::Sknp => vec![next, next + 2],
+            Op::JpV0 => {
+                computed_jumps.push(address);
+                vec![]
+            },
+            Op::LdI => {
+                pointers.push(target);
+                vec![next]
+            },
+            _ => vec![next],
+        };
+        for successor in successors {
+            if in_rom(successor) {
+                pending.push(successor);
+            } else {
+                outside.push((address, successor));
+            }
+        }
+    }
+
+    let mut histogram: Vec<(Op, usize)> = REFERENCE.iter().zip(counts)
+        .filter(|&(_, count)| count > 0)
+        .map(|(doc, count)| (doc.op, count))
+        .collect();
+    histogram.sort_by_key(|&(_, count)| core::cmp::Reverse(count));
+    computed_jumps.sort_unstable();
+    invalid.sort_unstable();
+    outside.sort_unstable();
+
+    // the runs of the ROM nothing reaches.
+    let (mut data, mut unreachable) = (Vec::new(), Vec::new());
+    let mut address = start;
+    while address < end {
+        if reachable[address] {
+            address += 1;
+            continue;
+        }
+        let run_start = address;
+        while address < end && !reachable[address] {
+            address += 1;
+        }
+        let run = MemoryRange { start: run_start as u16, end: (address - 1) as u16 };
+
+        let pointed_to = pointers.iter().any(|&pointer| (run_start..address).contains(&pointer));
+        let words = (run_start..address).step_by(2)
+            .map(|at| (byte(at) as u16) << 8 | byte(at + 1) as u16);
+        let decoding = words.clone().filter(|&word| opcode::decode(word).is_some()).count();
+        if pointed_to || decoding * 2 < words.count() {
+            data.push(run);
+        } else {
+            unreachable.push(run);
+        }
+    }
+
+    Analysis {
+        rom: MemoryRange { start: start as u16, end: end.saturating_sub(1).max(start) as u16 },
+        reachable,
+        histogram,
+        platform,
+        computed_jumps,
+        invalid,
+        outside,
+        data,
+        unreachable,
+    }
+}
+
+impl Analysis {
+    /// How many bytes of the ROM are reachable.
+    pub fn reachable_bytes(&self) -> usize {
+        (self.rom.start as usize..=self.rom.end as usize).filter(|&address| self.reachable[address]).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_followed_from_the_entry_point() {
+        let rom = [
+            0x22, 0x08, // 200: CALL 208
+            0xA2, 0x0C, // 202: LD I, 20C
+            0x12, 0x02, // 204: JP 202
+            0x00, 0xE0, // 206: CLS, never reached
+            0x30, 0x00, // 208: SE V0, 0
+            0xF0, 0x01, // 20A: PLANE 0
+            0x00, 0xEE, // 20C: RET, and a sprite I points at
+            0xFF, 0xFF, // 20E: data
+        ];
+        let analysis = analyze(&rom);
+
+        assert_eq!(analysis.reachable_bytes(), 12);
+        assert!(!analysis.reachable[0x206] && analysis.reachable[0x20D]);
+        assert_eq!(analysis.platform, Platform::XoChip);
+        assert_eq!(analysis.histogram.len(), 6);
+        assert_eq!(analysis.unreachable, vec![MemoryRange { start: 0x206, end: 0x207 }]);
+        assert_eq!(analysis.data, vec![MemoryRange { start: 0x20E, end: 0x20F }]);
+        assert!(analysis.invalid.is_empty() && analysis.outside.is_empty());
+    }
+}
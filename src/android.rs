@@ -0,0 +1,14 @@
+use std::ffi::CString;
+use std::io::{ self, Read };
+
+/// Reads a file bundled in the app's assets, which is where ROMs live
+/// on Android.
+pub fn read_asset(name: &str) -> io::Result<Vec<u8>> {
+    let name = CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut asset = ndk_glue::native_activity().asset_manager().open(&name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such asset"))?;
+
+    let mut bytes = Vec::new();
+    asset.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
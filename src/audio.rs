@@ -0,0 +1,438 @@
+use std::fmt;
+
+use chippy::chip8::Chip8;
+
+/// How much the volume keys change the volume by.
+const VOLUME_STEP: f32 = 0.1;
+
+/// The pitch of the buzzer.
+const BUZZER_FREQUENCY: f32 = 440.0;
+
+/// How many bits of an XO-CHIP audio pattern play a second at pitch 64.
+const PATTERN_RATE: f32 = 4000.0;
+
+/// What the buzzer should be playing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sound {
+    Silent,
+    /// The plain square wave.
+    Buzz,
+    /// An XO-CHIP audio pattern, a bit a sample, looped at a pitch.
+    Pattern { bits: [u8; 16], pitch: u8 },
+}
+
+impl Sound {
+    /// What the machine sounds like right now.
+    pub fn of(chippy: &Chip8) -> Self {
+        match chippy.audio_pattern() {
+            _ if !chippy.is_beeping() => Sound::Silent,
+            Some(&bits) => Sound::Pattern { bits, pitch: chippy.pitch() },
+            None => Sound::Buzz,
+        }
+    }
+}
+
+/// How many bits of an audio pattern play a second at `pitch`:
+/// 4000 at 64, doubling every 48 up.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    PATTERN_RATE * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+/// The volume of the sound output, from 0 to 1, which can be muted
+/// without losing the level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Volume {
+    level: f32,
+    muted: bool,
+}
+
+impl Volume {
+    pub fn new(level: f32) -> Self {
+        Self { level: level.clamp(0.0, 1.0), muted: false }
+    }
+
+    pub fn up(&mut self) {
+        *self = Self::new(self.level + VOLUME_STEP);
+    }
+
+    pub fn down(&mut self) {
+        *self = Self::new(self.level - VOLUME_STEP);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    /// What to scale the output by.
+    #[cfg_attr(not(feature = "audio"), allow(unused))]
+    pub fn gain(&self) -> f32 {
+        if self.muted { 0.0 } else { self.level }
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.muted {
+            write!(f, "volume muted")
+        } else {
+            write!(f, "volume {}%", (self.level * 100.0).round())
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+pub use output::Buzzer;
+
+/// Without the `audio` feature there's no sound, just a buzzer that
+/// never makes any.
+#[cfg(not(feature = "audio"))]
+pub struct Buzzer;
+
+#[cfg(not(feature = "audio"))]
+impl Buzzer {
+    pub fn start(_latency: std::time::Duration) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self)
+    }
+
+    pub fn update(&mut self, _sound: super::Sound) {}
+
+    pub fn set_volume(&mut self, _volume: Volume) {}
+}
+
+/// The pieces of the sound pipeline: the emulation loop renders the
+/// buzzer, or the XO-CHIP audio pattern, into a ring of samples as the sound timer changes, and the
+/// audio thread plays them back.
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+mod pipeline {
+    use std::sync::atomic::{ AtomicU32, AtomicUsize, Ordering };
+
+    use super::{ BUZZER_FREQUENCY, Sound, pattern_rate };
+
+    /// Square waves are loud, even a full volume is toned down.
+    const AMPLITUDE: f32 = 0.25;
+
+    /// How long the buzzer takes to fade in and out, in seconds.
+    /// Switching a square wave on and off instantly clicks.
+    const FADE_TIME: f32 = 0.002;
+
+    /// A fixed-size ring of samples with one thread pushing and another
+    /// popping, without locks, so the audio thread never waits on the
+    /// emulation.
+    pub struct SampleRing {
+        // f32s, as bits.
+        samples: Box<[AtomicU32]>,
+        // both only ever go up, and wrap around.
+        read: AtomicUsize,
+        write: AtomicUsize,
+    }
+
+    impl SampleRing {
+        /// A ring holding at least `capacity` samples.
+        pub fn new(capacity: usize) -> Self {
+            let capacity = capacity.next_power_of_two();
+            Self {
+                samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+                read: AtomicUsize::new(0),
+                write: AtomicUsize::new(0),
+            }
+        }
+
+        pub fn len(&self) -> usize {
+            self.write.load(Ordering::Acquire).wrapping_sub(self.read.load(Ordering::Acquire))
+        }
+
+        /// Adds a sample, unless the ring is full. Only one thread may push.
+        pub fn push(&self, sample: f32) -> bool {
+            let write = self.write.load(Ordering::Relaxed);
+            if write.wrapping_sub(self.read.load(Ordering::Acquire)) == self.samples.len() {
+                return false;
+            }
+
+            self.samples[write & (self.samples.len() - 1)].store(sample.to_bits(), Ordering::Relaxed);
+            self.write.store(write.wrapping_add(1), Ordering::Release);
+            true
+        }
+
+        /// Takes the oldest sample, if any. Only one thread may pop.
+        pub fn pop(&self) -> Option<f32> {
+            let read = self.read.load(Ordering::Relaxed);
+            if read == self.write.load(Ordering::Acquire) {
+                return None;
+            }
+
+            let sample = f32::from_bits(self.samples[read & (self.samples.len() - 1)].load(Ordering::Relaxed));
+            self.read.store(read.wrapping_add(1), Ordering::Release);
+            Some(sample)
+        }
+    }
+
+    /// Renders the buzzer a sample at a time, fading it in and out.
+    pub struct Tone {
+        sample_rate: f32,
+        phase: f32,
+        step: f32,
+        fade_step: f32,
+        envelope: f32,
+        // what's playing, kept while it fades out.
+        playing: Sound,
+        // how far into the audio pattern it is, in bits.
+        position: f32,
+    }
+
+    impl Tone {
+        pub fn new(sample_rate: u32) -> Self {
+            Self {
+                sample_rate: sample_rate as f32,
+                phase: 0.0,
+                step: BUZZER_FREQUENCY / sample_rate as f32,
+                fade_step: 1.0 / (FADE_TIME * sample_rate as f32),
+                envelope: 0.0,
+                playing: Sound::Buzz,
+                position: 0.0,
+            }
+        }
+
+        pub fn next(&mut self, sound: Sound, gain: f32) -> f32 {
+            let target = if sound == Sound::Silent { 0.0 } else { 1.0 };
+            if self.envelope < target {
+                self.envelope = (self.envelope + self.fade_step).min(target);
+            } else {
+                self.envelope = (self.envelope - self.fade_step).max(target);
+            }
+            if sound != Sound::Silent {
+                self.playing = sound;
+            }
+
+            let level = match self.playing {
+                Sound::Pattern { bits, pitch } => {
+                    let bit = self.position as usize;
+                    self.position = (self.position + pattern_rate(pitch) / self.sample_rate) % 128.0;
+                    if bits[bit / 8] & 0x80 >> (bit % 8) != 0 { 1.0 } else { -1.0 }
+                },
+                _ => {
+                    let square = if self.phase < 0.5 { 1.0 } else { -1.0 };
+                    self.phase = (self.phase + self.step).fract();
+                    square
+                },
+            };
+
+            level * self.envelope * AMPLITUDE * gain
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+mod output {
+    use std::error::Error;
+    use std::sync::Arc;
+    use std::sync::atomic::{ AtomicBool, Ordering };
+    use std::time::{ Duration, Instant };
+
+    use cpal::{ FromSample, SampleFormat, SizedSample, Stream, StreamConfig };
+    use cpal::traits::{ DeviceTrait, HostTrait, StreamTrait };
+
+    use super::{ Sound, Volume };
+    use super::pipeline::{ SampleRing, Tone };
+
+    /// How quickly the audio thread fades out when it runs out of samples.
+    const UNDERRUN_DECAY: f32 = 0.99;
+
+    /// The buzzer, playing on the default output device. The emulation
+    /// loop calls `update` as it goes to render the samples that have
+    /// come due, keeping about `latency` worth queued up for the audio
+    /// thread: enough to ride out a slow frame, little enough that the
+    /// buzzer follows the sound timer closely.
+    pub struct Buzzer {
+        ring: Arc<SampleRing>,
+        underrun: Arc<AtomicBool>,
+        tone: Tone,
+        gain: f32,
+        sample_rate: u32,
+        // how many samples to keep queued.
+        queued: usize,
+        started: Instant,
+        rendered: u64,
+        _stream: Stream,
+    }
+
+    impl Buzzer {
+        pub fn start(latency: Duration) -> Result<Self, Box<dyn Error>> {
+            let device = cpal::default_host().default_output_device()
+                .ok_or("there's no audio output device")?;
+            let supported = device.default_output_config()?;
+            let config = supported.config();
+
+            let sample_rate = config.sample_rate.0;
+            let queued = ((latency.as_secs_f32() * sample_rate as f32) as usize).max(1);
+            let ring = Arc::new(SampleRing::new(queued * 2));
+            let underrun = Arc::new(AtomicBool::new(true));
+
+            let stream = match supported.sample_format() {
+                SampleFormat::F32 => build_stream::<f32>(&device, &config, &ring, &underrun)?,
+                SampleFormat::I16 => build_stream::<i16>(&device, &config, &ring, &underrun)?,
+                SampleFormat::U16 => build_stream::<u16>(&device, &config, &ring, &underrun)?,
+                format => return Err(format!("unsupported sample format {}", format).into()),
+            };
+            stream.play()?;
+
+            Ok(Self {
+                ring,
+                underrun,
+                tone: Tone::new(sample_rate),
+                gain: 0.0,
+                sample_rate,
+                queued,
+                started: Instant::now(),
+                rendered: 0,
+                _stream: stream,
+            })
+        }
+
+        /// Renders the samples that have come due since the last call.
+        pub fn update(&mut self, sound: Sound) {
+            let due = (self.started.elapsed().as_secs_f64() * self.sample_rate as f64) as u64;
+            let mut samples = due.saturating_sub(self.rendered) as usize;
+            self.rendered = due;
+
+            // the audio thread ran dry (or was just started), queue up
+            // a fresh latency's worth so it has some slack again.
+            if self.underrun.swap(false, Ordering::AcqRel) {
+                samples += self.queued.saturating_sub(self.ring.len());
+            }
+
+            for _ in 0..samples {
+                // the ring only fills up if the audio thread has stalled,
+                // better to drop samples than to let the delay grow.
+                if !self.ring.push(self.tone.next(sound, self.gain)) {
+                    break;
+                }
+            }
+        }
+
+        pub fn set_volume(&mut self, volume: Volume) {
+            self.gain = volume.gain();
+        }
+    }
+
+    fn build_stream<T>(device: &cpal::Device, config: &StreamConfig, ring: &Arc<SampleRing>, underrun: &Arc<AtomicBool>)
+        -> Result<Stream, cpal::BuildStreamError>
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let channels = config.channels as usize;
+        let (ring, underrun) = (Arc::clone(ring), Arc::clone(underrun));
+        let mut last = 0.0;
+
+        device.build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                for frame in data.chunks_mut(channels) {
+                    last = match ring.pop() {
+                        Some(sample) => sample,
+                        None => {
+                            underrun.store(true, Ordering::Release);
+                            last * UNDERRUN_DECAY
+                        },
+                    };
+                    frame.fill(T::from_sample(last));
+                }
+            },
+            |e| eprintln!("chippy: audio output failed: {}", e),
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_stays_in_range_and_mutes() {
+        let mut volume = Volume::new(0.95);
+        volume.up();
+        assert_eq!(volume.gain(), 1.0);
+
+        volume.toggle_mute();
+        assert_eq!(volume.gain(), 0.0);
+        assert_eq!(volume.to_string(), "volume muted");
+
+        volume.toggle_mute();
+        for _ in 0..20 {
+            volume.down();
+        }
+        assert_eq!(volume.to_string(), "volume 0%");
+    }
+
+    #[test]
+    fn the_ring_hands_samples_over_in_order() {
+        let ring = pipeline::SampleRing::new(3);
+
+        for sample in 0..4 {
+            assert!(ring.push(sample as f32));
+        }
+        assert!(!ring.push(4.0));
+        assert_eq!(ring.pop(), Some(0.0));
+        assert!(ring.push(4.0));
+
+        let samples: Vec<f32> = std::iter::from_fn(|| ring.pop()).collect();
+        assert_eq!(samples, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(ring.len(), 0);
+    }
+
+    #[test]
+    fn the_tone_fades_in_and_out() {
+        let mut tone = pipeline::Tone::new(1000);
+
+        let first = tone.next(Sound::Buzz, 1.0).abs();
+        let faded_in = (0..10).map(|_| tone.next(Sound::Buzz, 1.0).abs()).last().unwrap();
+        assert!(first > 0.0 && first < faded_in);
+
+        let fading = tone.next(Sound::Silent, 1.0).abs();
+        assert!(fading > 0.0 && fading < faded_in);
+        assert_eq!((0..10).map(|_| tone.next(Sound::Silent, 1.0)).last(), Some(0.0));
+    }
+
+    #[test]
+    fn patterns_play_a_bit_at_a_time_at_their_pitch() {
+        let mut bits = [0; 16];
+        bits[0] = 0xFF;
+        let signs = |pitch| {
+            let mut tone = pipeline::Tone::new(8000);
+            (0..20).map(|_| tone.next(Sound::Pattern { bits, pitch }, 1.0) > 0.0).collect::<Vec<bool>>()
+        };
+
+        // half a bit a sample at 4000 a second, then a whole one an octave up.
+        assert_eq!(signs(64).iter().position(|&high| !high), Some(16));
+        assert_eq!(signs(112).iter().position(|&high| !high), Some(8));
+        assert_eq!(pattern_rate(16), 2000.0);
+    }
+
+    #[test]
+    fn the_volume_applies_to_patterns_too() {
+        let sound = Sound::Pattern { bits: [0xFF; 16], pitch: 64 };
+        let play = |gain| {
+            let mut tone = pipeline::Tone::new(1000);
+            (0..10).map(|_| tone.next(sound, gain)).last().unwrap()
+        };
+
+        assert_eq!(play(0.5), play(1.0) / 2.0);
+        assert_eq!(play(0.0), 0.0);
+    }
+
+    #[test]
+    fn the_machine_says_what_to_play() {
+        let mut chippy = Chip8::new();
+        assert_eq!(Sound::of(&chippy), Sound::Silent);
+
+        // LD V0 0x10, LD ST V0, AUDIO
+        chippy.load_rom(&[0x60, 0x10, 0xF0, 0x18, 0xF0, 0x02]).unwrap();
+        for _ in 0..2 {
+            chippy.cycle().unwrap();
+        }
+        assert_eq!(Sound::of(&chippy), Sound::Buzz);
+
+        chippy.cycle().unwrap();
+        assert!(matches!(Sound::of(&chippy), Sound::Pattern { pitch: 64, .. }));
+    }
+}
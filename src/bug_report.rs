@@ -0,0 +1,71 @@
+use std::fmt::Write;
+use std::fs;
+use std::time::SystemTime;
+
+use chippy::disasm;
+
+use crate::command::Session;
+use crate::config::CONFIG_PATH;
+use crate::dump;
+use crate::rom_info::RomInfo;
+use crate::screenshot;
+use crate::zip::ZipWriter;
+
+/// How much the screenshot in a report is blown up.
+const SCREENSHOT_SCALE: u32 = 8;
+
+/// The name of a bug report made at `time`, e.g.
+/// `chippy-report-20261014-153012.zip`.
+pub fn file_name(time: SystemTime) -> String {
+    format!("chippy-report-{}.zip", dump::timestamp(time))
+}
+
+/// Everything about a session worth attaching to a bug report, as a zip:
+/// - `report.txt`: why, the Chippy version, the ROM's hashes and title,
+///   how far it got, and the quirks and policies it ran with
+/// - `chippy.toml`: the config, if there is one
+/// - `trace.txt`: the instructions that ran last, disassembled
+/// - `dump.txt`: a dump of the machine (see `dump::dump`)
+/// - `state.json`: the machine state, for `chippy state import`
+/// - `screenshot.png`: the display
+///
+/// The ROM itself is left out, the hashes say which one it was.
+pub fn bundle(session: &Session, reason: &str, time: SystemTime) -> Vec<u8> {
+    let chippy = &session.chippy;
+    let mut report = String::new();
+
+    // writing to a String can't fail.
+    let _ = writeln!(report, "Chippy {} bug report: {}", env!("CARGO_PKG_VERSION"), reason);
+    let _ = writeln!(report);
+    let _ = writeln!(report, "source: {}", session.source);
+    if let Some(rom) = &session.rom {
+        let _ = writeln!(report, "sha1: {}", rom.sha1);
+        let _ = writeln!(report, "crc32: {:08x}", rom.crc32);
+        if let Some(info) = RomInfo::lookup(rom) {
+            let _ = writeln!(report, "title: {}", info);
+        }
+    }
+    let _ = writeln!(report, "frame: {}", session.frame);
+    let _ = writeln!(report, "instructions: {}", session.instructions);
+    let _ = writeln!(report, "instructions a frame: {}", session.instructions_per_frame);
+    let _ = writeln!(report, "status: {}", session.debugger.status().unwrap_or_else(|| "running".to_string()));
+    let _ = writeln!(report, "quirks: {:?}", chippy.quirks);
+    let _ = writeln!(report, "memory policy: {:?}", chippy.memory_policy);
+    let _ = writeln!(report, "opcode policy: {:?}", chippy.opcode_policy);
+
+    let mut trace = String::new();
+    for &(address, opcode) in session.debugger.trace() {
+        let _ = writeln!(trace, "{:#05x}  {:04X}  {}", address, opcode, disasm::disassemble(opcode));
+    }
+
+    let mut zip = ZipWriter::new(time);
+    zip.add("report.txt", report.as_bytes());
+    if let Ok(config) = fs::read(CONFIG_PATH) {
+        zip.add("chippy.toml", &config);
+    }
+    zip.add("trace.txt", trace.as_bytes());
+    zip.add("dump.txt", dump::dump(chippy, reason).as_bytes());
+    zip.add("state.json", chippy.save_state().to_json().as_bytes());
+    zip.add("screenshot.png", &screenshot::encode_png(&chippy.display_memory, &session.palette, SCREENSHOT_SCALE));
+    zip.finish()
+}
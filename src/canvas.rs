@@ -0,0 +1,76 @@
+use crate::font::{ self, GLYPH_HEIGHT, GLYPH_WIDTH };
+
+/// An RGBA frame to draw the display and the debugger panels into.
+/// Everything drawn is clipped to the frame.
+///
+/// Every panel and overlay, the speed bar and the opcode reference too,
+/// is drawn this way rather than with a GUI library like egui. egui's
+/// wgpu and winit backends would have to match the wgpu 0.11 and winit
+/// 0.24 that pixels 0.8 is built on, and it would need its own render
+/// pass and its own share of the input, next to `WinitInputHelper`. A
+/// slider and a searchable list of text don't need all that.
+pub struct Canvas<'a> {
+    frame: &'a mut [u8],
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(frame: &'a mut [u8], width: u32, height: u32) -> Self {
+        Self { frame, width, height }
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, rgba: [u8; 4]) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+
+        let offset = ((y as u32 * self.width + x as u32) * 4) as usize;
+        self.frame[offset..offset + 4].copy_from_slice(&rgba);
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, rgba: [u8; 4]) {
+        for row in y..y + height as i32 {
+            for col in x..x + width as i32 {
+                self.set_pixel(col, row, rgba);
+            }
+        }
+    }
+
+    /// Draws a horizontal or vertical line between two points.
+    pub fn draw_line(&mut self, (x0, y0): (i32, i32), (x1, y1): (i32, i32), rgba: [u8; 4]) {
+        let (left, right) = (x0.min(x1), x0.max(x1));
+        let (top, bottom) = (y0.min(y1), y0.max(y1));
+
+        self.fill_rect(left, top, (right - left + 1) as u32, (bottom - top + 1) as u32, rgba);
+    }
+
+    /// Draws text with its top-left corner at (x, y), with every pixel
+    /// of the font blown up to `scale` by `scale` pixels.
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, scale: u32, rgba: [u8; 4]) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph_x = x + (i as u32 * text_advance(scale)) as i32;
+
+            for (row, bits) in font::glyph(c).iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> col) != 0 {
+                        self.fill_rect(
+                            glyph_x + (col * scale) as i32,
+                            y + (row as u32 * scale) as i32,
+                            scale, scale, rgba);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How far apart characters are drawn at a scale.
+pub const fn text_advance(scale: u32) -> u32 {
+    (GLYPH_WIDTH + 1) * scale
+}
+
+/// How far apart lines of text are drawn at a scale.
+pub const fn line_height(scale: u32) -> u32 {
+    (GLYPH_HEIGHT + 2) * scale
+}
@@ -0,0 +1,118 @@
+use std::fmt::Write;
+
+use chippy::analysis::Analysis;
+use chippy::chip8::Chip8;
+use chippy::disasm;
+use chippy::opcode::OpcodeDoc;
+use chippy::quirks::MemoryRange;
+
+const MEMORY_SIZE: usize = 4096;
+
+/// Runs the machine for a number of frames, with no keys pressed, and
+/// returns which bytes of memory were executed. Stops early at a trap.
+pub fn run(chippy: &mut Chip8, frames: u64, instructions_per_frame: u32) -> Vec<bool> {
+    'frames: for _ in 0..frames {
+        for _ in 0..instructions_per_frame {
+            if chippy.cycle().is_err() {
+                break 'frames;
+            }
+        }
+        chippy.tick_timers();
+    }
+
+    (0..MEMORY_SIZE).map(|address| chippy.is_executed(address)).collect()
+}
+
+/// The report `chippy check` prints, with what a run of `frames` frames
+/// executed if there was one.
+pub fn report(analysis: &Analysis, rom: &[u8], run: Option<(u64, &[bool])>) -> String {
+    let mut report = String::new();
+    let (start, end) = (analysis.rom.start as usize, analysis.rom.end as usize);
+    let size = rom.len();
+
+    let _ = writeln!(report, "ROM: {:#05x}-{:#05x}, {} bytes", start, end, size);
+    let entry = match rom.get(..2) {
+        Some(bytes) => disasm::disassemble((bytes[0] as u16) << 8 | bytes[1] as u16),
+        None => "past the end of the ROM".to_string(),
+    };
+    let _ = writeln!(report, "entry point: {:#05x}, {}", start, entry);
+    let reachable = analysis.reachable_bytes();
+    let _ = writeln!(report, "reachable: {} of {} bytes ({}%)", reachable, size, reachable * 100 / size.max(1));
+    let _ = writeln!(report, "platform: {} (the newest a reachable instruction is from)", analysis.platform);
+
+    let _ = writeln!(report, "instructions:");
+    for &(op, count) in &analysis.histogram {
+        let doc = OpcodeDoc::of(op);
+        let _ = writeln!(report, "  {:>5}  {:<6} {}", count, doc.pattern, doc.mnemonic);
+    }
+
+    if !analysis.computed_jumps.is_empty() {
+        let at: Vec<String> = analysis.computed_jumps.iter().map(|address| format!("{:#05x}", address)).collect();
+        let _ = writeln!(report, "jumps through V0, not followed: {}", at.join(", "));
+    }
+    for &(address, opcode) in &analysis.invalid {
+        let _ = writeln!(report, "invalid opcode: {:04X} at {:#05x}", opcode, address);
+    }
+    for &(from, to) in &analysis.outside {
+        let _ = writeln!(report, "leaves the ROM: {:#05x} to {:#05x}", from, to);
+    }
+    for range in &analysis.data {
+        let _ = writeln!(report, "data: {}", describe(range));
+    }
+    for range in &analysis.unreachable {
+        let _ = writeln!(report, "unreachable code: {}", describe(range));
+    }
+
+    if let Some((frames, executed)) = run {
+        let ran = (start..=end).filter(|&address| executed[address]).count();
+        let _ = writeln!(report, "ran {} frames: {} of {} bytes executed", frames, ran, size);
+        // code only found by running it, usually through a jump through V0.
+        for range in ranges((start..=end).filter(|&address| executed[address] && !analysis.reachable[address])) {
+            let _ = writeln!(report, "executed, but not reachable by following the code: {}", describe(&range));
+        }
+    }
+
+    report
+}
+
+/// A range and its size, e.g. `0x3a0-0x3bf (32 bytes)`.
+fn describe(range: &MemoryRange) -> String {
+    let size = (range.end - range.start) as usize + 1;
+    format!("{:#05x}-{:#05x} ({} byte{})", range.start, range.end, size, if size == 1 { "" } else { "s" })
+}
+
+/// Runs of consecutive addresses.
+fn ranges(addresses: impl Iterator<Item = usize>) -> Vec<MemoryRange> {
+    let mut ranges: Vec<MemoryRange> = Vec::new();
+    for address in addresses {
+        match ranges.last_mut() {
+            Some(range) if range.end as usize + 1 == address => range.end = address as u16,
+            _ => ranges.push(MemoryRange { start: address as u16, end: address as u16 }),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use chippy::chip8::INSTRUCTIONS_PER_FRAME;
+
+    use super::*;
+
+    #[test]
+    fn the_test_rom_is_followed_and_run() {
+        let rom = std::fs::read("test_roms/test_opcode.ch8").unwrap();
+        let analysis = chippy::analysis::analyze(&rom);
+        let mut chippy = Chip8::initialize(&rom).unwrap();
+        let executed = run(&mut chippy, 60, INSTRUCTIONS_PER_FRAME);
+
+        let report = report(&analysis, &rom, Some((60, &executed)));
+        assert!(report.contains("platform: chip8"), "{}", report);
+        assert!(report.contains("ran 60 frames"), "{}", report);
+        assert!(!report.contains("not reachable by following"), "{}", report);
+        assert_eq!(ranges([1, 2, 3, 7].iter().copied()), vec![
+            MemoryRange { start: 1, end: 3 },
+            MemoryRange { start: 7, end: 7 },
+        ]);
+    }
+}
@@ -0,0 +1,23 @@
+use chippy::state::MachineState;
+
+use crate::canvas::{ self, Canvas };
+
+const TEXT_SCALE: u32 = 2;
+const PADDING: u32 = 6;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+
+/// Draws the frame and the checksum of the machine's state (see
+/// `MachineState::checksum`) in the top right corner of a display whose
+/// right edge is at `right`, so machines meant to run in lockstep can be
+/// told apart at a glance.
+pub fn draw(canvas: &mut Canvas, right: i32, frame: u64, state: &MachineState) {
+    let text = format!("{} {:08X}", frame, state.checksum());
+    let width = text.len() as u32 * canvas::text_advance(TEXT_SCALE) + 2 * PADDING;
+    let height = canvas::line_height(TEXT_SCALE) + 2 * PADDING;
+
+    let x = right - width as i32;
+    canvas.fill_rect(x, 0, width, height, BACKGROUND);
+    canvas.draw_text(x + PADDING as i32, (PADDING + TEXT_SCALE) as i32, &text, TEXT_SCALE, TEXT);
+}
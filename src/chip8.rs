@@ -1,18 +1,87 @@
-use std::fs;
-use std::fs::File;
-use std::io::Write;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
 
-use rand::Rng; 
-use rand::rngs::ThreadRng;
+use rand::{ Rng, SeedableRng };
+use rand::rngs::StdRng;
+
+use serde::Deserialize;
+
+use crate::loop_detector::{ LoopDetector, LoopState };
+use crate::opcode::{ self, Op };
+use crate::quirks::{ Quirks, Random };
+use crate::state::MachineState;
+use crate::trap::Trap;
 
 pub const VIDEO_WIDTH: u32 = 64;
 pub const VIDEO_HEIGHT: u32 = 32;
 
 const FONTSET_START_ADDRESS: u8 = 0x50;
-const ROM_START_ADDRESS: u16 = 0x200;
+/// Where ROMs are loaded, and where programs start.
+pub const ROM_START_ADDRESS: u16 = 0x200;
+
+/// The largest ROM that fits in memory.
+pub const MAX_ROM_SIZE: usize = 4096 - ROM_START_ADDRESS as usize;
+
+/// How often the timers count down, and so how many frames there are a second.
+pub const TIMER_HZ: u32 = 60;
+
+/// How many instructions run between timer ticks, unless a frontend
+/// says otherwise: 600 a second.
+pub const INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+/// A ROM that doesn't fit in memory.
+#[derive(Debug)]
+pub struct RomTooLarge {
+    pub size: usize,
+}
+
+impl fmt::Display for RomTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the ROM is {} bytes, only {} fit in memory", self.size, MAX_ROM_SIZE)
+    }
+}
+
+impl Error for RomTooLarge {}
+
+/// What to do when a program writes to the memory reserved for the
+/// interpreter (below 0x200) or accesses memory past 0xFFF.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryPolicy {
+    /// Wrap addresses around the end of memory and let reserved writes through.
+    Wrap,
+    /// Drop the write, or read zero.
+    Ignore,
+    /// Raise a trap so the debugger can pause and show a diagnostic.
+    #[default]
+    Pause,
+}
+
+/// What to do when the program counter reaches an opcode the
+/// interpreter doesn't know.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OpcodePolicy {
+    /// Raise a trap so the debugger can pause and show a diagnostic.
+    #[default]
+    Halt,
+    /// Log the opcode and carry on with the next instruction.
+    Skip,
+    /// Silently treat the opcode as a no-op.
+    Nop,
+}
+
+/// A write to memory that has already been executed as code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CodeWrite {
+    pub pc: usize,
+    pub address: usize,
+}
 
 /// The CHIP-8 has 4KiB of memory (4096 bytes) and
 /// various other things to keep track of things.
+#[derive(Clone)]
 pub struct Chip8 {
     index: usize,
     program_counter: usize,
@@ -22,9 +91,45 @@ pub struct Chip8 {
     sound_timer: u8,
     registers: [u8; 16],
     memory: [u8; 4096],
+    /// Every pixel is the planes it's lit on, 1 for the first and 2 for
+    /// the second. Only XO-CHIP programs draw on the second.
     pub display_memory: [u8; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize],
+    // the planes drawing and clearing work on, selected with FN01.
+    planes: u8,
+    // the XO-CHIP audio pattern loaded with F002 and the pitch it
+    // plays at, set with FX3A.
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
     pub keypad: [bool; 16],
-    rng: ThreadRng,
+    pub quirks: Quirks,
+    pub memory_policy: MemoryPolicy,
+    pub opcode_policy: OpcodePolicy,
+    awaited_key: Option<u8>,
+    // keys that were already held when FX0A started waiting and haven't
+    // been released since, a bit each, or `None` when it isn't waiting.
+    held_before_wait: Option<u16>,
+    // sprites drawn since the timers last ticked, for
+    // `Quirks::sprites_per_frame`.
+    sprites_drawn: u32,
+    // keys the program has seen held since `take_observed_keys`, a bit each.
+    observed_keys: u16,
+    instruction_address: usize,
+    trap: Option<Trap>,
+    loop_detector: LoopDetector,
+    executed: [bool; 4096],
+    code_writes: Vec<CodeWrite>,
+    rng: StdRng,
+    // the last number rolled, or the next of the sequence, for
+    // `Random::Vip` and `Random::Sequence`.
+    random_state: u8,
+    // stepped each frame, like the VIP's display interrupt did.
+    random_clock: u8,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chip8 {
@@ -39,18 +144,35 @@ impl Chip8 {
             registers: [0; 16],
             memory: [0; 4096],
             display_memory: [0; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize],
+            planes: 1,
+            audio_pattern: None,
+            pitch: 64,
             keypad: [false; 16],
-            rng: rand::thread_rng(),
+            quirks: Quirks::default(),
+            memory_policy: MemoryPolicy::default(),
+            opcode_policy: OpcodePolicy::default(),
+            awaited_key: None,
+            held_before_wait: None,
+            sprites_drawn: 0,
+            observed_keys: 0,
+            instruction_address: ROM_START_ADDRESS as usize,
+            trap: None,
+            loop_detector: LoopDetector::new(),
+            executed: [false; 4096],
+            code_writes: Vec::new(),
+            rng: new_rng(),
+            random_state: 0,
+            random_clock: 0,
         }
     }
 
-    pub fn initialize(rom_path: &str) -> Self {
+    pub fn initialize(rom: &[u8]) -> Result<Self, RomTooLarge> {
         let mut chippy = Self::new();
 
         chippy.load_fontset();
-        chippy.load_rom(rom_path);
+        chippy.load_rom(rom)?;
 
-        chippy
+        Ok(chippy)
     }
 
     pub fn load_fontset(&mut self) { 
@@ -73,15 +195,28 @@ impl Chip8 {
             0xF0, 0x80, 0xF0, 0x80, 0x80, // F
         ];
 
-        for i in 0..fontset.len() {
-            self.memory[FONTSET_START_ADDRESS as usize + i] = fontset[i];
-        }
+        let start = FONTSET_START_ADDRESS as usize;
+        self.memory[start..start + fontset.len()].copy_from_slice(&fontset);
     }
 
-    pub fn cycle(&mut self) {
+    /// Runs a single instruction. Returns the trap raised by the
+    /// instruction, if any, so the caller can pause.
+    pub fn cycle(&mut self) -> Result<(), Trap> {
+        self.instruction_address = self.program_counter;
+
         // fetch the opcode (by merging two consecutive bytes of memory)
-        let opcode = ((self.memory[self.program_counter] as u16) << 8) 
-            | self.memory[self.program_counter + 1] as u16;
+        let opcode = ((self.read(self.program_counter) as u16) << 8)
+            | self.read(self.program_counter + 1) as u16;
+
+        self.mark_executed(self.instruction_address);
+
+        // a jump to itself is the usual way of ending a program.
+        let jumps_to_self = opcode & 0xF000 == 0x1000
+            && (opcode & 0x0FFF) as usize == self.instruction_address;
+
+        if jumps_to_self || self.loop_detector.step(self.loop_state()) {
+            return Err(Trap::Halted { pc: self.instruction_address });
+        }
         
         // increment the program counter before executing anything
         self.program_counter += 2; // increment by two since two bytes of memory were consumed
@@ -89,6 +224,25 @@ impl Chip8 {
         // decode and execute the opcode
         self.decode_and_execute(opcode);
 
+        // running timers or touching the display or keypad is progress
+        // the loop detector can't see, so it has to start over.
+        if self.delay_timer > 0 || self.sound_timer > 0 || is_observable(opcode) {
+            self.loop_detector.activity();
+        }
+
+        match self.trap.take() {
+            Some(trap) => Err(trap),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts the delay and sound timers down. This should happen 60 times
+    /// a second, however many instructions run in between.
+    pub fn tick_timers(&mut self) {
+        // a frame's gone by, so sprites can be drawn again.
+        self.sprites_drawn = 0;
+        self.random_clock = self.random_clock.wrapping_add(1);
+
         // decrement the delay timer if it's been set
         if self.delay_timer > 0 { self.delay_timer -= 1; }
 
@@ -96,105 +250,309 @@ impl Chip8 {
         if self.sound_timer > 0 { self.sound_timer -= 1; }
     }
 
-    fn decode_and_execute(&mut self, opcode: u16) {
-        match opcode {
-            0x00E0 => self.opcode_00e0(), // clear the screen
-            0x00EE => self.opcode_00ee(), // return from subroutine
-            _ => {
-                match opcode & 0xF000 {
-                    0x1000 => self.opcode_1nnn(opcode),
-                    0x2000 => self.opcode_2nnn(opcode),
-                    0x3000 => self.opcode_3xkk(opcode),
-                    0x4000 => self.opcode_4xkk(opcode),
-                    0x5000 => self.opcode_5xy0(opcode),
-                    0x6000 => self.opcode_6xkk(opcode),
-                    0x7000 => self.opcode_7xkk(opcode),
-                    0x8000 => {
-                        match opcode & 0x000F {
-                            0x0000 => self.opcode_8xy0(opcode),
-                            0x0001 => self.opcode_8xy1(opcode),
-                            0x0002 => self.opcode_8xy2(opcode),
-                            0x0003 => self.opcode_8xy3(opcode),
-                            0x0004 => self.opcode_8xy4(opcode),
-                            0x0005 => self.opcode_8xy5(opcode),
-                            0x0006 => self.opcode_8xy6(opcode),
-                            0x0007 => self.opcode_8xy7(opcode),
-                            0x000E => self.opcode_8xye(opcode),
-                            _ => {},
-                        }
-                    },
-                    0x9000 => self.opcode_9xy0(opcode),
-                    0xA000 => self.opcode_annn(opcode),
-                    0xB000 => self.opcode_bnnn(opcode),
-                    0xC000 => self.opcode_cxkk(opcode),
-                    0xD000 => self.opcode_dxyn(opcode),
-                    0xE000 => {
-                        match opcode & 0x00FF {
-                            0x009E => self.opcode_ex9e(opcode),
-                            0x00A1 => self.opcode_exa1(opcode),
-                            _ => {},
-                        }
-                    },
-                    0xF000 => {
-                        match opcode & 0x00FF {
-                            0x0007 => self.opcode_fx07(opcode),
-                            0x000A => self.opcode_fx0a(opcode),
-                            0x0015 => self.opcode_fx15(opcode),
-                            0x0018 => self.opcode_fx18(opcode),
-                            0x001e => self.opcode_fx1e(opcode),
-                            0x0029 => self.opcode_fx29(opcode),
-                            0x0033 => self.opcode_fx33(opcode),
-                            0x0055 => self.opcode_fx55(opcode),
-                            0x0065 => self.opcode_fx65(opcode),
-                            _ => {},
-                        }
-                    },
-                    _ => {},
-                }
+    /// Captures everything needed to put the machine back the way it is now.
+    pub fn save_state(&self) -> MachineState {
+        let mut stack = [0; 16];
+        for (level, &address) in self.stack.iter().enumerate() {
+            stack[level] = address as u16;
+        }
+
+        MachineState {
+            program_counter: self.program_counter as u16,
+            index: self.index as u16,
+            stack_pointer: self.stack_pointer as u8,
+            stack,
+            registers: self.registers,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            awaited_key: self.awaited_key,
+            memory: self.memory.to_vec(),
+            display: self.display_memory.to_vec(),
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            quirks: Some(self.quirks),
+        }
+    }
+
+    /// Puts the machine back into a saved state.
+    pub fn load_state(&mut self, state: &MachineState) {
+        self.program_counter = state.program_counter as usize;
+        self.index = state.index as usize;
+        self.stack_pointer = state.stack_pointer as usize;
+        for (level, &address) in state.stack.iter().enumerate() {
+            self.stack[level] = address as usize;
+        }
+        self.registers = state.registers;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.awaited_key = state.awaited_key;
+        self.held_before_wait = None;
+        self.memory.copy_from_slice(&state.memory);
+        self.display_memory.copy_from_slice(&state.display);
+        self.planes = state.planes;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.sprites_drawn = 0;
+        if let Some(quirks) = state.quirks {
+            self.quirks = quirks;
+        }
+
+        // whatever the loop detector saw no longer applies.
+        self.loop_detector.activity();
+        self.trap = None;
+    }
+
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Restarts the random number generator from a seed, so machines
+    /// seeded alike roll the same numbers.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.random_state = 0;
+        self.random_clock = 0;
+    }
+
+    /// Whether the buzzer should be sounding.
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// The XO-CHIP audio pattern to play while beeping, 128 samples a
+    /// bit each, or None for the plain buzzer.
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    /// The pitch the audio pattern plays at, 64 for 4000 samples a second.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Reads memory without going through the memory policy, for the
+    /// debugger. Addresses past the end read as zero.
+    pub fn peek(&self, address: usize) -> u8 {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    /// The memory the quirks keep between runs (see `Quirks::battery_ram`),
+    /// cut off at the end of memory, if there is any.
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        let range = self.quirks.battery_ram?;
+        let end = (range.end as usize).min(self.memory.len() - 1);
+        self.memory.get(range.start as usize..=end)
+    }
+
+    /// Battery-backed memory to write into, for frontends that load and
+    /// save it in place.
+    pub fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        let range = self.quirks.battery_ram?;
+        let end = (range.end as usize).min(self.memory.len() - 1);
+        self.memory.get_mut(range.start as usize..=end)
+    }
+
+    /// Puts back battery-backed memory kept from an earlier run, as much of
+    /// it as fits.
+    pub fn restore_battery_ram(&mut self, bytes: &[u8]) {
+        let (start, length) = match (self.quirks.battery_ram, self.battery_ram()) {
+            (Some(range), Some(memory)) => (range.start as usize, memory.len().min(bytes.len())),
+            _ => return,
+        };
+        self.memory[start..start + length].copy_from_slice(&bytes[..length]);
+    }
+
+    /// Whether the byte at `address` has been fetched as part of an instruction.
+    pub fn is_executed(&self, address: usize) -> bool {
+        self.executed.get(address).copied().unwrap_or(false)
+    }
+
+    /// Takes the keys the program has seen held, with EX9E, EXA1 or FX0A,
+    /// since the last call, as a bit a key, for measuring input latency.
+    pub fn take_observed_keys(&mut self) -> u16 {
+        core::mem::take(&mut self.observed_keys)
+    }
+
+    /// Takes the writes to executed code made since the last call, so the
+    /// debugger can flag self-modifying code.
+    pub fn take_code_writes(&mut self) -> Vec<CodeWrite> {
+        core::mem::take(&mut self.code_writes)
+    }
+
+    fn mark_executed(&mut self, address: usize) {
+        for byte in address..address + 2 {
+            if let Some(executed) = self.executed.get_mut(byte) {
+                *executed = true;
+            }
+        }
+    }
+
+    fn loop_state(&self) -> LoopState {
+        LoopState {
+            program_counter: self.program_counter,
+            index: self.index,
+            stack_pointer: self.stack_pointer,
+            stack: self.stack,
+            registers: self.registers,
+        }
+    }
+
+    /// Reads a byte of memory, applying the memory policy to
+    /// addresses past the end of memory.
+    fn read(&mut self, address: usize) -> u8 {
+        if address < self.memory.len() {
+            return self.memory[address];
+        }
+
+        match self.memory_policy {
+            MemoryPolicy::Wrap => self.memory[address % self.memory.len()],
+            MemoryPolicy::Ignore => 0,
+            MemoryPolicy::Pause => {
+                self.raise(Trap::OutOfBounds { pc: self.instruction_address, address });
+                0
             },
         }
     }
 
-    pub fn load_rom(&mut self, path: &str) {
-        let rom_contents = fs::read(path).expect("failed to read ROM file");
+    /// Writes a byte of memory, applying the memory policy to writes into
+    /// the interpreter area and past the end of memory.
+    fn write(&mut self, address: usize, value: u8) {
+        let reserved = address < ROM_START_ADDRESS as usize;
+        let out_of_bounds = address >= self.memory.len();
 
-        // read the ROM into memory
-        for i in 0..rom_contents.len() {
-            self.memory[ROM_START_ADDRESS as usize + i] = rom_contents[i];
+        // the loop detector assumes memory stays the same.
+        self.loop_detector.activity();
+
+        if !reserved && !out_of_bounds {
+            if self.executed[address] {
+                self.code_writes.push(CodeWrite { pc: self.instruction_address, address });
+            }
+
+            self.memory[address] = value;
+            return;
+        }
+
+        match self.memory_policy {
+            MemoryPolicy::Wrap => self.memory[address % self.memory.len()] = value,
+            MemoryPolicy::Ignore => {},
+            MemoryPolicy::Pause => {
+                let pc = self.instruction_address;
+                self.raise(if out_of_bounds {
+                    Trap::OutOfBounds { pc, address }
+                } else {
+                    Trap::ReservedWrite { pc, address }
+                });
+            },
+        }
+    }
+
+    /// Applies the opcode policy to an opcode that didn't decode.
+    fn invalid_opcode(&mut self, opcode: u16) {
+        match self.opcode_policy {
+            OpcodePolicy::Halt => self.raise(Trap::InvalidOpcode {
+                pc: self.instruction_address,
+                opcode,
+            }),
+            // without std there's nowhere to say so.
+            #[cfg(feature = "std")]
+            OpcodePolicy::Skip => eprintln!("chippy: skipped invalid opcode {:#06x} (pc {:#05x})",
+                opcode, self.instruction_address),
+            #[cfg(not(feature = "std"))]
+            OpcodePolicy::Skip => {},
+            OpcodePolicy::Nop => {},
+        }
+    }
+
+    /// Records a trap, keeping the first one raised during an instruction.
+    fn raise(&mut self, trap: Trap) {
+        if self.trap.is_none() {
+            self.trap = Some(trap);
         }
     }
 
-    #[allow(unused)]
-    fn dump_memory(&self) {
-        println!("Memory dump:");
-        for i in 0..&self.memory.len()-1 {
-            print!("{:#x}, ", self.memory[i]);
+    fn decode_and_execute(&mut self, opcode: u16) {
+        let op = match opcode::decode(opcode) {
+            Some(op) => op,
+            None => return self.invalid_opcode(opcode),
+        };
+
+        match op {
+            Op::Cls => self.opcode_00e0(), // clear the screen
+            Op::Ret => self.opcode_00ee(), // return from subroutine
+            Op::Jp => self.opcode_1nnn(opcode),
+            Op::Call => self.opcode_2nnn(opcode),
+            Op::SeVxByte => self.opcode_3xkk(opcode),
+            Op::SneVxByte => self.opcode_4xkk(opcode),
+            Op::SeVxVy => self.opcode_5xy0(opcode),
+            Op::LdVxByte => self.opcode_6xkk(opcode),
+            Op::AddVxByte => self.opcode_7xkk(opcode),
+            Op::LdVxVy => self.opcode_8xy0(opcode),
+            Op::Or => self.opcode_8xy1(opcode),
+            Op::And => self.opcode_8xy2(opcode),
+            Op::Xor => self.opcode_8xy3(opcode),
+            Op::AddVxVy => self.opcode_8xy4(opcode),
+            Op::Sub => self.opcode_8xy5(opcode),
+            Op::Shr => self.opcode_8xy6(opcode),
+            Op::Subn => self.opcode_8xy7(opcode),
+            Op::Shl => self.opcode_8xye(opcode),
+            Op::SneVxVy => self.opcode_9xy0(opcode),
+            Op::LdI => self.opcode_annn(opcode),
+            Op::JpV0 => self.opcode_bnnn(opcode),
+            Op::Rnd => self.opcode_cxkk(opcode),
+            Op::Drw => self.opcode_dxyn(opcode),
+            Op::Skp => self.opcode_ex9e(opcode),
+            Op::Sknp => self.opcode_exa1(opcode),
+            Op::Plane => self.opcode_fn01(opcode),
+            Op::Audio => self.opcode_f002(),
+            Op::LdVxDt => self.opcode_fx07(opcode),
+            Op::LdVxK => self.opcode_fx0a(opcode),
+            Op::LdDtVx => self.opcode_fx15(opcode),
+            Op::LdStVx => self.opcode_fx18(opcode),
+            Op::AddIVx => self.opcode_fx1e(opcode),
+            Op::LdFVx => self.opcode_fx29(opcode),
+            Op::LdBVx => self.opcode_fx33(opcode),
+            Op::Pitch => self.opcode_fx3a(opcode),
+            Op::LdIVx => self.opcode_fx55(opcode),
+            Op::LdVxI => self.opcode_fx65(opcode),
         }
-        println!("{:#x}", self.memory.iter().last().unwrap());
     }
 
-    #[allow(unused)]
-    fn dump_memory_to_file(&self, path: Option<&str>) {
-        let mut memory_log_file: File;
-        if path != None {
-             memory_log_file = File::create(path.unwrap())
-                .expect("failed to create memory dump file");
-        } else { 
-             memory_log_file = File::create("memory_dump")
-                .expect("failed to create memory dump file");
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), RomTooLarge> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(RomTooLarge { size: rom.len() });
         }
-        memory_log_file.write_all(&self.memory).unwrap(); 
+
+        // read the ROM into memory
+        let start = ROM_START_ADDRESS as usize;
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        Ok(())
     }
 
     // INSTRUCTIONS //
 
-    // CLS - clear the screen
+    // CLS - clear the screen, or just the selected planes of it.
     fn opcode_00e0(&mut self) {
-        self.display_memory = [0; 64 * 32];
+        for pixel in self.display_memory.iter_mut() {
+            *pixel &= !self.planes;
+        }
     }
 
     // RET - return from subroutine
     fn opcode_00ee(&mut self) {
+        if self.stack_pointer == 0 {
+            self.raise(Trap::StackUnderflow { pc: self.instruction_address });
+            return;
+        }
+
         self.stack_pointer -= 1;
         self.program_counter = self.stack[self.stack_pointer];
     }
@@ -209,6 +567,16 @@ impl Chip8 {
     // CALL addr - 2NNN. Call subroutine at NNN.
     fn opcode_2nnn(&mut self, opcode: u16) {
         let address = opcode & 0x0FFF; // extracts the address at location NNN 
+
+        if self.stack_pointer == self.stack.len() {
+            let mut stack = [0; 16];
+            for (level, &address) in self.stack.iter().enumerate() {
+                stack[level] = address as u16;
+            }
+
+            self.raise(Trap::StackOverflow { pc: self.instruction_address, stack });
+            return;
+        }
         
         // to be able to return from this subroutine, we 
         // push the program counter to the stack.
@@ -305,65 +673,47 @@ impl Chip8 {
         let vx = (opcode & 0x0F00) >> 8;
         let vy = (opcode & 0x00F0) >> 4;
 
-        let sum = (self.registers[vx as usize] as u16 + self.registers[vy as usize] as u16) % 256;
-
-        // toggle the carry bit in register VF if the 
-        // sum would overflow 8 bits.
-        if sum > 255 { 
-            self.registers[0xF] = 1;
-        } else {
-            self.registers[0xF] = 0; 
-        }
-
         // set VX to the lowest 8 bits of the sum.
-        self.registers[vx as usize] = sum as u8 & 0xFF;
+        let (sum, carry) = self.registers[vx as usize].overflowing_add(self.registers[vy as usize]);
+        self.registers[vx as usize] = sum;
+
+        // the carry goes in VF after the sum, so it wins when VX is VF.
+        self.registers[0xF] = carry as u8;
     }
 
-    // 8XY5 - SUB VX, VY. Set VX = VX - VY, set VF = !carry.
+    // 8XY5 - SUB VX, VY. Set VX = VX - VY, set VF = !borrow.
     fn opcode_8xy5(&mut self, opcode: u16) {
         let vx = (opcode & 0x0F00) >> 8;
         let vy = (opcode & 0x00F0) >> 4;
 
-        let diff: u8;
-        if self.registers[vx as usize] > self.registers[vy as usize] {
-            self.registers[0xF] = 1;
-
-            // subtract if the sum is not negative.
-            self.registers[vx as usize] -= self.registers[vy as usize];
-        } else {
-            self.registers[0xF] = 0;
+        // the difference wraps around below zero.
+        let (diff, borrow) = self.registers[vx as usize].overflowing_sub(self.registers[vy as usize]);
+        self.registers[vx as usize] = diff;
 
-            // otherwise get the difference and wrap around.
-            diff = self.registers[vy as usize] - self.registers[vx as usize];
-            self.registers[vx as usize] = (256 - diff as u16) as u8;
-        }
+        self.registers[0xF] = !borrow as u8;
     }
 
     // 8XY6 - SHR VX. Set VX = VX SHR 1.
     fn opcode_8xy6(&mut self, opcode: u16) {
         let vx = (opcode & 0x0F00) >> 8;
-        let lsb = vx & 0x1;
-
-        // save the least significant bit in VF.
-        self.registers[0xF] = lsb as u8;
+        let lsb = self.registers[vx as usize] & 0x1;
 
         // one right-shift is equivalent to division by two.
         self.registers[vx as usize] >>= 1;
+
+        // save the least significant bit in VF.
+        self.registers[0xF] = lsb;
     }
 
-    // 8XY7 - SUBN VX, VY. Set VX = VY - VX and set VF = !carry.
+    // 8XY7 - SUBN VX, VY. Set VX = VY - VX and set VF = !borrow.
     fn opcode_8xy7(&mut self, opcode: u16) {
         let vx = (opcode & 0x0F00) >> 8;
         let vy = (opcode & 0x00F0) >> 4;
 
-        if self.registers[vy as usize] > self.registers[vx as usize] {
-            self.registers[0xF] = 1;
-        } else {
-            self.registers[0xF] = 0;
-        }
+        let (diff, borrow) = self.registers[vy as usize].overflowing_sub(self.registers[vx as usize]);
+        self.registers[vx as usize] = diff;
 
-        self.registers[vx as usize] = 
-            self.registers[vy as usize] - self.registers[vx as usize];
+        self.registers[0xF] = !borrow as u8;
     }
 
     // 8XYE - SHL VX. Set VX = VX SHL 1.
@@ -371,11 +721,11 @@ impl Chip8 {
         let vx = (opcode & 0x0F00) >> 8;
         let msb = (self.registers[vx as usize] & 0x80) >> 7;
 
-        // save the most significant bit.
-        self.registers[0xF] = msb;
-
         // one left-shift is equivalent to multiplication by two.
         self.registers[vx as usize] <<= 1;
+
+        // save the most significant bit.
+        self.registers[0xF] = msb;
     }
 
     // 9XY0 - SNE VX, VY. Skip next instruction if VX != VY.
@@ -407,7 +757,24 @@ impl Chip8 {
         let vx = (opcode & 0x0F00) >> 8;
         let byte = opcode & 0x00FF;
 
-        self.registers[vx as usize] = self.rng.gen::<u8>() & byte as u8;
+        self.registers[vx as usize] = self.random() & byte as u8;
+    }
+
+    /// Rolls a random byte, the way `Quirks::random` says.
+    fn random(&mut self) -> u8 {
+        match self.quirks.random {
+            Random::Prng => self.rng.gen(),
+            Random::Vip => {
+                let byte = self.memory[ROM_START_ADDRESS as usize | self.random_state as usize];
+                self.random_state = byte.wrapping_add(self.random_clock);
+                self.random_state
+            },
+            Random::Sequence => {
+                let byte = self.random_state;
+                self.random_state = byte.wrapping_add(1);
+                byte
+            },
+        }
     }
 
     // DXYN - DRW VX, VY, nibble. Display n-byte sprite starting at 
@@ -418,35 +785,45 @@ impl Chip8 {
 
         let height = opcode & 0x000F;
 
+        // past the sprites a frame can draw, wait for the next one.
+        if self.quirks.sprites_per_frame.is_some_and(|limit| self.sprites_drawn >= limit) {
+            self.program_counter -= 2;
+            return;
+        }
+        self.sprites_drawn += 1;
+
         // wrap if the sprite goes beyond the screen boundaries.
         let x_pos = self.registers[vx as usize] as u32 % VIDEO_WIDTH;
         let y_pos = self.registers[vy as usize] as u32 % VIDEO_HEIGHT;
 
         self.registers[0xF] = 0;
 
-        for row in 0..height {
-            let sprite_byte = self.memory[self.index + row as usize];
+        // the sprite is drawn on every selected plane, each plane's rows
+        // following the last one's in memory.
+        let (planes, mut sprite) = (self.planes, self.index);
+        for plane in [1, 2].iter().copied().filter(|&plane| planes & plane != 0) {
+            for row in 0..height {
+                let sprite_byte = self.read(sprite + row as usize);
 
-            for col in 0..8 {
-                let sprite_pixel = sprite_byte & (0x80 >> col);
+                for col in 0..8 {
+                    let sprite_pixel = sprite_byte & (0x80 >> col);
 
-                let xy_to_index = (
-                    ((y_pos + row as u32) * VIDEO_WIDTH + (x_pos + col)) as usize
-                ) % self.display_memory.len();
+                    let xy_to_index = (
+                        ((y_pos + row as u32) * VIDEO_WIDTH + (x_pos + col)) as usize
+                    ) % self.display_memory.len();
 
-                let mut screen_pixel = self.display_memory[xy_to_index];
+                    // if both the sprite pixel and the screen pixel is on: collision
+                    if sprite_pixel != 0 {
+                        if self.display_memory[xy_to_index] & plane != 0 {
+                            self.registers[0xF] = 1;
+                        }
 
-                // if both the sprite pixel and the screen pixel is on: collision
-                if sprite_pixel != 0 { 
-                    if screen_pixel == 0xFF { 
-                        self.registers[0xF] = 1;
+                        // effectively XOR with the sprite pixel
+                        self.display_memory[xy_to_index] ^= plane;
                     }
-
-                    // effectively XOR with the sprite pixel
-                    screen_pixel ^= 0xFF;
-                    self.display_memory[xy_to_index] = screen_pixel;
                 }
             }
+            sprite += height as usize;
         }
     }
 
@@ -457,6 +834,7 @@ impl Chip8 {
         let key = self.registers[vx as usize];
 
         if self.keypad[key as usize] { // the key was pressed
+            self.observed_keys |= 1 << key;
             self.program_counter += 2;
         }
     }
@@ -469,9 +847,28 @@ impl Chip8 {
 
         if !self.keypad[key as usize] { // the key was pressed
             self.program_counter += 2;
+        } else {
+            self.observed_keys |= 1 << key;
         }
     }
 
+    // FN01 - PLANE n. Select the planes to draw on and clear, XO-CHIP
+    // only: 1 for the first, 2 for the second, 3 for both and 0 for none.
+    fn opcode_fn01(&mut self, opcode: u16) {
+        self.planes = ((opcode & 0x0F00) >> 8) as u8 & 0b11;
+    }
+
+    // F002 - AUDIO. Load the 16 bytes from I as the audio pattern,
+    // XO-CHIP only.
+    fn opcode_f002(&mut self) {
+        let mut pattern = [0; 16];
+        for (i, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read(self.index + i);
+        }
+
+        self.audio_pattern = Some(pattern);
+    }
+
     // FX07 - LD VX, DT. Set VX = delay timer value.
     fn opcode_fx07(&mut self, opcode: u16) {
         let vx = (opcode & 0x0F00) >> 8;
@@ -483,42 +880,39 @@ impl Chip8 {
     // value of the key in VX.
     fn opcode_fx0a(&mut self, opcode: u16) {
         let vx = (opcode & 0x0F00) >> 8;
-
-        if self.keypad[0x0] {
-            self.registers[vx as usize] = 0x0;
-        } else if self.keypad[0x1] {
-            self.registers[vx as usize] = 0x1;
-        } else if self.keypad[0x2] {
-            self.registers[vx as usize] = 0x2;
-        } else if self.keypad[0x3] {
-            self.registers[vx as usize] = 0x3;
-        } else if self.keypad[0x4] {
-            self.registers[vx as usize] = 0x4;
-        } else if self.keypad[0x5] {
-            self.registers[vx as usize] = 0x5;
-        } else if self.keypad[0x6] {
-            self.registers[vx as usize] = 0x6;
-        } else if self.keypad[0x7] {
-            self.registers[vx as usize] = 0x7;
-        } else if self.keypad[0x8] {
-            self.registers[vx as usize] = 0x8;
-        } else if self.keypad[0x9] {
-            self.registers[vx as usize] = 0x9;
-        } else if self.keypad[0xA] {
-            self.registers[vx as usize] = 0xA;
-        } else if self.keypad[0xB] {
-            self.registers[vx as usize] = 0xB;
-        } else if self.keypad[0xC] {
-            self.registers[vx as usize] = 0xC;
-        } else if self.keypad[0xD] {
-            self.registers[vx as usize] = 0xD;
-        } else if self.keypad[0xE] {
-            self.registers[vx as usize] = 0xE;
-        } else if self.keypad[0xF] {
-            self.registers[vx as usize] = 0xF;
-        } else { // decrease the PC to reiterate the same instruction.
-            self.program_counter -= 2;
+        let held = self.keypad.iter().enumerate()
+            .filter(|&(_, &down)| down)
+            .fold(0u16, |held, (key, _)| held | 1 << key);
+        self.observed_keys |= held;
+
+        // a key that was down before the wait began doesn't count until
+        // it's been let go, so holding one can't answer several FX0As.
+        let ignored = self.held_before_wait.unwrap_or(held) & held;
+        self.held_before_wait = Some(ignored);
+        let pressed = (0..16).find(|&key| held & !ignored & 1 << key != 0);
+
+        if self.quirks.fx0a_on_press {
+            // complete as soon as any key is held down.
+            if let Some(key) = pressed {
+                self.registers[vx as usize] = key;
+                self.held_before_wait = None;
+                return;
+            }
+        } else if let Some(key) = self.awaited_key {
+            // a key went down earlier, complete once it's released.
+            if !self.keypad[key as usize] {
+                self.registers[vx as usize] = key;
+                self.awaited_key = None;
+                self.held_before_wait = None;
+                return;
+            }
+        } else if let Some(key) = pressed {
+            // remember the key and wait for it to be released.
+            self.awaited_key = Some(key);
         }
+
+        // decrease the PC to reiterate the same instruction.
+        self.program_counter -= 2;
     }
 
     // FX15 - LD DT, VX. Set delay timer = VX.
@@ -540,6 +934,11 @@ impl Chip8 {
         let vx = (opcode & 0x0F00) >> 8;
 
         self.index += self.registers[vx as usize] as usize;
+
+        // the Amiga interpreter flags I leaving addressable memory in VF.
+        if self.quirks.fx1e_overflow_vf {
+            self.registers[0xF] = (self.index > 0xFFF) as u8;
+        }
     }
 
     // FX29 - LD F, VX. Set I = location of sprite for digit VX.
@@ -557,15 +956,23 @@ impl Chip8 {
         let mut value = self.registers[vx as usize];
 
         // ones-place
-        self.memory[self.index + 2] = value % 10;
+        self.write(self.index + 2, value % 10);
         value /= 10;
         
         // tens-place
-        self.memory[self.index + 1] = value % 10;
+        self.write(self.index + 1, value % 10);
         value /= 10;
 
         // hundreds-place
-        self.memory[self.index] = value % 10;
+        self.write(self.index, value % 10);
+    }
+
+    // FX3A - PITCH VX. Set the pitch the audio pattern plays at = VX,
+    // XO-CHIP only.
+    fn opcode_fx3a(&mut self, opcode: u16) {
+        let vx = (opcode & 0x0F00) >> 8;
+
+        self.pitch = self.registers[vx as usize];
     }
 
     // FX55 - LD [I], VX. Store registers V0 through VX in 
@@ -574,7 +981,7 @@ impl Chip8 {
         let vx = (opcode & 0x0F00) >> 8;
 
         for i in 0..=vx as usize {
-            self.memory[self.index + i] = self.registers[i];
+            self.write(self.index + i, self.registers[i]);
         }
     }
 
@@ -584,8 +991,441 @@ impl Chip8 {
         let vx = (opcode & 0x0F00) >> 8;
 
         for i in 0..=vx as usize {
-            self.registers[i] = self.memory[self.index + i];
+            self.registers[i] = self.read(self.index + i);
         }
     }
 }
 
+/// A random number generator seeded from the OS, or from 0 without std,
+/// where there's no OS to ask.
+fn new_rng() -> StdRng {
+    #[cfg(feature = "std")]
+    return StdRng::from_entropy();
+
+    #[cfg(not(feature = "std"))]
+    StdRng::seed_from_u64(0)
+}
+
+/// Whether an opcode uses the display, the timers, the sound, the
+/// keypad or the random number generator.
+fn is_observable(opcode: u16) -> bool {
+    matches!(opcode::decode(opcode), Some(
+        Op::Cls | Op::Rnd | Op::Drw | Op::Plane | Op::Audio | Op::Skp | Op::Sknp
+        | Op::LdVxDt | Op::LdVxK | Op::LdDtVx | Op::LdStVx | Op::Pitch
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::quirks::MemoryRange;
+
+    use super::*;
+
+    impl Chip8 {
+        fn load_program(&mut self, program: &[u8]) {
+            let start = ROM_START_ADDRESS as usize;
+            self.memory[start..start + program.len()].copy_from_slice(program);
+        }
+    }
+
+    /// Runs the 8XYN opcode `n` on `registers`, and returns them afterwards.
+    fn alu(n: u16, x: usize, y: usize, registers: [u8; 16]) -> [u8; 16] {
+        let mut chippy = Chip8::new();
+        chippy.registers = registers;
+        chippy.decode_and_execute(0x8000 | (x as u16) << 8 | (y as u16) << 4 | n);
+        chippy.registers
+    }
+
+    /// The registers an 8XYN opcode should leave: the result goes in VX,
+    /// then the flag in VF, so the flag wins when X is F.
+    fn expected(registers: [u8; 16], x: usize, (result, flag): (u8, bool)) -> [u8; 16] {
+        let mut expected = registers;
+        expected[x] = result;
+        expected[0xF] = flag as u8;
+        expected
+    }
+
+    proptest! {
+        #[test]
+        fn add_sets_vf_on_carry(registers: [u8; 16], x in 0..16usize, y in 0..16usize) {
+            let sum = registers[x] as u16 + registers[y] as u16;
+            prop_assert_eq!(alu(0x4, x, y, registers), expected(registers, x, ((sum % 0x100) as u8, sum > 0xFF)));
+        }
+
+        #[test]
+        fn sub_sets_vf_unless_it_borrows(registers: [u8; 16], x in 0..16usize, y in 0..16usize) {
+            let diff = registers[x] as i16 - registers[y] as i16;
+            prop_assert_eq!(alu(0x5, x, y, registers), expected(registers, x, (diff.rem_euclid(0x100) as u8, diff >= 0)));
+        }
+
+        #[test]
+        fn subn_sets_vf_unless_it_borrows(registers: [u8; 16], x in 0..16usize, y in 0..16usize) {
+            let diff = registers[y] as i16 - registers[x] as i16;
+            prop_assert_eq!(alu(0x7, x, y, registers), expected(registers, x, (diff.rem_euclid(0x100) as u8, diff >= 0)));
+        }
+
+        #[test]
+        fn shr_moves_the_low_bit_to_vf(registers: [u8; 16], x in 0..16usize, y in 0..16usize) {
+            let shifted = (registers[x] >> 1, registers[x] & 1 == 1);
+            prop_assert_eq!(alu(0x6, x, y, registers), expected(registers, x, shifted));
+        }
+
+        #[test]
+        fn shl_moves_the_high_bit_to_vf(registers: [u8; 16], x in 0..16usize, y in 0..16usize) {
+            let shifted = (registers[x] << 1, registers[x] & 0x80 != 0);
+            prop_assert_eq!(alu(0xE, x, y, registers), expected(registers, x, shifted));
+        }
+    }
+
+    #[test]
+    fn fx1e_leaves_vf_alone_by_default() {
+        let mut chippy = Chip8::new();
+        chippy.index = 0xFFF;
+        chippy.registers[0x1] = 0x02;
+        chippy.registers[0xF] = 0x7;
+
+        chippy.decode_and_execute(0xF11E);
+
+        assert_eq!(chippy.index, 0x1001);
+        assert_eq!(chippy.registers[0xF], 0x7);
+    }
+
+    #[test]
+    fn fx1e_sets_vf_on_overflow_with_quirk() {
+        let mut chippy = Chip8::new();
+        chippy.quirks.fx1e_overflow_vf = true;
+        chippy.index = 0xFFF;
+        chippy.registers[0x1] = 0x02;
+
+        chippy.decode_and_execute(0xF11E);
+
+        assert_eq!(chippy.index, 0x1001);
+        assert_eq!(chippy.registers[0xF], 1);
+    }
+
+    #[test]
+    fn fx1e_clears_vf_without_overflow_with_quirk() {
+        let mut chippy = Chip8::new();
+        chippy.quirks.fx1e_overflow_vf = true;
+        chippy.index = 0x300;
+        chippy.registers[0x1] = 0x02;
+        chippy.registers[0xF] = 1;
+
+        chippy.decode_and_execute(0xF11E);
+
+        assert_eq!(chippy.index, 0x302);
+        assert_eq!(chippy.registers[0xF], 0);
+    }
+
+    #[test]
+    fn keys_seen_held_are_kept_until_taken() {
+        let mut chippy = Chip8::new();
+        chippy.registers[0x1] = 0x5;
+        chippy.keypad[0x5] = true;
+
+        chippy.decode_and_execute(0xE19E);
+        chippy.decode_and_execute(0xE1A1);
+        assert_eq!(chippy.take_observed_keys(), 1 << 0x5);
+        assert_eq!(chippy.take_observed_keys(), 0);
+
+        chippy.keypad[0x5] = false;
+        chippy.decode_and_execute(0xE19E);
+        assert_eq!(chippy.take_observed_keys(), 0);
+    }
+
+    #[test]
+    fn fx0a_finishes_when_the_key_is_released() {
+        let mut chippy = Chip8::initialize(&[0xF3, 0x0A]).unwrap();
+        chippy.cycle().unwrap();
+        chippy.keypad[0x7] = true;
+        chippy.cycle().unwrap();
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.program_counter(), 0x200);
+
+        chippy.keypad[0x7] = false;
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.program_counter(), 0x202);
+        assert_eq!(chippy.registers[0x3], 0x7);
+    }
+
+    #[test]
+    fn fx0a_ignores_a_key_held_before_it_started() {
+        let mut chippy = Chip8::initialize(&[0xF3, 0x0A]).unwrap();
+        chippy.keypad[0x7] = true;
+        chippy.cycle().unwrap();
+        chippy.keypad[0x7] = false;
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.program_counter(), 0x200);
+
+        chippy.keypad[0x7] = true;
+        chippy.cycle().unwrap();
+        chippy.keypad[0x7] = false;
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.program_counter(), 0x202);
+        assert_eq!(chippy.registers[0x3], 0x7);
+    }
+
+    #[test]
+    fn fx0a_finishes_on_the_press_with_quirk() {
+        let mut chippy = Chip8::initialize(&[0xF3, 0x0A]).unwrap();
+        chippy.quirks.fx0a_on_press = true;
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.program_counter(), 0x200);
+
+        chippy.keypad[0xB] = true;
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.program_counter(), 0x202);
+        assert_eq!(chippy.registers[0x3], 0xB);
+    }
+
+    #[test]
+    fn sprites_past_the_limit_wait_for_the_next_frame() {
+        let mut chippy = Chip8::initialize(&[0xD0, 0x01, 0xD0, 0x01]).unwrap();
+        chippy.quirks.sprites_per_frame = Some(1);
+
+        chippy.cycle().unwrap();
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.program_counter(), 0x202);
+
+        chippy.tick_timers();
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.program_counter(), 0x204);
+    }
+
+    #[test]
+    fn random_bytes_come_from_the_chosen_generator() {
+        let mut chippy = Chip8::initialize(&[0xC0, 0xFF, 0xC1, 0x0F, 0xC2, 0xFF]).unwrap();
+        chippy.quirks.random = Random::Sequence;
+        for _ in 0..3 {
+            chippy.cycle().unwrap();
+        }
+        assert_eq!(chippy.registers[..3], [0, 1, 2]);
+
+        // the byte at 0x200 plus the frame count, then the byte at 0x2C0.
+        chippy.quirks.random = Random::Vip;
+        chippy.reseed(0);
+        chippy.decode_and_execute(0xC0FF);
+        chippy.decode_and_execute(0xC1FF);
+        assert_eq!(chippy.registers[..2], [0xC0, 0x00]);
+        chippy.reseed(0);
+        chippy.tick_timers();
+        chippy.decode_and_execute(0xC0FF);
+        assert_eq!(chippy.registers[0], 0xC1);
+    }
+
+    #[test]
+    fn battery_ram_is_cut_off_at_the_end_of_memory() {
+        let mut chippy = Chip8::new();
+        assert_eq!(chippy.battery_ram(), None);
+
+        chippy.quirks.battery_ram = Some(MemoryRange { start: 0xFFE, end: 0x1FFF });
+        chippy.restore_battery_ram(&[1, 2, 3]);
+
+        assert_eq!(chippy.battery_ram(), Some(&[1, 2][..]));
+        assert_eq!(chippy.memory[0xFFD], 0);
+    }
+
+    #[test]
+    fn timers_only_count_down_on_ticks() {
+        let mut chippy = Chip8::new();
+        chippy.delay_timer = 2;
+        chippy.memory[0x200] = 0x60; // LD V0, 0x00
+        chippy.memory[0x201] = 0x00;
+
+        chippy.cycle().unwrap();
+        assert_eq!(chippy.delay_timer, 2);
+
+        chippy.tick_timers();
+        assert_eq!(chippy.delay_timer, 1);
+    }
+
+    #[test]
+    fn reserved_write_traps_when_pausing() {
+        let mut chippy = Chip8::new();
+        chippy.index = 0x1F0;
+        chippy.registers[0x0] = 0xAB;
+        chippy.memory[0x200] = 0xF0; // LD [I], V0
+        chippy.memory[0x201] = 0x55;
+
+        let trap = chippy.cycle().unwrap_err();
+
+        assert_eq!(trap, Trap::ReservedWrite { pc: 0x200, address: 0x1F0 });
+        assert_eq!(chippy.memory[0x1F0], 0);
+    }
+
+    #[test]
+    fn out_of_bounds_read_wraps_with_wrap_policy() {
+        let mut chippy = Chip8::new();
+        chippy.memory_policy = MemoryPolicy::Wrap;
+        chippy.index = 0xFFF;
+        chippy.memory[0xFFF] = 0x11;
+        chippy.memory[0x000] = 0x22;
+        chippy.memory[0x200] = 0xF1; // LD V1, [I]
+        chippy.memory[0x201] = 0x65;
+
+        assert_eq!(chippy.cycle(), Ok(()));
+        assert_eq!(chippy.registers[0x0], 0x11);
+        assert_eq!(chippy.registers[0x1], 0x22);
+    }
+
+    #[test]
+    fn call_with_a_full_stack_traps() {
+        let mut chippy = Chip8::new();
+        chippy.stack_pointer = 16;
+        chippy.memory[0x200] = 0x23; // CALL 0x300
+        chippy.memory[0x201] = 0x00;
+
+        let trap = chippy.cycle().unwrap_err();
+
+        assert_eq!(trap, Trap::StackOverflow { pc: 0x200, stack: [0; 16] });
+        assert_eq!(chippy.stack_pointer, 16);
+    }
+
+    #[test]
+    fn return_with_an_empty_stack_traps() {
+        let mut chippy = Chip8::new();
+        chippy.memory[0x200] = 0x00; // RET
+        chippy.memory[0x201] = 0xEE;
+
+        assert_eq!(chippy.cycle(), Err(Trap::StackUnderflow { pc: 0x200 }));
+        assert_eq!(chippy.stack_pointer, 0);
+    }
+
+    #[test]
+    fn invalid_opcode_follows_the_policy() {
+        let mut chippy = Chip8::new();
+        chippy.memory[0x200] = 0xE1; // not an EX opcode
+        chippy.memory[0x201] = 0x23;
+
+        assert_eq!(chippy.cycle(), Err(Trap::InvalidOpcode { pc: 0x200, opcode: 0xE123 }));
+
+        let mut chippy = Chip8::new();
+        chippy.opcode_policy = OpcodePolicy::Nop;
+        chippy.memory[0x200] = 0xE1;
+        chippy.memory[0x201] = 0x23;
+
+        assert_eq!(chippy.cycle(), Ok(()));
+        assert_eq!(chippy.program_counter, 0x202);
+    }
+
+    #[test]
+    fn jump_to_self_halts() {
+        let mut chippy = Chip8::new();
+        chippy.memory[0x200] = 0x12; // JP 0x200
+        chippy.memory[0x201] = 0x00;
+
+        assert_eq!(chippy.cycle(), Err(Trap::Halted { pc: 0x200 }));
+    }
+
+    #[test]
+    fn tight_loop_halts_once_it_repeats() {
+        let mut chippy = Chip8::new();
+        chippy.load_program(&[
+            0x70, 0x01, // ADD V0, 1
+            0x30, 0x00, // SE V0, 0
+            0x12, 0x00, // JP 0x200
+            0x12, 0x08, // JP 0x208
+            0x12, 0x06, // JP 0x206
+        ]);
+
+        let trap = (0..10_000).find_map(|_| chippy.cycle().err());
+
+        // the counting loop ends by itself, the ping-pong doesn't.
+        assert_eq!(chippy.registers[0x0], 0);
+        assert!(matches!(trap, Some(Trap::Halted { pc: 0x206 }) | Some(Trap::Halted { pc: 0x208 })));
+    }
+
+    #[test]
+    fn writes_to_executed_code_are_recorded() {
+        let mut chippy = Chip8::new();
+        chippy.load_program(&[
+            0xA2, 0x00, // LD I, 0x200
+            0xF0, 0x55, // LD [I], V0
+        ]);
+
+        chippy.cycle().unwrap();
+        chippy.cycle().unwrap();
+
+        assert!(chippy.executed[0x203]);
+        assert!(!chippy.executed[0x204]);
+        assert_eq!(chippy.take_code_writes(), vec![CodeWrite { pc: 0x202, address: 0x200 }]);
+        assert!(chippy.take_code_writes().is_empty());
+    }
+
+    #[test]
+    fn sprites_are_drawn_and_cleared_on_the_selected_planes() {
+        let mut chippy = Chip8::new();
+        // PLANE 3, LD I 0x300, DRW V0 V0 1, PLANE 1, CLS
+        chippy.load_program(&[0xF3, 0x01, 0xA3, 0x00, 0xD0, 0x01, 0xF1, 0x01, 0x00, 0xE0]);
+        chippy.memory[0x300] = 0b1100_0000;
+        chippy.memory[0x301] = 0b1010_0000;
+
+        for _ in 0..3 {
+            chippy.cycle().unwrap();
+        }
+        assert_eq!(&chippy.display_memory[..3], &[3, 1, 2]);
+        assert_eq!(chippy.registers[0xF], 0);
+
+        chippy.program_counter = 0x204;
+        chippy.cycle().unwrap();
+        assert_eq!(&chippy.display_memory[..3], &[0, 0, 0]);
+        assert_eq!(chippy.registers[0xF], 1);
+
+        chippy.program_counter = 0x204;
+        for _ in 0..3 {
+            chippy.cycle().unwrap();
+        }
+        assert_eq!(&chippy.display_memory[..3], &[2, 0, 2]);
+    }
+
+    #[test]
+    fn the_audio_pattern_and_pitch_are_loaded() {
+        let mut chippy = Chip8::new();
+        // LD I 0x300, AUDIO, LD V5 0x70, PITCH V5
+        chippy.load_program(&[0xA3, 0x00, 0xF0, 0x02, 0x65, 0x70, 0xF5, 0x3A]);
+        chippy.memory[0x300..0x310].copy_from_slice(&[0xA5; 16]);
+        assert_eq!((chippy.audio_pattern(), chippy.pitch()), (None, 64));
+
+        for _ in 0..4 {
+            chippy.cycle().unwrap();
+        }
+        assert_eq!(chippy.audio_pattern(), Some(&[0xA5; 16]));
+        assert_eq!(chippy.pitch(), 0x70);
+
+        let mut restored = Chip8::new();
+        restored.load_state(&chippy.save_state());
+        assert_eq!((restored.audio_pattern(), restored.pitch()), (Some(&[0xA5; 16]), 0x70));
+    }
+
+    #[test]
+    fn state_survives_a_json_round_trip() {
+        let mut chippy = Chip8::initialize(include_bytes!("../test_roms/test_opcode.ch8")).unwrap();
+        for _ in 0..100 {
+            chippy.cycle().unwrap();
+        }
+
+        let state = chippy.save_state();
+        let json = state.to_json();
+        let mut restored = Chip8::new();
+        restored.load_state(&MachineState::from_json(&json).unwrap());
+
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.display_memory.to_vec(), chippy.display_memory.to_vec());
+    }
+
+    #[test]
+    fn state_survives_a_binary_round_trip() {
+        let mut chippy = Chip8::initialize(include_bytes!("../test_roms/test_opcode.ch8")).unwrap();
+        for _ in 0..100 {
+            chippy.cycle().unwrap();
+        }
+
+        let state = chippy.save_state();
+        let bytes = state.to_bytes();
+
+        assert!(bytes.starts_with(crate::state::MAGIC));
+        assert_eq!(MachineState::from_bytes(&bytes).unwrap(), state);
+    }
+}
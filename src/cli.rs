@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+
+use clap::{ Args, Parser, Subcommand };
+
+use chippy::platform::Platform;
+
+/// The ROM that's run when none is given.
+pub const DEFAULT_ROM: &str = "test_roms/Tetris [Fran Dachille, 1991].ch8";
+
+/// Where `chippy roms` keeps the ROMs it downloads.
+pub const ROMS_DIR: &str = "roms";
+
+/// The port the debug server listens on when none is given.
+const DEFAULT_DEBUG_PORT: &str = "8066";
+
+/// The port remote control listens on when none is given.
+const DEFAULT_REMOTE_PORT: &str = "8065";
+
+/// Chippy, a CHIP-8 emulator
+#[derive(Parser)]
+#[command(name = "chippy", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a ROM in a window
+    Run {
+        /// A ROM file, - to read it from standard input, or an http(s) URL
+        #[arg(default_value = DEFAULT_ROM)]
+        rom: String,
+        #[command(flatten)]
+        options: RunOptions,
+        #[command(flatten)]
+        headless: HeadlessOptions,
+    },
+    /// Run a ROM twice side by side with different quirk profiles, and
+    /// stop where the displays first differ
+    Compare {
+        rom: String,
+        /// The quirk profile on the left: vip, amiga, or one from chippy.toml
+        #[arg(long, default_value = "vip")]
+        left: String,
+        /// The quirk profile on the right
+        #[arg(long, default_value = "amiga")]
+        right: String,
+    },
+    /// Print a ROM's SHA-1 and CRC32, and what the ROM index knows about it
+    Info {
+        /// A ROM file, - or an http(s) URL
+        rom: String,
+    },
+    /// Follow a ROM's code without running it, and report what's
+    /// reachable, the instructions it uses, the platform it needs and what
+    /// looks like data
+    Check {
+        /// A ROM file, - or an http(s) URL
+        rom: String,
+        /// Run it for this many frames too, with no keys pressed, and
+        /// report what was executed
+        #[arg(long, value_name = "N")]
+        frames: Option<u64>,
+    },
+    /// Carry on from a workspace file: a ROM with its quirks, keys, speed,
+    /// breakpoints and save states, and the machine where it was left
+    /// (Shift+F2 in the window writes one)
+    Open {
+        /// A .chippy workspace
+        path: PathBuf,
+        #[command(flatten)]
+        options: RunOptions,
+    },
+    /// Export or import the full machine state as JSON
+    #[command(subcommand)]
+    State(StateCommand),
+    /// Download free test and demo ROMs, or list them
+    #[command(subcommand)]
+    Roms(RomsCommand),
+    /// Tools for writing CHIP-8 programs
+    #[command(subcommand)]
+    Dev(DevCommand),
+}
+
+#[derive(Subcommand)]
+pub enum DevCommand {
+    /// Assemble a program and run it, and assemble and load it again
+    /// every time it's saved
+    Watch {
+        /// The program's source
+        source: PathBuf,
+        /// The assembler command, with {source} and {rom} for the paths,
+        /// e.g. "octo {source} {rom}" (or assembler in chippy.toml)
+        #[arg(long, value_name = "COMMAND")]
+        assembler: Option<String>,
+        #[command(flatten)]
+        options: RunOptions,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RomsCommand {
+    /// Download the ROMs that aren't there yet, checking each one's SHA-1
+    Fetch {
+        /// The directory to put them in
+        #[arg(long, default_value = ROMS_DIR)]
+        dir: PathBuf,
+    },
+    /// List the ROMs, and whether they've been downloaded
+    List {
+        /// The directory they're downloaded to
+        #[arg(long, default_value = ROMS_DIR)]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StateCommand {
+    /// Run a ROM without a window and export the machine state, or
+    /// convert a binary save state to JSON
+    Export {
+        /// A ROM, or a binary save state
+        input: String,
+        /// How many instructions to run before exporting
+        #[arg(long, default_value_t = 0)]
+        cycles: u64,
+        /// Where to write the JSON, instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Start the emulator from an exported machine state (JSON or binary)
+    Import {
+        path: PathBuf,
+        #[command(flatten)]
+        options: RunOptions,
+    },
+}
+
+/// Options for running without a window, for scripted tests and
+/// screenshots.
+#[derive(Args, Clone, Default)]
+pub struct HeadlessOptions {
+    /// Run this many frames (at 60 a second) without a window, then stop
+    #[arg(long, value_name = "N")]
+    pub frames: Option<u64>,
+    /// Write the last frame to this file, a .png or a .ppm
+    #[arg(long, value_name = "PATH", requires = "frames")]
+    pub dump_frame: Option<PathBuf>,
+    /// Write every Kth frame instead, numbered, e.g. out-00060.png
+    #[arg(long, value_name = "K", requires = "dump_frame",
+        value_parser = clap::value_parser!(u64).range(1..))]
+    pub dump_every: Option<u64>,
+    /// How many pixels across each CHIP-8 pixel is in dumped frames
+    #[arg(long, default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=32))]
+    pub scale: u32,
+}
+
+/// Options for running the emulator in a window.
+#[derive(Args, Clone, Default)]
+pub struct RunOptions {
+    /// Stream the display to spectators connecting to this address,
+    /// e.g. 0.0.0.0:8064 (open it in a browser to watch)
+    #[arg(long, value_name = "ADDR")]
+    pub stream: Option<String>,
+    /// Accept remote-control actions as JSON over a WebSocket on
+    /// localhost, on this port or 8065
+    #[arg(long, value_name = "PORT", num_args = 0..=1,
+        default_missing_value = DEFAULT_REMOTE_PORT)]
+    pub remote: Option<u16>,
+    /// Let remote control be reached from other machines too, with
+    /// no authentication
+    #[arg(long, requires = "remote")]
+    pub remote_public: bool,
+    /// Serve /registers, /memory, /framebuffer.png and /disasm over
+    /// HTTP on localhost, on this port or 8066
+    #[arg(long, value_name = "PORT", num_args = 0..=1,
+        default_missing_value = DEFAULT_DEBUG_PORT)]
+    pub debug_server: Option<u16>,
+    /// Show an on-screen keypad under the display, for touch screens
+    /// (always on on Android)
+    #[arg(long)]
+    pub touch_keypad: bool,
+    /// Start in borderless fullscreen (F11 switches)
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// The monitor to open on, by number from 0 or by part of its name
+    /// (the one it was on last time by default)
+    #[arg(long, value_name = "MONITOR")]
+    pub monitor: Option<String>,
+    /// Don't wait for the display's refresh to draw a frame
+    #[arg(long)]
+    pub no_vsync: bool,
+    /// Draw as many frames as possible, without vsync, for benchmarking
+    /// (emulation stays at 60 frames a second)
+    #[arg(long)]
+    pub uncapped: bool,
+    /// Dump memory, the registers and the stack to a timestamped file
+    /// when the emulator stops (F1 dumps them any time)
+    #[arg(long)]
+    pub dump_on_exit: bool,
+    /// Write a bug report, a zip of the machine, the config, a trace and a
+    /// screenshot, when the emulator stops (Shift+F1 writes one any time)
+    #[arg(long)]
+    pub report_on_exit: bool,
+    /// Press and release keys at given frames, as a script read from
+    /// this file or from standard input for -
+    #[arg(long, value_name = "PATH")]
+    pub input_script: Option<String>,
+    /// The platform the ROM was written for: chip8, schip or xochip,
+    /// which decides how fast it runs by default
+    #[arg(long)]
+    pub platform: Option<Platform>,
+    /// Measure input latency, how long key presses take to be seen by the
+    /// game and to reach the screen, to tune vsync with
+    #[arg(long)]
+    pub latency: bool,
+    /// How many instructions run a frame, whatever the platform
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=10000))]
+    pub ipf: Option<u32>,
+}
@@ -0,0 +1,538 @@
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::time::SystemTime;
+
+use serde::{ Deserialize, Serialize };
+
+use chippy::chip8::{ self, Chip8 };
+use chippy::quirks::Quirks;
+use chippy::rewind::Rewind;
+use chippy::state::MachineState;
+use chippy::trap::Trap;
+
+use crate::bug_report;
+use crate::debugger::Debugger;
+use crate::dump;
+use crate::input_script::InputScript;
+use crate::present::Palette;
+use crate::rom_info::{ RomId, RomInfo };
+use crate::SAVES_DIR;
+
+/// The file a game's battery-backed memory is kept in, next to its save
+/// states.
+const BATTERY_FILE: &str = "battery.ram";
+
+/// How many frames apart the machine is kept while an input script plays,
+/// to seek back from.
+const KEYFRAME_INTERVAL: u64 = 60;
+
+/// How many keyframes are kept. Past that, every other one is let go,
+/// so older parts of a long script are further apart to seek back to.
+const MAX_KEYFRAMES: usize = 600;
+
+/// Something the emulator can be told to do, from the debugger keys or
+/// by a remote-control client. In JSON an action is an object naming
+/// it in `action`, next to its arguments, e.g.
+/// `{ "action": "read_memory", "address": 512, "length": 16 }`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    Pause,
+    Resume,
+    TogglePause,
+    /// Runs a single instruction while paused.
+    Step,
+    /// Runs a whole frame while paused.
+    StepFrame,
+    /// Plays the input script from a frame, going back or forward.
+    Seek { frame: u64 },
+    ToggleBreakpoint { address: usize },
+    /// Puts the machine back the way it was started.
+    Reset,
+    LoadRom { path: String },
+    PressKey { key: u8 },
+    ReleaseKey { key: u8 },
+    ReadMemory { address: usize, length: usize },
+    Screenshot,
+    /// Writes the machine state to a file as JSON.
+    ExportState { path: PathBuf },
+    /// Dumps memory, the registers and the stack to a timestamped text
+    /// file in the working directory.
+    Dump,
+    /// Bundles what's needed to look into a bug into a timestamped zip
+    /// in the working directory (see `bug_report::bundle`).
+    BugReport,
+}
+
+impl Action {
+    /// Whether a remote-control client may ask for it: actions that read
+    /// or write files are left to the keys, so whoever can reach the
+    /// socket can't write wherever the emulator can, or load a file as a
+    /// ROM and read it back out of memory.
+    pub fn allowed_remotely(&self) -> bool {
+        !matches!(self, Action::LoadRom { .. } | Action::ExportState { .. } | Action::Dump | Action::BugReport)
+    }
+}
+
+/// The outcome of an action, named in `reply` in JSON.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum Reply {
+    Done,
+    /// Memory as hex, two digits a byte.
+    Memory { address: usize, bytes: String },
+    /// The display as rows of `#` (on) and `.` (off).
+    Screenshot { rows: Vec<String> },
+    /// Where a dump or a bug report went.
+    Dumped { path: PathBuf },
+    Error { message: String },
+}
+
+impl Reply {
+    fn error(message: String) -> Self {
+        Reply::Error { message }
+    }
+}
+
+/// The machine and everything around it that actions act on.
+pub struct Session {
+    pub chippy: Chip8,
+    pub debugger: Debugger,
+    pub rewind: Rewind,
+    /// The ROM or state file the machine was started from.
+    pub source: String,
+    /// The ROM the machine was started with, unless it was a state file.
+    pub rom: Option<RomId>,
+    /// Input played back as the frames go by.
+    pub input_script: Option<InputScript>,
+    /// How many frames have run, not counting those spent paused.
+    pub frame: u64,
+    // the machine as it was at the start of every so many frames of the
+    // input script, to seek back from.
+    keyframes: Vec<(u64, Chip8)>,
+    /// How many instructions run a frame.
+    pub instructions_per_frame: u32,
+    /// How many instructions have run.
+    pub instructions: u64,
+    /// The colors the display is shown in, for screenshots.
+    pub palette: Palette,
+    // the machine as it was started, for resets.
+    boot_state: MachineState,
+    // the quirks the session was set up with, before the ROM index had
+    // its say, for the next ROM loaded.
+    quirks: Quirks,
+}
+
+impl Session {
+    pub fn new(chippy: Chip8, source: &str) -> Self {
+        Self {
+            boot_state: chippy.save_state(),
+            quirks: chippy.quirks,
+            chippy,
+            debugger: Debugger::new(),
+            rewind: Rewind::new(),
+            source: source.to_string(),
+            rom: None,
+            input_script: None,
+            frame: 0,
+            keyframes: Vec::new(),
+            instructions_per_frame: chip8::INSTRUCTIONS_PER_FRAME,
+            instructions: 0,
+            palette: Palette::default(),
+        }
+    }
+
+    /// A session on `rom`, loaded into `chippy` from `source`. A game the
+    /// ROM index knows needs a sprite limit gets it, unless the quirks
+    /// already have one, and battery-backed memory is put back.
+    pub fn for_rom(mut chippy: Chip8, source: &str, rom: &[u8]) -> Self {
+        let id = RomId::of(rom);
+        let quirks = chippy.quirks;
+        if quirks.sprites_per_frame.is_none() {
+            chippy.quirks.sprites_per_frame = RomInfo::lookup(&id).and_then(|info| info.sprites_per_frame);
+        }
+
+        let mut session = Self::new(chippy, source);
+        session.quirks = quirks;
+        session.rom = Some(id);
+        session.load_battery();
+        session
+    }
+
+    /// Runs a frame: the input script's actions due by now, as many of
+    /// `instructions_per_frame` as the debugger lets through, then a tick
+    /// of the timers, unless emulation is paused.
+    pub fn run_frame(&mut self) {
+        let keyframe_due = self.frame.is_multiple_of(KEYFRAME_INTERVAL)
+            && self.keyframes.last().is_none_or(|&(at, _)| at < self.frame);
+        if self.input_script.is_some() && keyframe_due {
+            if self.keyframes.len() == MAX_KEYFRAMES {
+                // the first is kept, there's always one to seek back to.
+                let mut index = 0;
+                self.keyframes.retain(|_| {
+                    index += 1;
+                    index % 2 == 1
+                });
+            }
+            self.keyframes.push((self.frame, self.chippy.clone()));
+        }
+
+        if let Some(script) = &mut self.input_script {
+            for action in script.due(self.frame) {
+                self.execute(action);
+            }
+        }
+
+        for _ in 0..self.instructions_per_frame {
+            let pc = self.chippy.program_counter();
+            if !self.debugger.should_run(pc) {
+                break;
+            }
+
+            let opcode = (self.chippy.peek(pc) as u16) << 8 | self.chippy.peek(pc + 1) as u16;
+            let result = self.chippy.cycle();
+            self.instructions += 1;
+            self.debugger.record_step(pc, opcode, self.chippy.program_counter());
+
+            if let Err(trap) = result {
+                // invalid opcodes only trap when the policy is to halt,
+                // and the dump shows what the program was up to.
+                if let Trap::InvalidOpcode { .. } = trap {
+                    match self.dump(&trap.to_string()) {
+                        Ok(path) => eprintln!("chippy: dumped the machine to {}", path.display()),
+                        Err(e) => eprintln!("chippy: failed to dump the machine: {}", e),
+                    }
+                }
+                self.debugger.report(trap);
+            }
+            self.debugger.note_code_writes(self.chippy.take_code_writes());
+        }
+
+        if !self.debugger.paused {
+            self.chippy.tick_timers();
+            self.frame += 1;
+            self.rewind.record(&self.chippy);
+        }
+    }
+
+    pub fn execute(&mut self, action: Action) -> Reply {
+        match action {
+            Action::Pause => self.debugger.pause(),
+            Action::Resume => self.debugger.resume(),
+            Action::TogglePause => self.debugger.toggle_pause(),
+            Action::Step => self.debugger.step(),
+            Action::StepFrame => {
+                if self.debugger.paused {
+                    self.run_to(self.frame + 1);
+                }
+            },
+            Action::Seek { frame } => return self.seek(frame),
+            Action::ToggleBreakpoint { address } => self.debugger.toggle_breakpoint(address),
+            Action::Reset => {
+                // battery-backed memory outlives a reset, that's its point,
+                // and the quirks are settings rather than state.
+                let battery = self.chippy.battery_ram().map(<[u8]>::to_vec);
+                let quirks = self.chippy.quirks;
+                self.chippy.load_state(&self.boot_state);
+                self.chippy.quirks = quirks;
+                if let Some(battery) = battery {
+                    self.chippy.restore_battery_ram(&battery);
+                }
+                self.rewind = Rewind::new();
+            },
+            Action::LoadRom { path } => return self.load_rom(path),
+            Action::PressKey { key } | Action::ReleaseKey { key } if key > 0xF => {
+                return Reply::error(format!("there's no key {:#x}, keys go from 0x0 to 0xf", key));
+            },
+            Action::PressKey { key } => self.chippy.keypad[key as usize] = true,
+            Action::ReleaseKey { key } => self.chippy.keypad[key as usize] = false,
+            Action::ReadMemory { address, length } => {
+                if address.checked_add(length).is_none_or(|end| end > 4096) {
+                    return Reply::error(format!(
+                        "{} bytes from {:#05x} runs past the end of memory", length, address));
+                }
+
+                let bytes = (address..address + length)
+                    .map(|address| format!("{:02X}", self.chippy.peek(address)))
+                    .collect();
+                return Reply::Memory { address, bytes };
+            },
+            Action::Screenshot => {
+                let rows = self.chippy.display_memory.chunks(chip8::VIDEO_WIDTH as usize)
+                    .map(|row| row.iter().map(|&pixel| if pixel != 0 { '#' } else { '.' }).collect())
+                    .collect();
+                return Reply::Screenshot { rows };
+            },
+            Action::ExportState { path } => {
+                if let Err(e) = fs::write(&path, self.chippy.save_state().to_json()) {
+                    return Reply::error(format!("failed to write {}: {}", path.display(), e));
+                }
+            },
+            Action::Dump => return match self.dump("requested") {
+                Ok(path) => Reply::Dumped { path },
+                Err(e) => Reply::error(format!("failed to dump the machine: {}", e)),
+            },
+            Action::BugReport => return match self.bug_report("requested") {
+                Ok(path) => Reply::Dumped { path },
+                Err(e) => Reply::error(format!("failed to write a bug report: {}", e)),
+            },
+        }
+
+        Reply::Done
+    }
+
+    /// Plays the input script from `frame`: going back starts from the
+    /// last keyframe before it, then both ways the frames in between run
+    /// as fast as they can.
+    fn seek(&mut self, frame: u64) -> Reply {
+        let script = match &mut self.input_script {
+            Some(script) => script,
+            None => return Reply::error("there's no input script to seek in".to_string()),
+        };
+
+        if frame < self.frame {
+            // keyframes past the one gone back to may have seen other
+            // input, and are taken again on the way.
+            let index = self.keyframes.iter().rposition(|&(at, _)| at <= frame)
+                .expect("the first frame of a script is always a keyframe");
+            self.keyframes.truncate(index + 1);
+
+            let (at, chippy) = &self.keyframes[index];
+            self.chippy = chippy.clone();
+            self.frame = *at;
+            script.seek(*at);
+            self.rewind = Rewind::new();
+        }
+        self.run_to(frame);
+
+        Reply::Done
+    }
+
+    /// Runs frames until `frame`, paused or not, stopping early at a
+    /// breakpoint or a trap.
+    fn run_to(&mut self, frame: u64) {
+        let paused = self.debugger.paused;
+        self.debugger.paused = false;
+        while self.frame < frame && !self.debugger.paused {
+            self.run_frame();
+        }
+        self.debugger.paused |= paused;
+    }
+
+    /// What the game is called in the files and directories kept for it:
+    /// the SHA-1 of the ROM, so renaming the file doesn't lose them, or
+    /// without a ROM the name of the state file it was started from.
+    pub fn game_key(&self) -> String {
+        match &self.rom {
+            Some(rom) => rom.sha1.clone(),
+            None => source_name(&self.source),
+        }
+    }
+
+    /// Where the game's battery-backed memory is kept, for games run from a
+    /// ROM. States carry the memory themselves.
+    fn battery_path(&self) -> Option<PathBuf> {
+        self.rom.as_ref().map(|rom| Path::new(SAVES_DIR).join(&rom.sha1).join(BATTERY_FILE))
+    }
+
+    /// Puts back the battery-backed memory kept from the last run, if the
+    /// quirks ask for some and there is any.
+    pub fn load_battery(&mut self) {
+        let path = match self.battery_path() {
+            Some(path) if self.chippy.battery_ram().is_some() => path,
+            _ => return,
+        };
+
+        match fs::read(&path) {
+            Ok(bytes) => self.chippy.restore_battery_ram(&bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => eprintln!("chippy: failed to read {}: {}", path.display(), e),
+        }
+    }
+
+    /// Keeps the battery-backed memory for the next run.
+    pub fn save_battery(&self) {
+        let (path, memory) = match (self.battery_path(), self.chippy.battery_ram()) {
+            (Some(path), Some(memory)) => (path, memory),
+            _ => return,
+        };
+
+        let saved = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, memory));
+        if let Err(e) = saved {
+            eprintln!("chippy: failed to save {}: {}", path.display(), e);
+        }
+    }
+
+    /// Writes a dump of the machine (see `dump::dump`) to the working
+    /// directory, and returns where.
+    pub fn dump(&self, reason: &str) -> io::Result<PathBuf> {
+        let path = PathBuf::from(dump::file_name(SystemTime::now()));
+        fs::write(&path, dump::dump(&self.chippy, reason))?;
+        Ok(path)
+    }
+
+    /// Writes a bug report (see `bug_report::bundle`) to the working
+    /// directory, and returns where.
+    pub fn bug_report(&self, reason: &str) -> io::Result<PathBuf> {
+        let now = SystemTime::now();
+        let path = PathBuf::from(bug_report::file_name(now));
+        fs::write(&path, bug_report::bundle(self, reason, now))?;
+        Ok(path)
+    }
+
+    /// Swaps the machine for a fresh one running another ROM, with the
+    /// same quirks and policies.
+    fn load_rom(&mut self, path: String) -> Reply {
+        match fs::read(&path) {
+            Ok(rom) => self.load_program(&rom, &path),
+            Err(e) => Reply::error(format!("failed to read {}: {}", path, e)),
+        }
+    }
+
+    /// Starts `rom` on a fresh machine with the same settings, as if it
+    /// had been read from `path`, which names its saves. The ROM index is
+    /// asked about the new game, as it is for the first (see `for_rom`).
+    pub fn load_program(&mut self, rom: &[u8], path: &str) -> Reply {
+        let mut chippy = match Chip8::initialize(rom) {
+            Ok(chippy) => chippy,
+            Err(e) => return Reply::error(format!("failed to load {}: {}", path, e)),
+        };
+        chippy.quirks = self.quirks;
+        chippy.memory_policy = self.chippy.memory_policy;
+        chippy.opcode_policy = self.chippy.opcode_policy;
+
+        let (visible, instructions_per_frame, palette) = (self.debugger.visible, self.instructions_per_frame, self.palette);
+        self.save_battery();
+        *self = Self::for_rom(chippy, path, rom);
+        self.palette = palette;
+        self.debugger.visible = visible;
+        self.instructions_per_frame = instructions_per_frame;
+
+        Reply::Done
+    }
+}
+
+/// The name of a ROM or state file, without the extension.
+pub fn source_name(source: &str) -> String {
+    let name = match source {
+        "-" => Some("stdin".to_string()),
+        _ => Path::new(source).file_stem().map(|stem| stem.to_string_lossy().into_owned()),
+    };
+    name.unwrap_or_else(|| "unnamed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chippy::rewind::REWIND_INTERVAL;
+
+    use super::*;
+
+    #[test]
+    fn actions_are_read_from_json() {
+        let action: Action = serde_json::from_str(r#"{ "action": "read_memory", "address": 512, "length": 2 }"#).unwrap();
+        assert_eq!(action, Action::ReadMemory { address: 512, length: 2 });
+
+        let reply = serde_json::to_value(Reply::Memory { address: 512, bytes: "00E0".to_string() }).unwrap();
+        assert_eq!(reply, serde_json::json!({ "reply": "memory", "address": 512, "bytes": "00E0" }));
+    }
+
+    #[test]
+    fn memory_is_read_within_bounds() {
+        let mut session = Session::new(Chip8::new(), "test");
+
+        assert_eq!(session.execute(Action::ReadMemory { address: 4094, length: 2 }),
+            Reply::Memory { address: 4094, bytes: "0000".to_string() });
+        assert!(matches!(session.execute(Action::ReadMemory { address: 4095, length: 2 }), Reply::Error { .. }));
+    }
+
+    #[test]
+    fn reset_puts_the_machine_back() {
+        let mut session = Session::new(Chip8::new(), "test");
+        session.chippy.display_memory[0] = 0xFF;
+
+        session.execute(Action::Reset);
+
+        assert_eq!(session.chippy.display_memory[0], 0);
+    }
+
+    #[test]
+    fn reset_keeps_the_quirks() {
+        let mut session = Session::for_rom(Chip8::initialize(&[0x12, 0x00]).unwrap(), "test", &[0x12, 0x00]);
+        // as a game's own limit would be, or one set since it started
+        session.chippy.quirks.sprites_per_frame = Some(2);
+
+        session.execute(Action::Reset);
+
+        assert_eq!(session.chippy.quirks.sprites_per_frame, Some(2));
+    }
+
+    #[test]
+    fn new_roms_start_from_the_quirks_the_session_was_set_up_with() {
+        let mut session = Session::new(Chip8::new(), "test");
+        session.chippy.quirks.sprites_per_frame = Some(2);
+
+        session.load_program(&[0x12, 0x00], "other.ch8");
+
+        assert_eq!(session.chippy.quirks.sprites_per_frame, None);
+    }
+
+    #[test]
+    fn seeking_back_plays_the_script_the_same_way_again() {
+        // counts in V0, rolls random numbers into V1
+        let chippy = Chip8::initialize(&[0x70, 0x01, 0xC1, 0xFF, 0x12, 0x00]).unwrap();
+        let mut session = Session::new(chippy, "test");
+        session.input_script = Some(InputScript::parse("30 5 press\n100 5 release").unwrap());
+
+        assert_eq!(session.execute(Action::Seek { frame: 150 }), Reply::Done);
+        let state = session.chippy.save_state();
+
+        session.execute(Action::Seek { frame: 40 });
+        assert_eq!(session.frame, 40);
+        assert!(session.chippy.keypad[5]);
+
+        session.execute(Action::Seek { frame: 150 });
+        assert_eq!(session.chippy.save_state(), state);
+        assert!(!session.chippy.keypad[5]);
+    }
+
+    #[test]
+    fn keyframes_thin_out_over_long_scripts() {
+        // rolls random numbers, so the loop isn't taken for a hang
+        let chippy = Chip8::initialize(&[0x70, 0x01, 0xC1, 0xFF, 0x12, 0x00]).unwrap();
+        let mut session = Session::new(chippy, "test");
+        session.input_script = Some(InputScript::parse("1 5 press").unwrap());
+
+        session.execute(Action::Seek { frame: MAX_KEYFRAMES as u64 * KEYFRAME_INTERVAL + 1 });
+        assert_eq!(session.keyframes.len(), MAX_KEYFRAMES / 2 + 1);
+        assert_eq!(session.keyframes[0].0, 0);
+        assert_eq!(session.keyframes[1].0, 2 * KEYFRAME_INTERVAL);
+
+        session.execute(Action::Seek { frame: 90 });
+        assert_eq!(session.frame, 90);
+        assert!(session.chippy.keypad[5]);
+    }
+
+    #[test]
+    fn rewind_snapshots_are_taken_every_so_many_frames() {
+        // rolls random numbers, so the loop isn't taken for a hang
+        let chippy = Chip8::initialize(&[0x70, 0x01, 0xC1, 0xFF, 0x12, 0x00]).unwrap();
+        let mut session = Session::new(chippy, "test");
+        session.instructions_per_frame = 1000;
+
+        for _ in 0..REWIND_INTERVAL {
+            session.run_frame();
+        }
+        assert!(session.rewind.step_back(&mut session.chippy));
+        assert!(!session.rewind.step_back(&mut session.chippy));
+    }
+
+    #[test]
+    fn only_keypad_keys_can_be_pressed() {
+        let mut session = Session::new(Chip8::new(), "test");
+
+        assert_eq!(session.execute(Action::PressKey { key: 0xF }), Reply::Done);
+        assert!(session.chippy.keypad[0xF]);
+        assert!(matches!(session.execute(Action::PressKey { key: 0x10 }), Reply::Error { .. }));
+    }
+}
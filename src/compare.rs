@@ -0,0 +1,194 @@
+use std::time::Instant;
+
+use winit::dpi::LogicalSize;
+use winit::event::{ Event, VirtualKeyCode };
+use winit::event_loop::{ ControlFlow, EventLoop };
+use winit::window::WindowBuilder;
+
+use winit_input_helper::WinitInputHelper;
+
+use chippy::chip8::{ self, Chip8 };
+
+use crate::canvas::Canvas;
+use crate::checksum;
+use crate::keymap::{ self, Keymap };
+use crate::present::{ DisplayFilter, Palette, Presenter };
+use crate::scheduler::FrameScheduler;
+use crate::{ DISPLAY_HEIGHT, DISPLAY_WIDTH, SCALE };
+
+/// Space between the two displays, in frame pixels.
+const GAP: u32 = 8;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const DIFFERENT: [u8; 4] = [0xE8, 0x48, 0x48, 0xFF];
+
+/// Both machines roll the same random numbers, so only the quirks set
+/// them apart.
+const SEED: u64 = 0xC8;
+
+/// One of the two machines being compared.
+pub struct Side {
+    name: String,
+    chippy: Chip8,
+    presenter: Presenter,
+    stopped: bool,
+}
+
+impl Side {
+    pub fn new(name: &str, chippy: Chip8, palette: Palette) -> Self {
+        let mut presenter = Presenter::new(DisplayFilter::None, 1.0);
+        presenter.palette = palette;
+        Self { name: name.to_string(), chippy, presenter, stopped: false }
+    }
+}
+
+/// Runs two machines side by side in a window, feeding both the same
+/// input, and pauses at the first instruction after which their
+/// displays differ. From then on the differing pixels are highlighted.
+/// The first frame after which the machines differ at all is logged,
+/// with what's different. F5 pauses and resumes, and K shows the
+/// frame and the checksum of each machine.
+pub fn run(mut left: Side, mut right: Side, keys: Keymap) -> ! {
+    // initialization //
+
+    left.chippy.reseed(SEED);
+    right.chippy.reseed(SEED);
+
+    let mut paused = false;
+    let mut instructions: u64 = 0;
+    let mut diverged_at = None;
+    let mut frame: u64 = 0;
+    let mut states_differ = false;
+    let mut show_checksums = false;
+    let mut title = String::new();
+    let mut scheduler = FrameScheduler::new(Instant::now());
+
+    let event_loop = EventLoop::new();
+    let mut input = WinitInputHelper::new();
+    let (width, height) = (2 * DISPLAY_WIDTH + GAP, DISPLAY_HEIGHT);
+    let window = WindowBuilder::new()
+        .with_title("Chippy")
+        .with_resizable(false)
+        .with_inner_size(LogicalSize::new(width, height))
+        .build(&event_loop)
+        .unwrap();
+
+    let mut pixels = crate::create_pixels(&window, (width, height), true);
+
+    // event loop //
+
+    event_loop.run(move |event, _, control_flow| {
+        let frames = scheduler.due(Instant::now());
+        for _ in 0..frames {
+            for _ in 0..chip8::INSTRUCTIONS_PER_FRAME {
+                if paused {
+                    break;
+                }
+
+                for side in [&mut left, &mut right].iter_mut().filter(|side| !side.stopped) {
+                    if let Err(trap) = side.chippy.cycle() {
+                        eprintln!("chippy: {} stopped: {}", side.name, trap);
+                        side.stopped = true;
+                    }
+                }
+                instructions += 1;
+
+                if diverged_at.is_none() && left.chippy.display_memory[..] != right.chippy.display_memory[..] {
+                    eprintln!("chippy: the displays differ after {} instructions", instructions);
+                    diverged_at = Some(instructions);
+                    paused = true;
+                }
+            }
+
+            if !paused {
+                left.chippy.tick_timers();
+                right.chippy.tick_timers();
+                frame += 1;
+
+                // the quirks are left out, so this is the first frame
+                // they made a difference to.
+                if !states_differ {
+                    let diff = left.chippy.save_state().diff(&right.chippy.save_state());
+                    if !diff.is_empty() {
+                        eprintln!("chippy: the machines differ from frame {} on, {} vs {}:", frame, left.name, right.name);
+                        for line in diff {
+                            eprintln!("chippy:   {}", line);
+                        }
+                        states_differ = true;
+                    }
+                }
+            }
+        }
+
+        let status = match (diverged_at, paused) {
+            (Some(at), true) => format!(" - differ after {} instructions, paused", at),
+            (Some(at), false) => format!(" - differ after {} instructions", at),
+            (None, true) => " - paused".to_string(),
+            (None, false) => String::new(),
+        };
+        let new_title = format!("Chippy - {} | {}{}", left.name, right.name, status);
+        if new_title != title {
+            window.set_title(&new_title);
+            title = new_title;
+        }
+
+        // draw the current frame
+        if let Event::RedrawRequested(_) = event {
+            let mut canvas = Canvas::new(pixels.get_frame(), width, height);
+            canvas.fill_rect(DISPLAY_WIDTH as i32, 0, GAP, height, BACKGROUND);
+
+            let right_x = (DISPLAY_WIDTH + GAP) as i32;
+            left.presenter.draw(&mut canvas, &left.chippy.display_memory, frame, 0, SCALE as u32);
+            right.presenter.draw(&mut canvas, &right.chippy.display_memory, frame, right_x, SCALE as u32);
+
+            if show_checksums {
+                checksum::draw(&mut canvas, DISPLAY_WIDTH as i32, frame, &left.chippy.save_state());
+                checksum::draw(&mut canvas, width as i32, frame, &right.chippy.save_state());
+            }
+
+            if diverged_at.is_some() {
+                let pairs = left.chippy.display_memory.iter().zip(right.chippy.display_memory.iter());
+                for (i, _) in pairs.enumerate().filter(|(_, (left, right))| left != right) {
+                    let x = (i as u32 % chip8::VIDEO_WIDTH * SCALE as u32) as i32;
+                    let y = (i as u32 / chip8::VIDEO_WIDTH * SCALE as u32) as i32;
+                    canvas.fill_rect(x, y, SCALE as u32, SCALE as u32, DIFFERENT);
+                    canvas.fill_rect(right_x + x, y, SCALE as u32, SCALE as u32, DIFFERENT);
+                }
+            }
+
+            if pixels
+                .render()
+                .map_err(|e| eprintln!("pixels.render() failed: {}", e))
+                .is_err()
+            {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+        }
+
+        if input.update(&event) {
+            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            if input.key_pressed(VirtualKeyCode::F5) {
+                paused = !paused;
+            }
+            if input.key_pressed(VirtualKeyCode::K) {
+                show_checksums = !show_checksums;
+            }
+            if let Some(size) = input.window_resized() {
+                pixels.resize_surface(size.width, size.height);
+            }
+
+            // both machines get the same keys
+            keymap::update_keypad(&input, &keys, &mut left.chippy.keypad);
+            keymap::update_keypad(&input, &keys, &mut right.chippy.keypad);
+        }
+
+        if frames > 0 {
+            window.request_redraw();
+        }
+        *control_flow = ControlFlow::WaitUntil(scheduler.next_frame());
+    });
+}
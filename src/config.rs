@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+
+use serde::de::{ self, IntoDeserializer };
+use serde::{ Deserialize, Deserializer };
+
+use winit::event::VirtualKeyCode;
+
+use chippy::chip8::{ MemoryPolicy, OpcodePolicy };
+use chippy::platform::Platform;
+use chippy::quirks::Quirks;
+
+use crate::keymap;
+use crate::present::{ DisplayFilter, Palette };
+
+/// The path the configuration is read from, relative to the working directory.
+pub const CONFIG_PATH: &str = "chippy.toml";
+
+/// User settings, read from a TOML file. Every field is optional:
+/// ```toml
+/// volume = 0.5            # from 0 to 1
+/// audio_latency_ms = 40    # how far the sound runs behind
+/// display_filter = "blend" # none or blend
+/// blend_weight = 0.6       # how much of the current frame a blend is
+/// pixel_grid = true        # lines between the pixels
+/// border = true            # a border around the display
+/// border_width = 16        # in frame pixels, the display shrinks to fit
+/// border_color = "#1e1e28"
+/// palette = "octo"         # chippy, octo, lcd, hotdog, gray, cga0 or cga1
+/// shader = "crt"           # a post-process shader: crt, scanlines or one in shaders/
+/// fullscreen = true        # borderless, on the monitor below or the last one used
+/// monitor = "HDMI"         # a number from 0, or part of the monitor's name
+/// vsync = false            # draw without waiting for the display's refresh
+/// memory_policy = "wrap"   # wrap, ignore or pause
+/// opcode_policy = "skip"   # halt, skip or nop
+/// platform = "schip"       # chip8, schip or xochip, which sets the default speed
+/// instructions_per_frame = 15 # the speed, whatever the platform
+/// assembler = "octo {source} {rom}" # for chippy dev watch
+///
+/// [quirks]
+/// fx0a_on_press = true
+/// battery_ram = { start = 0xE00, end = 0xFFF } # kept between runs
+/// sprites_per_frame = 1   # DXYN waits for the next frame past this many
+/// random = "vip"         # prng, vip or sequence, where CXKK's numbers come from
+///
+/// # the second player's keys, instead of the numeric keypad
+/// [player_two]
+/// Up = 0x2
+/// Down = 0x8
+///
+/// # or colors of your own, for unlit pixels and those lit on the first,
+/// # the second and both XO-CHIP planes, as in Octo
+/// [palette]
+/// background = "#996600"
+/// fill = "#ffcc00"
+/// fill2 = "#ff6600"
+/// blend = "#662200"
+///
+/// # quirk profiles of your own, next to the built-in ones
+/// [profiles.mine]
+/// fx1e_overflow_vf = true
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub volume: f32,
+    pub audio_latency_ms: u64,
+    pub display_filter: DisplayFilter,
+    pub blend_weight: f32,
+    pub pixel_grid: bool,
+    pub border: bool,
+    pub border_width: u32,
+    #[serde(deserialize_with = "color")]
+    pub border_color: [u8; 4],
+    #[serde(deserialize_with = "palette")]
+    pub palette: Palette,
+    pub shader: Option<String>,
+    pub fullscreen: bool,
+    pub monitor: Option<String>,
+    pub vsync: bool,
+    pub quirks: Quirks,
+    pub memory_policy: MemoryPolicy,
+    pub opcode_policy: OpcodePolicy,
+    pub platform: Platform,
+    pub instructions_per_frame: Option<u32>,
+    /// The command `chippy dev watch` assembles with, see `Watch`.
+    pub assembler: Option<String>,
+    pub profiles: HashMap<String, Quirks>,
+    /// Keyboard keys and the keypad keys they press, for a second player.
+    #[serde(deserialize_with = "keys")]
+    pub player_two: HashMap<VirtualKeyCode, u8>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            volume: 0.5,
+            audio_latency_ms: 40,
+            display_filter: DisplayFilter::default(),
+            blend_weight: 0.5,
+            pixel_grid: false,
+            border: false,
+            border_width: 16,
+            border_color: [0x1E, 0x1E, 0x28, 0xFF],
+            palette: Palette::default(),
+            shader: None,
+            fullscreen: false,
+            monitor: None,
+            vsync: true,
+            quirks: Quirks::default(),
+            memory_policy: MemoryPolicy::default(),
+            opcode_policy: OpcodePolicy::default(),
+            platform: Platform::default(),
+            instructions_per_frame: None,
+            assembler: None,
+            profiles: HashMap::new(),
+            player_two: keymap::PLAYER_TWO.iter().map(|&(key, pad)| (key, pad as u8)).collect(),
+        }
+    }
+}
+
+/// Reads a color written like `"#1e1e28"`.
+pub fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 4], D::Error> {
+    let text = String::deserialize(deserializer)?;
+    let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii());
+    let channel = |i: usize| hex.and_then(|hex| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok());
+    match (channel(0), channel(1), channel(2)) {
+        (Some(red), Some(green), Some(blue)) => Ok([red, green, blue, 0xFF]),
+        _ => Err(de::Error::custom(format!("{:?} isn't a color, expected one like \"#1e1e28\"", text))),
+    }
+}
+
+/// Reads a palette by name, or a table of colors for the ones to change.
+fn palette<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Palette, D::Error> {
+    match toml::Value::deserialize(deserializer)? {
+        toml::Value::String(name) => Palette::named(&name).ok_or_else(|| {
+            let names: Vec<&str> = Palette::NAMED.iter().map(|&(name, _)| name).collect();
+            de::Error::custom(format!("there's no palette called {:?}, expected {} or a table of colors",
+                name, names.join(", ")))
+        }),
+        colors => Palette::deserialize(colors).map_err(de::Error::custom),
+    }
+}
+
+/// Reads a table of keyboard keys, by their names in winit, and keypad keys.
+fn keys<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<VirtualKeyCode, u8>, D::Error> {
+    let names = HashMap::<String, u8>::deserialize(deserializer)?;
+    names.into_iter()
+        .map(|(name, pad)| {
+            let key = VirtualKeyCode::deserialize(name.as_str().into_deserializer())
+                .map_err(|_: de::value::Error| de::Error::custom(format!("there's no key called {:?}", name)))?;
+            if pad > 0xF {
+                return Err(de::Error::custom(format!("{} can't press keypad key {:#x}, keys go from 0 to f", name, pad)));
+            }
+            Ok((key, pad))
+        })
+        .collect()
+}
+
+impl Config {
+    /// Loads the configuration at `path`, or the defaults if there's no such file.
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(toml::from_str(&contents)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// A quirk profile from the config, or a built-in one.
+    pub fn profile(&self, name: &str) -> Option<Quirks> {
+        self.profiles.get(name).copied().or_else(|| Quirks::profile(name))
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{ self, BufRead, BufReader, Read, Write };
+use std::net::{ Ipv4Addr, SocketAddr, TcpListener, TcpStream };
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::thread;
+use std::time::Duration;
+
+use serde_json::json;
+
+use chippy::chip8::Chip8;
+use chippy::disasm;
+use chippy::state::MachineState;
+
+use crate::present::Palette;
+use crate::remote;
+use crate::screenshot;
+
+/// How many instructions `/disasm` shows when it isn't told.
+const DISASM_LINES: usize = 16;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// The most of a request that's read, the request line and headers
+/// together. Anything longer is refused.
+const MAX_REQUEST: u64 = 8 * 1024;
+
+/// How long a client gets to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A plain HTTP server on localhost for poking at the machine from a
+/// browser or curl during development:
+/// - `/registers`: the registers, timers and stack as JSON,
+/// - `/memory?start=..&len=..`: a hex dump, all of memory by default,
+/// - `/framebuffer.png?scale=..`: the display,
+/// - `/disasm?addr=..&count=..`: disassembly, from the program counter
+///   by default.
+///
+/// Numbers can be given in decimal or as hex with `0x`. Every request is
+/// answered from a snapshot of the machine taken by `serve`, one at a
+/// time. Requests naming a host other than this machine are refused, so
+/// a page elsewhere can't read the machine through a rebound name.
+pub struct DebugServer {
+    snapshots: Receiver<Sender<MachineState>>,
+    address: SocketAddr,
+}
+
+impl DebugServer {
+    /// Starts serving on `port`, with the framebuffer in `palette`.
+    pub fn start(port: u16, palette: Palette) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let address = listener.local_addr()?;
+        let (sender, snapshots) = mpsc::channel();
+
+        // a client that stalls only holds the others up until its read
+        // times out.
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = handle(stream, &sender, &palette);
+            }
+        });
+
+        Ok(Self { snapshots, address })
+    }
+
+    /// The address the server answers on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Answers the requests made since the last call.
+    pub fn serve(&self, chippy: &Chip8) {
+        for snapshot in self.snapshots.try_iter() {
+            let _ = snapshot.send(chippy.save_state());
+        }
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self { status: "200 OK", content_type, body: body.into() }
+    }
+
+    fn error(status: &'static str, message: impl Into<String>) -> Self {
+        Self { status, content_type: "text/plain", body: (message.into() + "\n").into_bytes() }
+    }
+}
+
+fn handle(mut stream: TcpStream, snapshots: &Sender<Sender<MachineState>>, palette: &Palette) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST));
+    reader.read_line(&mut request_line)?;
+
+    // only the host matters of the headers, but they all have to be read.
+    let mut host = None;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_string());
+            }
+        }
+        header.clear();
+    }
+    let too_long = reader.get_ref().limit() == 0;
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, parse_query(query)),
+        None => (target, HashMap::new()),
+    };
+
+    let response = if too_long {
+        Response::error("431 Request Header Fields Too Large", "the request is too long")
+    } else if !host.as_deref().is_some_and(remote::is_local) {
+        Response::error("403 Forbidden", "only requests for localhost are answered")
+    } else {
+        match take_snapshot(snapshots) {
+            Some(state) => respond(path, &query, &state, palette)
+                .unwrap_or_else(|message| Response::error("400 Bad Request", message)),
+            None => Response::error("503 Service Unavailable", "the emulator has stopped"),
+        }
+    };
+
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, response.content_type, response.body.len())?;
+    stream.write_all(&response.body)?;
+
+    Ok(())
+}
+
+fn take_snapshot(snapshots: &Sender<Sender<MachineState>>) -> Option<MachineState> {
+    let (sender, snapshot) = mpsc::channel();
+    snapshots.send(sender).ok()?;
+    snapshot.recv().ok()
+}
+
+fn respond(path: &str, query: &HashMap<&str, &str>, state: &MachineState, palette: &Palette) -> Result<Response, String> {
+    let response = match path {
+        "/registers" => {
+            let registers = json!({
+                "program_counter": state.program_counter,
+                "index": state.index,
+                "registers": state.registers,
+                "delay_timer": state.delay_timer,
+                "sound_timer": state.sound_timer,
+                "stack": &state.stack[..state.stack_pointer as usize],
+            });
+            Response::ok("application/json", registers.to_string())
+        },
+        "/memory" => {
+            let start = number(query, "start")?.unwrap_or(0);
+            let length = number(query, "len")?.unwrap_or(state.memory.len());
+            let memory = start.checked_add(length)
+                .and_then(|end| state.memory.get(start..end))
+                .ok_or_else(|| format!("{} bytes from {:#05x} runs past the end of memory", length, start))?;
+
+            Response::ok("text/plain", hex_dump(start, memory))
+        },
+        "/framebuffer.png" => {
+            let scale = number(query, "scale")?.unwrap_or(1);
+            if !(1..=32).contains(&scale) {
+                return Err("the scale goes from 1 to 32".to_string());
+            }
+
+            Response::ok("image/png", screenshot::encode_png(&state.display, palette, scale as u32))
+        },
+        "/disasm" => {
+            let start = number(query, "addr")?.unwrap_or(state.program_counter as usize);
+            let count = number(query, "count")?.unwrap_or(DISASM_LINES);
+
+            Response::ok("text/plain", disassembly(state, start, count))
+        },
+        _ => Response::error("404 Not Found",
+            "try /registers, /memory?start=..&len=.., /framebuffer.png or /disasm?addr=.."),
+    };
+
+    Ok(response)
+}
+
+fn parse_query(query: &str) -> HashMap<&str, &str> {
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect()
+}
+
+/// Reads a number parameter in decimal or hex.
+fn number(query: &HashMap<&str, &str>, name: &str) -> Result<Option<usize>, String> {
+    let value = match query.get(name) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map(Some).map_err(|_| format!("{} should be a number, not {:?}", name, value))
+}
+
+fn hex_dump(start: usize, memory: &[u8]) -> String {
+    memory.chunks(BYTES_PER_LINE).enumerate()
+        .map(|(line, bytes)| {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            format!("{:03X}: {}\n", start + line * BYTES_PER_LINE, bytes.join(" "))
+        })
+        .collect()
+}
+
+/// Disassembles `count` instructions from `start`, pointing out the
+/// program counter.
+fn disassembly(state: &MachineState, start: usize, count: usize) -> String {
+    (start..state.memory.len() - 1).step_by(2).take(count)
+        .map(|address| {
+            let opcode = (state.memory[address] as u16) << 8 | state.memory[address + 1] as u16;
+            let marker = if address == state.program_counter as usize { '>' } else { ' ' };
+            format!("{} {:03X}  {:04X}  {}\n", marker, address, opcode, disasm::disassemble(opcode))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(address: SocketAddr, target: &str) -> thread::JoinHandle<String> {
+        send(address, format!("GET {} HTTP/1.1\r\nHost: localhost:{}\r\n\r\n", target, address.port()))
+    }
+
+    fn send(address: SocketAddr, request: String) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    }
+
+    fn answer(server: &DebugServer, chippy: &Chip8, request: thread::JoinHandle<String>) -> String {
+        while !request.is_finished() {
+            server.serve(chippy);
+        }
+        request.join().unwrap()
+    }
+
+    #[test]
+    fn endpoints_answer_from_the_machine() {
+        let server = DebugServer::start(0, Palette::default()).unwrap();
+        let mut chippy = Chip8::new();
+        let mut state = chippy.save_state();
+        state.memory[0x200..0x204].copy_from_slice(&[0x00, 0xE0, 0x12, 0x00]);
+        chippy.load_state(&state);
+
+        let memory = answer(&server, &chippy, get(server.address(), "/memory?start=0x200&len=4"));
+        assert!(memory.ends_with("\r\n\r\n200: 00 E0 12 00\n"), "{}", memory);
+
+        let disasm = answer(&server, &chippy, get(server.address(), "/disasm?count=2"));
+        assert!(disasm.ends_with("> 200  00E0  CLS\n  202  1200  JP 0x200\n"), "{}", disasm);
+
+        let registers = answer(&server, &chippy, get(server.address(), "/registers"));
+        assert!(registers.contains("\"program_counter\":512"), "{}", registers);
+    }
+
+    #[test]
+    fn bad_requests_are_refused() {
+        let server = DebugServer::start(0, Palette::default()).unwrap();
+        let chippy = Chip8::new();
+
+        let response = answer(&server, &chippy, get(server.address(), "/memory?start=4095&len=2"));
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+
+        let response = answer(&server, &chippy, get(server.address(), "/nowhere"));
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+    }
+
+    #[test]
+    fn other_hosts_and_long_requests_are_refused() {
+        let server = DebugServer::start(0, Palette::default()).unwrap();
+        let chippy = Chip8::new();
+
+        let rebound = "GET /registers HTTP/1.1\r\nHost: attacker.example:8066\r\n\r\n".to_string();
+        let response = answer(&server, &chippy, send(server.address(), rebound));
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+
+        let nameless = "GET /registers HTTP/1.0\r\n\r\n".to_string();
+        let response = answer(&server, &chippy, send(server.address(), nameless));
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+
+        // as much as is read, with the headers still going.
+        let mut long = "GET /registers HTTP/1.1\r\nHost: localhost\r\nX: ".to_string();
+        long += &"a".repeat(MAX_REQUEST as usize - long.len());
+        let response = answer(&server, &chippy, send(server.address(), long));
+        assert!(response.starts_with("HTTP/1.1 431"), "{}", response);
+    }
+}
@@ -0,0 +1,186 @@
+use winit::dpi::{ LogicalSize, PhysicalPosition };
+use winit::event::{ ElementState, Event, MouseButton, WindowEvent };
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::{ Window, WindowBuilder, WindowId };
+
+use pixels::Pixels;
+
+use crate::canvas::Canvas;
+use crate::debugger::Debugger;
+use crate::placement::Placement;
+use crate::{ disasm_view, machine_view, timer_scope };
+
+/// A panel of the debugger that can have a window of its own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Panel {
+    Disassembly,
+    Timers,
+    Registers,
+    /// Memory from I, and the sprite there.
+    Memory,
+}
+
+impl Panel {
+    pub const ALL: [Panel; 4] = [Panel::Disassembly, Panel::Timers, Panel::Registers, Panel::Memory];
+
+    /// What the panel's called, in its title and in the placement file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Panel::Disassembly => "disassembly",
+            Panel::Timers => "timers",
+            Panel::Registers => "registers",
+            Panel::Memory => "memory",
+        }
+    }
+
+    /// The size of the panel's frame, in pixels.
+    pub fn size(self) -> (u32, u32) {
+        match self {
+            Panel::Disassembly => (disasm_view::PANEL_WIDTH, crate::DISPLAY_HEIGHT + timer_scope::SCOPE_HEIGHT),
+            Panel::Timers => (crate::DISPLAY_WIDTH, timer_scope::SCOPE_HEIGHT),
+            Panel::Registers => machine_view::REGISTERS_SIZE,
+            Panel::Memory => machine_view::MEMORY_SIZE,
+        }
+    }
+}
+
+/// What happened in a panel's window that the debugger wants to know.
+pub enum PanelEvent {
+    /// Nothing the rest of the emulator needs to see.
+    Handled,
+    /// A click, at a pixel of the panel's frame.
+    Clicked(Panel, (usize, usize)),
+}
+
+struct PanelWindow {
+    panel: Panel,
+    // declared before the window, so the surface goes first.
+    pixels: Pixels,
+    window: Window,
+    // where the mouse is over the window, in physical pixels.
+    cursor: Option<(f32, f32)>,
+}
+
+/// The debugger's panels in windows of their own, so they don't make
+/// the game's window bigger. The windows are open while the debugger is
+/// showing, and go back where they were last time (see `Placement`).
+pub struct DebugWindows {
+    /// Whether the panels go in windows of their own, rather than next to
+    /// and under the display.
+    pub separate: bool,
+    windows: Vec<PanelWindow>,
+}
+
+impl DebugWindows {
+    pub fn new(separate: bool) -> Self {
+        Self { separate, windows: Vec::new() }
+    }
+
+    /// Whether the debugger's panels are in the game's window.
+    pub fn docked(&self, debugger: &Debugger) -> bool {
+        debugger.visible && !self.separate
+    }
+
+    /// Opens the windows when the debugger shows in them, and closes them
+    /// when it doesn't, remembering where they were.
+    pub fn update(&mut self, target: &EventLoopWindowTarget<()>, debugger: &Debugger, placement: &mut Placement, vsync: bool) {
+        let open = debugger.visible && self.separate;
+        if open && self.windows.is_empty() {
+            for &panel in Panel::ALL.iter() {
+                let (width, height) = panel.size();
+                let window = WindowBuilder::new()
+                    .with_title(format!("Chippy - {}", panel.name()))
+                    .with_resizable(false)
+                    .with_inner_size(LogicalSize::new(width, height))
+                    .build(target);
+                let window = match window {
+                    Ok(window) => window,
+                    Err(e) => {
+                        eprintln!("chippy: failed to open the {} window: {}", panel.name(), e);
+                        continue;
+                    },
+                };
+                if let Some(&(x, y)) = placement.panels.get(panel.name()) {
+                    window.set_outer_position(PhysicalPosition::new(x, y));
+                }
+
+                let pixels = crate::create_pixels(&window, (width, height), vsync);
+                self.windows.push(PanelWindow { panel, pixels, window, cursor: None });
+            }
+        } else if !open && !self.windows.is_empty() {
+            self.remember(placement);
+            self.windows.clear();
+        }
+    }
+
+    /// Keeps where the open windows are, for next time.
+    pub fn remember(&self, placement: &mut Placement) {
+        placement.separate_panels = self.separate;
+        for panel in &self.windows {
+            if let Ok(position) = panel.window.outer_position() {
+                placement.panels.insert(panel.panel.name().to_string(), (position.x, position.y));
+            }
+        }
+    }
+
+    /// Handles an event if it's for one of the panels' windows. Keys
+    /// pressed in them are left for the emulator, so the debugger's keys
+    /// work from any window.
+    pub fn handle(&mut self, event: &Event<()>) -> Option<PanelEvent> {
+        let (id, event) = match event {
+            Event::WindowEvent { window_id, event } => (*window_id, event),
+            _ => return None,
+        };
+        let panel = self.windows.iter_mut().find(|panel| panel.window.id() == id)?;
+
+        match event {
+            WindowEvent::KeyboardInput { .. } | WindowEvent::ModifiersChanged(_) | WindowEvent::ReceivedCharacter(_) => {
+                return None;
+            },
+            WindowEvent::CloseRequested => {
+                // closing any of them goes back to the panels next to the display.
+                self.separate = false;
+            },
+            WindowEvent::CursorMoved { position, .. } => panel.cursor = Some((position.x as f32, position.y as f32)),
+            WindowEvent::CursorLeft { .. } => panel.cursor = None,
+            WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. } => {
+                let pixel = panel.cursor.and_then(|cursor| panel.pixels.window_pos_to_pixel(cursor).ok());
+                if let Some(pixel) = pixel {
+                    return Some(PanelEvent::Clicked(panel.panel, pixel));
+                }
+            },
+            WindowEvent::Resized(size) => panel.pixels.resize_surface(size.width, size.height),
+            _ => (),
+        }
+
+        Some(PanelEvent::Handled)
+    }
+
+    /// The pixel of a panel's frame the mouse is over, if any.
+    pub fn cursor(&self, panel: Panel) -> Option<(usize, usize)> {
+        let window = self.windows.iter().find(|window| window.panel == panel)?;
+        window.cursor.and_then(|cursor| window.pixels.window_pos_to_pixel(cursor).ok())
+    }
+
+    pub fn request_redraw(&self) {
+        for panel in &self.windows {
+            panel.window.request_redraw();
+        }
+    }
+
+    /// Draws a panel's window with `draw`, if `id` is one of them.
+    /// Returns whether it was.
+    pub fn redraw(&mut self, id: WindowId, draw: impl FnOnce(Panel, &mut Canvas)) -> bool {
+        let panel = match self.windows.iter_mut().find(|panel| panel.window.id() == id) {
+            Some(panel) => panel,
+            None => return false,
+        };
+
+        let (width, height) = panel.panel.size();
+        draw(panel.panel, &mut Canvas::new(panel.pixels.get_frame(), width, height));
+        if let Err(e) = panel.pixels.render() {
+            eprintln!("chippy: failed to draw the {} window: {}", panel.panel.name(), e);
+        }
+        true
+    }
+}
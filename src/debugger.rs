@@ -0,0 +1,172 @@
+use std::collections::{ BTreeSet, VecDeque };
+
+use chippy::chip8::CodeWrite;
+use chippy::trap::Trap;
+
+/// How many of the most recent branches are remembered for the
+/// disassembly view.
+pub const RECENT_BRANCHES: usize = 4;
+
+/// How many of the most recent instructions are remembered, for bug
+/// reports.
+pub const TRACE_LENGTH: usize = 256;
+
+/// A jump, call, return or skip taken by the program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Branch {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Keeps track of whether emulation is paused, and why, along with
+/// breakpoints and what the program has been up to.
+pub struct Debugger {
+    pub paused: bool,
+    pub visible: bool,
+    trap: Option<Trap>,
+    modified_code: BTreeSet<usize>,
+    breakpoints: BTreeSet<usize>,
+    branches: VecDeque<Branch>,
+    // the address and opcode of the latest instructions, oldest first.
+    trace: VecDeque<(usize, u16)>,
+    step_requested: bool,
+    // the breakpoint we stopped at, so resuming doesn't stop there again.
+    stopped_at: Option<usize>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            paused: false,
+            visible: false,
+            trap: None,
+            modified_code: BTreeSet::new(),
+            breakpoints: BTreeSet::new(),
+            branches: VecDeque::with_capacity(RECENT_BRANCHES),
+            trace: VecDeque::with_capacity(TRACE_LENGTH),
+            step_requested: false,
+            stopped_at: None,
+        }
+    }
+
+    /// Pauses on a trap raised by the core and prints a diagnostic.
+    pub fn report(&mut self, trap: Trap) {
+        eprintln!("chippy: paused: {}", trap);
+
+        self.paused = true;
+        self.trap = Some(trap);
+    }
+
+    /// Whether the instruction at `pc` should run now, either because
+    /// emulation is running or because a single step was requested.
+    /// Stops the program if there's a breakpoint at `pc`.
+    pub fn should_run(&mut self, pc: usize) -> bool {
+        if self.paused && !std::mem::take(&mut self.step_requested) {
+            return false;
+        }
+
+        if self.breakpoints.contains(&pc) && self.stopped_at != Some(pc) {
+            self.stopped_at = Some(pc);
+            self.report(Trap::Breakpoint { pc });
+            return false;
+        }
+
+        true
+    }
+
+    /// Records that the instruction `opcode` at `from` ran and left the
+    /// program counter at `to`.
+    pub fn record_step(&mut self, from: usize, opcode: u16, to: usize) {
+        self.stopped_at = None;
+
+        if self.trace.len() == TRACE_LENGTH {
+            self.trace.pop_front();
+        }
+        self.trace.push_back((from, opcode));
+
+        if to != from + 2 {
+            if self.branches.len() == RECENT_BRANCHES {
+                self.branches.pop_back();
+            }
+            self.branches.push_front(Branch { from, to });
+        }
+    }
+
+    /// The address and opcode of the most recent instructions, oldest first.
+    pub fn trace(&self) -> impl Iterator<Item = &(usize, u16)> {
+        self.trace.iter()
+    }
+
+    /// The most recent branches, newest first.
+    pub fn recent_branches(&self) -> impl Iterator<Item = &Branch> {
+        self.branches.iter()
+    }
+
+    /// Flags code the program has written over, printing a note the
+    /// first time each address is modified.
+    pub fn note_code_writes(&mut self, writes: Vec<CodeWrite>) {
+        for write in writes {
+            if self.modified_code.insert(write.address) {
+                eprintln!("chippy: self-modifying write to {:#05x} (pc {:#05x})",
+                    write.address, write.pc);
+            }
+        }
+    }
+
+    /// Whether the program has overwritten any of the bytes of the
+    /// instruction at `address`.
+    pub fn is_modified_code(&self, address: usize) -> bool {
+        self.modified_code.contains(&address) || self.modified_code.contains(&(address + 1))
+    }
+
+    pub fn toggle_breakpoint(&mut self, address: usize) {
+        if !self.breakpoints.remove(&address) {
+            self.breakpoints.insert(address);
+        }
+    }
+
+    /// The addresses with breakpoints, lowest first.
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn has_breakpoint(&self, address: usize) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+
+        // resuming acknowledges whatever trap caused the pause.
+        self.trap = None;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Runs a single instruction while paused.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.trap = None;
+            self.step_requested = true;
+        }
+    }
+
+    /// A one-line summary of the debugger state, for the window title.
+    pub fn status(&self) -> Option<String> {
+        match (&self.trap, self.paused) {
+            (Some(trap), _) => Some(format!("paused: {}", trap)),
+            (None, true) => Some("paused".to_string()),
+            (None, false) => None,
+        }
+    }
+}
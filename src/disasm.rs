@@ -0,0 +1,78 @@
+use alloc::format;
+use alloc::string::{ String, ToString };
+
+use crate::opcode::{ self, Op };
+
+/// Turns an opcode into its mnemonic, e.g. `LD VA, 0x05`. Opcodes that
+/// don't decode are shown as a data word.
+pub fn disassemble(opcode: u16) -> String {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let kk = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+
+    let op = match opcode::decode(opcode) {
+        Some(op) => op,
+        None => return format!("DW {:#06X}", opcode),
+    };
+
+    match op {
+        Op::Cls => "CLS".to_string(),
+        Op::Ret => "RET".to_string(),
+        Op::Jp => format!("JP {:#05X}", nnn),
+        Op::Call => format!("CALL {:#05X}", nnn),
+        Op::SeVxByte => format!("SE V{:X}, {:#04X}", x, kk),
+        Op::SneVxByte => format!("SNE V{:X}, {:#04X}", x, kk),
+        Op::SeVxVy => format!("SE V{:X}, V{:X}", x, y),
+        Op::LdVxByte => format!("LD V{:X}, {:#04X}", x, kk),
+        Op::AddVxByte => format!("ADD V{:X}, {:#04X}", x, kk),
+        Op::LdVxVy => format!("LD V{:X}, V{:X}", x, y),
+        Op::Or => format!("OR V{:X}, V{:X}", x, y),
+        Op::And => format!("AND V{:X}, V{:X}", x, y),
+        Op::Xor => format!("XOR V{:X}, V{:X}", x, y),
+        Op::AddVxVy => format!("ADD V{:X}, V{:X}", x, y),
+        Op::Sub => format!("SUB V{:X}, V{:X}", x, y),
+        Op::Shr => format!("SHR V{:X}", x),
+        Op::Subn => format!("SUBN V{:X}, V{:X}", x, y),
+        Op::Shl => format!("SHL V{:X}", x),
+        Op::SneVxVy => format!("SNE V{:X}, V{:X}", x, y),
+        Op::LdI => format!("LD I, {:#05X}", nnn),
+        Op::JpV0 => format!("JP V0, {:#05X}", nnn),
+        Op::Rnd => format!("RND V{:X}, {:#04X}", x, kk),
+        Op::Drw => format!("DRW V{:X}, V{:X}, {:X}", x, y, n),
+        Op::Skp => format!("SKP V{:X}", x),
+        Op::Sknp => format!("SKNP V{:X}", x),
+        Op::Plane => format!("PLANE {:X}", x),
+        Op::Audio => "AUDIO".to_string(),
+        Op::LdVxDt => format!("LD V{:X}, DT", x),
+        Op::LdVxK => format!("LD V{:X}, K", x),
+        Op::LdDtVx => format!("LD DT, V{:X}", x),
+        Op::LdStVx => format!("LD ST, V{:X}", x),
+        Op::AddIVx => format!("ADD I, V{:X}", x),
+        Op::LdFVx => format!("LD F, V{:X}", x),
+        Op::LdBVx => format!("LD B, V{:X}", x),
+        Op::Pitch => format!("PITCH V{:X}", x),
+        Op::LdIVx => format!("LD [I], V{:X}", x),
+        Op::LdVxI => format!("LD V{:X}, [I]", x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_operands() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x12A4), "JP 0x2A4");
+        assert_eq!(disassemble(0x6A05), "LD VA, 0x05");
+        assert_eq!(disassemble(0xD12F), "DRW V1, V2, F");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0xF301), "PLANE 3");
+        assert_eq!(disassemble(0xF002), "AUDIO");
+        assert_eq!(disassemble(0xF102), "DW 0xF102");
+        assert_eq!(disassemble(0xF53A), "PITCH V5");
+        assert_eq!(disassemble(0xE1FF), "DW 0xE1FF");
+    }
+}
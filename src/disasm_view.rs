@@ -0,0 +1,141 @@
+use chippy::chip8::Chip8;
+use chippy::disasm;
+
+use crate::canvas::{ self, Canvas };
+use crate::debugger::{ Debugger, RECENT_BRANCHES };
+
+/// Width of the disassembly panel, in frame pixels.
+pub const PANEL_WIDTH: u32 = 360;
+
+const TEXT_SCALE: u32 = 2;
+const GUTTER_WIDTH: i32 = 24;
+const MARKER_WIDTH: i32 = 16;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const PC_HIGHLIGHT: [u8; 4] = [0x3C, 0x3C, 0x64, 0xFF];
+const BREAKPOINT: [u8; 4] = [0xE8, 0x48, 0x48, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+const NOT_EXECUTED: [u8; 4] = [0x80, 0x80, 0x90, 0xFF];
+const MODIFIED: [u8; 4] = [0xE8, 0xA0, 0x48, 0xFF];
+const ARROWS: [[u8; 4]; RECENT_BRANCHES] = [
+    [0x48, 0xB2, 0xE8, 0xFF],
+    [0x40, 0x90, 0xB8, 0xFF],
+    [0x38, 0x70, 0x90, 0xFF],
+    [0x30, 0x50, 0x68, 0xFF],
+];
+
+/// A scrolling disassembly centered on the program counter. Lines
+/// flagged with `*` have been overwritten by the program, dim lines
+/// haven't been executed (yet).
+pub struct DisassemblyView {
+    x: i32,
+    height: u32,
+    top: usize,
+}
+
+impl DisassemblyView {
+    /// A view drawn at `x`, filling `height` pixels of the frame.
+    pub fn new(x: i32, height: u32) -> Self {
+        Self { x, height, top: 0 }
+    }
+
+    /// Moves the view to `x`, as the panels move in and out of the window.
+    pub fn move_to(&mut self, x: i32) {
+        self.x = x;
+    }
+
+    fn lines(&self) -> usize {
+        (self.height / canvas::line_height(TEXT_SCALE)) as usize
+    }
+
+    fn line_y(&self, line: usize) -> i32 {
+        (line as u32 * canvas::line_height(TEXT_SCALE)) as i32
+    }
+
+    /// The line showing `address`, if it's in view.
+    fn line_of(&self, address: usize) -> Option<usize> {
+        if address < self.top {
+            return None;
+        }
+
+        let line = (address - self.top) / 2;
+        if line < self.lines() { Some(line) } else { None }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, chippy: &Chip8, debugger: &Debugger) {
+        let pc = chippy.program_counter();
+
+        // keep the program counter in the middle of the view.
+        self.top = pc.saturating_sub(self.lines() / 2 * 2);
+
+        canvas.fill_rect(self.x, 0, PANEL_WIDTH, self.height, BACKGROUND);
+
+        for line in 0..self.lines() {
+            let address = self.top + line * 2;
+            let y = self.line_y(line);
+
+            if address == pc {
+                canvas.fill_rect(self.x, y, PANEL_WIDTH, canvas::line_height(TEXT_SCALE), PC_HIGHLIGHT);
+            }
+
+            if debugger.has_breakpoint(address) {
+                canvas.fill_rect(self.x + GUTTER_WIDTH + 2, y + 3, 10, 10, BREAKPOINT);
+            }
+
+            let opcode = ((chippy.peek(address) as u16) << 8) | chippy.peek(address + 1) as u16;
+            let modified = debugger.is_modified_code(address);
+            let text = format!("{:03X} {:04X} {}{}",
+                address, opcode, disasm::disassemble(opcode), if modified { " *" } else { "" });
+
+            let color = if modified {
+                MODIFIED
+            } else if chippy.is_executed(address) {
+                TEXT
+            } else {
+                NOT_EXECUTED
+            };
+
+            canvas.draw_text(self.x + GUTTER_WIDTH + MARKER_WIDTH, y + 2, &text, TEXT_SCALE, color);
+        }
+
+        self.draw_branches(canvas, debugger);
+    }
+
+    /// Draws an arrow in the gutter for each recent branch that's in
+    /// view, most recent on the inside.
+    fn draw_branches(&self, canvas: &mut Canvas, debugger: &Debugger) {
+        let half_line = canvas::line_height(TEXT_SCALE) as i32 / 2;
+        let right = self.x + GUTTER_WIDTH - 2;
+
+        for (age, branch) in debugger.recent_branches().enumerate() {
+            let (from, to) = match (self.line_of(branch.from), self.line_of(branch.to)) {
+                (Some(from), Some(to)) => (from, to),
+                _ => continue,
+            };
+
+            let color = ARROWS[age];
+            let left = right - 6 - age as i32 * 4;
+            let from_y = self.line_y(from) + half_line;
+            let to_y = self.line_y(to) + half_line;
+
+            canvas.draw_line((right, from_y), (left, from_y), color);
+            canvas.draw_line((left, from_y), (left, to_y), color);
+            canvas.draw_line((left, to_y), (right, to_y), color);
+
+            // arrowhead pointing at the target
+            canvas.draw_line((right - 1, to_y - 1), (right - 1, to_y + 1), color);
+            canvas.draw_line((right - 2, to_y - 2), (right - 2, to_y + 2), color);
+        }
+    }
+
+    /// The address of the line at a point in the frame, for toggling
+    /// breakpoints with the mouse.
+    pub fn address_at(&self, x: usize, y: usize) -> Option<usize> {
+        if (x as i32) < self.x {
+            return None;
+        }
+
+        let line = y / canvas::line_height(TEXT_SCALE) as usize;
+        if line < self.lines() { Some(self.top + line * 2) } else { None }
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt::Write;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use chippy::chip8::Chip8;
+use chippy::disasm;
+
+/// Where the font and programs start in memory, for the annotations.
+const FONT_START: usize = 0x50;
+const PROGRAM_START: usize = 0x200;
+
+/// How many bytes of memory go on a line of the hex dump.
+const BYTES_PER_LINE: usize = 16;
+
+/// How many instructions either side of the program counter are
+/// disassembled.
+const CODE_CONTEXT: usize = 8;
+
+/// Dumps the whole machine as text, for reading offline: `reason` says
+/// why, then come the registers, the stack, the code around the program
+/// counter and all of memory as hex, with the lines holding the font,
+/// the start of the program, the program counter and I pointed out.
+pub fn dump(chippy: &Chip8, reason: &str) -> String {
+    let state = chippy.save_state();
+    let pc = state.program_counter as usize;
+    let mut text = String::new();
+
+    // writing to a String can't fail.
+    let _ = writeln!(text, "Chippy dump: {}", reason);
+    let _ = writeln!(text);
+    let _ = writeln!(text, "PC {:#05x}  I {:#05x}  SP {}  DT {}  ST {}",
+        pc, state.index, state.stack_pointer, state.delay_timer, state.sound_timer);
+    for (row, registers) in state.registers.chunks(8).enumerate() {
+        let registers: Vec<String> = registers.iter().enumerate()
+            .map(|(i, value)| format!("V{:X} {:02x}", row * 8 + i, value))
+            .collect();
+        let _ = writeln!(text, "{}", registers.join("  "));
+    }
+    if let Some(key) = state.awaited_key {
+        let _ = writeln!(text, "key {:X} is down, waiting for its release", key);
+    }
+
+    let _ = writeln!(text);
+    let _ = writeln!(text, "Stack:");
+    if state.stack_pointer == 0 {
+        let _ = writeln!(text, "  empty");
+    }
+    for (level, address) in state.stack.iter().enumerate().take(state.stack_pointer as usize) {
+        let _ = writeln!(text, "  {:2}: {:#05x}", level, address);
+    }
+
+    let _ = writeln!(text);
+    let _ = writeln!(text, "Code:");
+    let first = pc.saturating_sub(CODE_CONTEXT * 2);
+    for address in (first..=pc + CODE_CONTEXT * 2).step_by(2).filter(|&address| address + 1 < state.memory.len()) {
+        let opcode = (state.memory[address] as u16) << 8 | state.memory[address + 1] as u16;
+        let marker = if address == pc { '>' } else { ' ' };
+        let _ = writeln!(text, "{} {:#05x}  {:04X}  {}", marker, address, opcode, disasm::disassemble(opcode));
+    }
+
+    let _ = writeln!(text);
+    let _ = writeln!(text, "Memory:");
+    for (line, bytes) in state.memory.chunks(BYTES_PER_LINE).enumerate() {
+        let start = line * BYTES_PER_LINE;
+        let lines = start..start + BYTES_PER_LINE;
+
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let mut notes = Vec::new();
+        if lines.contains(&FONT_START) {
+            notes.push("font");
+        }
+        if lines.contains(&PROGRAM_START) {
+            notes.push("program");
+        }
+        if lines.contains(&pc) {
+            notes.push("PC");
+        }
+        if lines.contains(&(state.index as usize)) {
+            notes.push("I");
+        }
+
+        let _ = write!(text, "{:#05x}  {}", start, hex.join(" "));
+        if !notes.is_empty() {
+            let _ = write!(text, "  <- {}", notes.join(", "));
+        }
+        let _ = writeln!(text);
+    }
+
+    text
+}
+
+/// The name of a dump made at `time`, e.g. `chippy-dump-20261014-153012.txt`
+/// (in UTC), so dumps sort by when they were made.
+pub fn file_name(time: SystemTime) -> String {
+    format!("chippy-dump-{}.txt", timestamp(time))
+}
+
+/// A time in UTC as `20261014-153012`, for naming files.
+pub fn timestamp(time: SystemTime) -> String {
+    let time = Utc::of(time);
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", time.year, time.month, time.day, time.hour, time.minute, time.second)
+}
+
+/// A time in UTC, to the second.
+pub struct Utc {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
+
+impl Utc {
+    pub fn of(time: SystemTime) -> Self {
+        let seconds = time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+        // days since the epoch to a civil date, after Howard Hinnant's
+        // civil_from_days, with years starting in March.
+        let days = days as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self { year, month, day, hour: seconds / 3600, minute: seconds / 60 % 60, second: seconds % 60 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn dumps_are_named_after_the_time() {
+        assert_eq!(file_name(UNIX_EPOCH), "chippy-dump-19700101-000000.txt");
+        assert_eq!(file_name(UNIX_EPOCH + Duration::from_secs(1_791_991_812)),
+            "chippy-dump-20261014-153012.txt");
+        assert_eq!(file_name(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "chippy-dump-20000229-000000.txt");
+    }
+
+    #[test]
+    fn dumps_point_out_the_program_counter() {
+        let chippy = Chip8::initialize(&[0x00, 0xE0, 0xA2, 0x2A]).unwrap();
+        let text = dump(&chippy, "test");
+
+        assert!(text.contains("> 0x200  00E0  CLS"));
+        assert!(text.contains("0x200  00 e0 a2 2a"));
+        assert!(text.contains("<- program, PC"));
+        assert!(text.contains("<- font"));
+    }
+
+    #[test]
+    fn dumps_say_which_key_fx0a_waits_to_see_released() {
+        // LD V3, K, then key 5 goes down
+        let mut chippy = Chip8::initialize(&[0xF3, 0x0A]).unwrap();
+        chippy.cycle().unwrap();
+        chippy.keypad[5] = true;
+        chippy.cycle().unwrap();
+        let text = dump(&chippy, "test");
+
+        assert!(text.contains("key 5 is down, waiting for its release"));
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::canvas::{ self, Canvas };
+use crate::scheduler::FRAME;
+
+/// Height of the graph, in frame pixels.
+pub const GRAPH_HEIGHT: u32 = 96;
+
+/// How wide each frame's bar is.
+const BAR_WIDTH: u32 = 2;
+
+const TEXT_SCALE: u32 = 2;
+const LEGEND_HEIGHT: u32 = 20;
+const MISSED_HEIGHT: u32 = 4;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const GRID: [u8; 4] = [0x30, 0x30, 0x40, 0xFF];
+const EMULATION: [u8; 4] = [0x48, 0xB2, 0xE8, 0xFF];
+const RENDER: [u8; 4] = [0xE8, 0xA0, 0x48, 0xFF];
+const MISSED: [u8; 4] = [0xE8, 0x48, 0x48, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+
+/// How long a frame took.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct FrameTime {
+    emulation: Duration,
+    render: Duration,
+    // the frame ran late, catching up after it was due.
+    missed: bool,
+}
+
+/// A graph of how long the last few hundred frames took to emulate (in
+/// blue) and to draw (in orange, on top), drawn over the bottom of the
+/// display, with a red mark over frames that ran late. The line across
+/// is a frame's worth of time, so bars past it are frames that couldn't
+/// keep up.
+pub struct FrameGraph {
+    pub visible: bool,
+    y: i32,
+    width: u32,
+    // oldest first.
+    history: VecDeque<FrameTime>,
+}
+
+impl FrameGraph {
+    /// A graph whose bottom edge is at `bottom`, as wide as `width`.
+    pub fn new(bottom: u32, width: u32) -> Self {
+        Self {
+            visible: false,
+            y: (bottom - GRAPH_HEIGHT) as i32,
+            width,
+            history: VecDeque::with_capacity((width / BAR_WIDTH) as usize),
+        }
+    }
+
+    /// Keeps how long a frame took to emulate, and whether it was late.
+    pub fn record_frame(&mut self, emulation: Duration, missed: bool) {
+        if self.history.len() == (self.width / BAR_WIDTH) as usize {
+            self.history.pop_front();
+        }
+        self.history.push_back(FrameTime { emulation, render: Duration::ZERO, missed });
+    }
+
+    /// Adds how long drawing took to the latest frame.
+    pub fn record_render(&mut self, render: Duration) {
+        if let Some(frame) = self.history.back_mut() {
+            frame.render += render;
+        }
+    }
+
+    /// The average emulation and render times, and how many frames were
+    /// late, over the frames in view.
+    fn summary(&self) -> (Duration, Duration, usize) {
+        let frames = (self.history.len() as u32).max(1);
+        let emulation: Duration = self.history.iter().map(|frame| frame.emulation).sum();
+        let render: Duration = self.history.iter().map(|frame| frame.render).sum();
+        let missed = self.history.iter().filter(|frame| frame.missed).count();
+        (emulation / frames, render / frames, missed)
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        canvas.fill_rect(0, self.y, self.width, GRAPH_HEIGHT, BACKGROUND);
+
+        let (emulation, render, missed) = self.summary();
+        canvas.draw_text(8, self.y + 4, &format!("emu {}", millis(emulation)), TEXT_SCALE, EMULATION);
+        canvas.draw_text(152, self.y + 4, &format!("draw {}", millis(render)), TEXT_SCALE, RENDER);
+        canvas.draw_text(308, self.y + 4, &format!("late {}", missed), TEXT_SCALE, MISSED);
+        let top = format!("top {}", millis(2 * FRAME));
+        let top_x = self.width - top.len() as u32 * canvas::text_advance(TEXT_SCALE) - 8;
+        canvas.draw_text(top_x as i32, self.y + 4, &top, TEXT_SCALE, TEXT);
+
+        // the plot goes up to two frames' worth, a frame's in the middle.
+        let plot_top = self.y + (LEGEND_HEIGHT + MISSED_HEIGHT) as i32;
+        let plot_height = GRAPH_HEIGHT - LEGEND_HEIGHT - MISSED_HEIGHT - 4;
+        let plot_bottom = plot_top + plot_height as i32 - 1;
+        let height_of = |time: Duration| {
+            let height = time.as_secs_f64() / (2 * FRAME).as_secs_f64() * plot_height as f64;
+            (height.ceil() as u32).min(plot_height)
+        };
+        let budget = plot_bottom - height_of(FRAME) as i32;
+        canvas.draw_line((0, budget), (self.width as i32 - 1, budget), GRID);
+
+        let x0 = (self.width - self.history.len() as u32 * BAR_WIDTH) as i32;
+        for (i, frame) in self.history.iter().enumerate() {
+            let x = x0 + (i as u32 * BAR_WIDTH) as i32;
+
+            let emulation = height_of(frame.emulation);
+            let render = height_of(frame.emulation + frame.render) - emulation;
+            canvas.fill_rect(x, plot_bottom + 1 - emulation as i32, BAR_WIDTH, emulation, EMULATION);
+            canvas.fill_rect(x, plot_bottom + 1 - (emulation + render) as i32, BAR_WIDTH, render, RENDER);
+            if frame.missed {
+                canvas.fill_rect(x, plot_top - MISSED_HEIGHT as i32, BAR_WIDTH, MISSED_HEIGHT, MISSED);
+            }
+        }
+    }
+}
+
+/// A duration in milliseconds, e.g. `1.2ms`.
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_keeps_a_bar_of_frames_and_adds_drawing_to_the_latest() {
+        let mut graph = FrameGraph::new(GRAPH_HEIGHT, 4 * BAR_WIDTH);
+        for i in 0..6 {
+            graph.record_frame(Duration::from_millis(2), i == 5);
+        }
+        graph.record_render(Duration::from_millis(4));
+        graph.record_render(Duration::from_millis(4));
+
+        assert_eq!(graph.history.len(), 4);
+        assert_eq!(graph.summary(), (Duration::from_millis(2), Duration::from_millis(2), 1));
+    }
+}
@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use crate::cli::HeadlessOptions;
+use crate::command::Session;
+use crate::screenshot;
+
+/// Runs a session for `options.frames` frames without a window, writing
+/// out frames along the way as asked. A trap pauses the machine like it
+/// would in a window, and the frames after it show where it stopped.
+pub fn run(session: &mut Session, options: &HeadlessOptions) -> Result<(), Box<dyn Error>> {
+    let frames = options.frames.unwrap_or(0);
+
+    for frame in 1..=frames {
+        session.run_frame();
+
+        if let (Some(path), Some(every)) = (&options.dump_frame, options.dump_every) {
+            if frame % every == 0 {
+                dump_frame(session, &numbered(path, frame), options.scale)?;
+            }
+        }
+    }
+
+    if let (Some(path), None) = (&options.dump_frame, options.dump_every) {
+        dump_frame(session, path, options.scale)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the display to a file, as a PPM if it ends in .ppm and as a
+/// PNG otherwise.
+fn dump_frame(session: &Session, path: &Path, scale: u32) -> Result<(), Box<dyn Error>> {
+    let (display, palette) = (&session.chippy.display_memory, &session.palette);
+    let is_ppm = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ppm"));
+    let bytes = if is_ppm {
+        screenshot::encode_ppm(display, palette, scale)
+    } else {
+        screenshot::encode_png(display, palette, scale)
+    };
+
+    fs::write(path, bytes).map_err(|e| format!("failed to write {}: {}", path.display(), e).into())
+}
+
+/// The path a numbered frame goes to: `out.png` becomes `out-00060.png`
+/// for frame 60.
+fn numbered(path: &Path, frame: u64) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{:05}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}-{:05}", stem, frame),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_frames_keep_their_extension() {
+        assert_eq!(numbered(Path::new("frames/out.png"), 60), Path::new("frames/out-00060.png"));
+        assert_eq!(numbered(Path::new("out"), 7), Path::new("out-00007"));
+    }
+}
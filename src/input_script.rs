@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::fs;
+use std::io::{ self, Read };
+
+use crate::command::Action;
+
+/// Keypad presses and releases to make at given frames, for tests that
+/// play a game. A script has a line per event, the frame it happens at
+/// (counted in frames run, starting from 0), the key in hex and `press`
+/// or `release`:
+///
+/// ```text
+/// # start the game, then move left for a second
+/// 10 5 press
+/// 12 5 release
+/// 60 4 press
+/// 120 4 release
+/// ```
+pub struct InputScript {
+    // sorted by frame, and the first one still to come.
+    events: Vec<(u64, Action)>,
+    next: usize,
+}
+
+impl InputScript {
+    /// Reads a script from a file, or from standard input for `-`.
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let text = if path == "-" {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        } else {
+            fs::read_to_string(path)?
+        };
+
+        Ok(Self::parse(&text)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (frame, key, action) = match fields[..] {
+                [frame, key, action] => (frame, key, action),
+                _ => return Err(error("expected a frame, a key and press or release")),
+            };
+
+            let frame = frame.parse::<u64>().map_err(|_| error(&format!("{:?} isn't a frame number", frame)))?;
+            let key = u8::from_str_radix(key.trim_start_matches("0x"), 16).ok()
+                .filter(|&key| key <= 0xF)
+                .ok_or_else(|| error(&format!("there's no key {:?}, keys go from 0 to f", key)))?;
+            let action = match action {
+                "press" => Action::PressKey { key },
+                "release" => Action::ReleaseKey { key },
+                _ => return Err(error(&format!("expected press or release, not {:?}", action))),
+            };
+
+            events.push((frame, action));
+        }
+
+        // events at the same frame keep the order they're written in.
+        events.sort_by_key(|&(frame, _)| frame);
+
+        Ok(Self { events, next: 0 })
+    }
+
+    /// Plays the script from `frame` on, as if it had just got there.
+    pub fn seek(&mut self, frame: u64) {
+        self.next = self.events.partition_point(|&(at, _)| at < frame);
+    }
+
+    /// The actions due by `frame` that haven't been taken yet.
+    pub fn due(&mut self, frame: u64) -> Vec<Action> {
+        let start = self.next;
+        while self.events.get(self.next).is_some_and(|&(at, _)| at <= frame) {
+            self.next += 1;
+        }
+
+        self.events[start..self.next].iter().map(|(_, action)| action.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_come_due_in_frame_order() {
+        let mut script = InputScript::parse("# comment\n\n12 5 release\n10 0x5 press # start\n12 a press\n").unwrap();
+
+        assert_eq!(script.due(9), vec![]);
+        assert_eq!(script.due(10), vec![Action::PressKey { key: 5 }]);
+        assert_eq!(script.due(20), vec![Action::ReleaseKey { key: 5 }, Action::PressKey { key: 0xA }]);
+        assert_eq!(script.due(30), vec![]);
+
+        script.seek(12);
+        assert_eq!(script.due(12), vec![Action::ReleaseKey { key: 5 }, Action::PressKey { key: 0xA }]);
+    }
+
+    #[test]
+    fn mistakes_are_reported_by_line() {
+        assert_eq!(InputScript::parse("1 5 press\n2 g press").err().unwrap(),
+            "line 2: there's no key \"g\", keys go from 0 to f");
+        assert!(InputScript::parse("x 5 press").is_err());
+        assert!(InputScript::parse("1 5 hold").is_err());
+        assert!(InputScript::parse("1 5").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use winit::event::VirtualKeyCode;
+
+use winit_input_helper::WinitInputHelper;
+
+/// The keyboard key for every keypad key, laid out like the keypad
+/// (see the top of main.rs).
+pub const KEYMAP: [(VirtualKeyCode, usize); 16] = [
+    (VirtualKeyCode::Key1, 0x1), (VirtualKeyCode::Key2, 0x2), (VirtualKeyCode::Key3, 0x3), (VirtualKeyCode::Key4, 0xC),
+    (VirtualKeyCode::Q, 0x4), (VirtualKeyCode::W, 0x5), (VirtualKeyCode::E, 0x6), (VirtualKeyCode::R, 0xD),
+    (VirtualKeyCode::A, 0x7), (VirtualKeyCode::S, 0x8), (VirtualKeyCode::D, 0x9), (VirtualKeyCode::F, 0xE),
+    (VirtualKeyCode::Z, 0xA), (VirtualKeyCode::X, 0x0), (VirtualKeyCode::C, 0xB), (VirtualKeyCode::V, 0xF),
+];
+
+/// The keys a second player presses the same keypad with, on the numeric
+/// keypad: the digits are themselves, and A to F are the keys around
+/// them, so C and D (the second paddle in Pong) sit one above the other.
+pub const PLAYER_TWO: [(VirtualKeyCode, usize); 16] = [
+    (VirtualKeyCode::Numpad0, 0x0), (VirtualKeyCode::Numpad1, 0x1), (VirtualKeyCode::Numpad2, 0x2), (VirtualKeyCode::Numpad3, 0x3),
+    (VirtualKeyCode::Numpad4, 0x4), (VirtualKeyCode::Numpad5, 0x5), (VirtualKeyCode::Numpad6, 0x6), (VirtualKeyCode::Numpad7, 0x7),
+    (VirtualKeyCode::Numpad8, 0x8), (VirtualKeyCode::Numpad9, 0x9), (VirtualKeyCode::NumpadDivide, 0xA), (VirtualKeyCode::NumpadMultiply, 0xB),
+    (VirtualKeyCode::NumpadSubtract, 0xC), (VirtualKeyCode::NumpadAdd, 0xD), (VirtualKeyCode::NumpadEnter, 0xE), (VirtualKeyCode::NumpadDecimal, 0xF),
+];
+
+/// Every keyboard key that presses a keypad key, whichever player's it is.
+pub type Keymap = Vec<(VirtualKeyCode, usize)>;
+
+/// The first player's keys together with the second player's.
+pub fn keymap(player_two: &HashMap<VirtualKeyCode, u8>) -> Keymap {
+    let mut keymap: Keymap = KEYMAP.to_vec();
+    keymap.extend(player_two.iter().map(|(&key, &pad)| (key, pad as usize)));
+    keymap
+}
+
+/// Presses and releases keypad keys along with their keyboard keys. A
+/// keypad key held by both players stays down until both let go.
+pub fn update_keypad(input: &WinitInputHelper, keymap: &[(VirtualKeyCode, usize)], keypad: &mut [bool; 16]) {
+    for &(key, pad) in keymap {
+        if input.key_pressed(key) {
+            keypad[pad] = true;
+        }
+        if input.key_released(key) && !held(input, keymap, pad) {
+            keypad[pad] = false;
+        }
+    }
+}
+
+/// Whether any keyboard key for a keypad key is held.
+fn held(input: &WinitInputHelper, keymap: &[(VirtualKeyCode, usize)], pad: usize) -> bool {
+    keymap.iter().any(|&(key, other)| other == pad && input.key_held(key))
+}
@@ -0,0 +1,128 @@
+use std::time::{ Duration, Instant };
+
+/// How long it took a key press to get through, from the keyboard event
+/// to the game seeing the key and to the frame after that being shown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub key: u8,
+    pub observed: Duration,
+    pub presented: Duration,
+}
+
+/// Measures input latency for `--latency`. The time of the keyboard
+/// event goes with each keypad key it presses; the first frame whose
+/// instructions see the key held (EX9E, EXA1 or FX0A, see
+/// `Chip8::take_observed_keys`) stamps it as observed, and the first
+/// frame shown after that, once it's been handed to the GPU, as
+/// presented. A key the game doesn't look at before it's released isn't
+/// counted.
+#[derive(Default)]
+pub struct LatencyMeter {
+    // when each keypad key was pressed, until the game sees it.
+    pressed: [Option<Instant>; 16],
+    // keys the game has seen, with when they were pressed and seen, until
+    // a frame shows.
+    observed: Vec<(u8, Instant, Instant)>,
+    pub samples: Vec<Sample>,
+}
+
+impl LatencyMeter {
+    /// Keeps when keys went down or up, `at` the keyboard event that
+    /// moved them.
+    pub fn update_keypad(&mut self, before: &[bool; 16], after: &[bool; 16], at: Instant) {
+        for key in 0..16 {
+            match (before[key], after[key]) {
+                (false, true) => self.pressed[key] = Some(at),
+                (true, false) => self.pressed[key] = None,
+                _ => (),
+            }
+        }
+    }
+
+    /// Stamps the keys the game saw in a frame that ran at `now`.
+    pub fn observe(&mut self, keys: u16, now: Instant) {
+        for key in 0..16 {
+            if keys & (1 << key) == 0 {
+                continue;
+            }
+            if let Some(pressed) = self.pressed[key].take() {
+                self.observed.push((key as u8, pressed, now));
+            }
+        }
+    }
+
+    /// Stamps the keys seen so far as shown, by a frame presented at
+    /// `now`, and returns the finished samples.
+    pub fn present(&mut self, now: Instant) -> Vec<Sample> {
+        let samples: Vec<Sample> = self.observed.drain(..)
+            .map(|(key, pressed, observed)| Sample {
+                key,
+                observed: observed - pressed,
+                presented: now - pressed,
+            })
+            .collect();
+        self.samples.extend(&samples);
+        samples
+    }
+
+    /// The median and the worst of the samples so far, to observed and to
+    /// presented, if there are any.
+    pub fn summary(&self) -> Option<String> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let stats = |mut durations: Vec<Duration>| {
+            durations.sort();
+            (durations[durations.len() / 2], durations[durations.len() - 1])
+        };
+        let observed = stats(self.samples.iter().map(|sample| sample.observed).collect());
+        let presented = stats(self.samples.iter().map(|sample| sample.presented).collect());
+        let presses = self.samples.len();
+        Some(format!(
+            "{} key press{}: seen by the game after {} (worst {}), on screen after {} (worst {}), medians",
+            presses, if presses == 1 { "" } else { "es" },
+            millis(observed.0), millis(observed.1), millis(presented.0), millis(presented.1)))
+    }
+}
+
+impl Sample {
+    pub fn describe(&self) -> String {
+        format!("key {:X}: seen by the game after {}, on screen after {}",
+            self.key, millis(self.observed), millis(self.presented))
+    }
+}
+
+/// A duration in milliseconds, e.g. `16.7ms`.
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presses_are_timed_until_seen_and_shown() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut meter = LatencyMeter::default();
+        let mut keypad = [false; 16];
+
+        let before = keypad;
+        keypad[0x5] = true;
+        keypad[0x6] = true;
+        meter.update_keypad(&before, &keypad, at(0));
+        meter.observe(1 << 0x5, at(10));
+        // 6 is let go before the game looks at it.
+        let before = keypad;
+        keypad[0x6] = false;
+        meter.update_keypad(&before, &keypad, at(12));
+        meter.observe(1 << 0x6 | 1 << 0x5, at(14));
+
+        let samples = meter.present(at(25));
+        assert_eq!(samples, vec![Sample { key: 0x5, observed: Duration::from_millis(10), presented: Duration::from_millis(25) }]);
+        assert!(meter.present(at(40)).is_empty());
+        assert_eq!(meter.summary().unwrap(), "1 key press: seen by the game after 10.0ms (worst 10.0ms), on screen after 25.0ms (worst 25.0ms), medians");
+    }
+}
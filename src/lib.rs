@@ -0,0 +1,23 @@
+//! The Chippy core: the CHIP-8 machine, its save states, a disassembler
+//! and static analysis of ROMs, for the frontends to build on.
+//!
+//! Without the `std` feature the core is `no_std` (it still needs
+//! `alloc`), to run on microcontrollers: there's no rewind, save states
+//! can't be encoded or read from files, and the random number generator
+//! starts from the same seed until it's reseeded.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod analysis;
+pub mod chip8;
+pub mod disasm;
+mod loop_detector;
+pub mod opcode;
+pub mod platform;
+pub mod quirks;
+#[cfg(feature = "std")]
+pub mod rewind;
+pub mod state;
+pub mod trap;
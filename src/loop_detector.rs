@@ -0,0 +1,56 @@
+/// The parts of the machine that decide where a program goes next, as
+/// long as it doesn't touch memory, the display, the timers or the keypad.
+#[derive(Clone, Copy, PartialEq)]
+pub struct LoopState {
+    pub program_counter: usize,
+    pub index: usize,
+    pub stack_pointer: usize,
+    pub stack: [usize; 16],
+    pub registers: [u8; 16],
+}
+
+/// Spots programs stuck in a loop that can never have a visible effect.
+///
+/// Between two bits of activity the machine is deterministic, so if it
+/// comes back to a state it has already been in, it'll loop forever.
+/// States are compared against a snapshot that's retaken at doubling
+/// intervals (Brent's algorithm), which catches loops of any length
+/// without remembering more than one state.
+#[derive(Clone)]
+pub struct LoopDetector {
+    snapshot: Option<LoopState>,
+    steps: u32,
+    interval: u32,
+}
+
+impl LoopDetector {
+    pub fn new() -> Self {
+        Self {
+            snapshot: None,
+            steps: 0,
+            interval: 1,
+        }
+    }
+
+    /// Forgets everything seen so far, the program did something observable.
+    pub fn activity(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Records the state before an instruction, returns true if the
+    /// program is stuck.
+    pub fn step(&mut self, state: LoopState) -> bool {
+        if self.snapshot == Some(state) {
+            return true;
+        }
+
+        self.steps += 1;
+        if self.steps == self.interval {
+            self.snapshot = Some(state);
+            self.steps = 0;
+            self.interval = self.interval.saturating_mul(2);
+        }
+
+        false
+    }
+}
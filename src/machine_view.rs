@@ -0,0 +1,92 @@
+use chippy::state::MachineState;
+
+use crate::canvas::{ self, Canvas };
+
+/// How many rows of memory the memory panel shows, from the row I is in.
+const MEMORY_ROWS: u32 = 16;
+const BYTES_PER_ROW: usize = 8;
+/// How many characters a row of memory takes, `3A0  F0 90 ...`.
+const ROW_COLUMNS: u32 = 5 + 3 * BYTES_PER_ROW as u32 - 1;
+
+const TEXT_SCALE: u32 = 2;
+const MARGIN: u32 = 8;
+const LINE_HEIGHT: u32 = canvas::line_height(TEXT_SCALE);
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+const DIM: [u8; 4] = [0x80, 0x80, 0x90, 0xFF];
+const HEADING: [u8; 4] = [0x48, 0xB2, 0xE8, 0xFF];
+const UNLIT: [u8; 4] = [0x30, 0x30, 0x40, 0xFF];
+
+/// The size of the registers panel, in frame pixels.
+pub const REGISTERS_SIZE: (u32, u32) = (360, 9 * LINE_HEIGHT + 2 * MARGIN);
+/// The size of the memory panel, in frame pixels: the hex, then the
+/// sprite at I, a line high a row.
+pub const MEMORY_SIZE: (u32, u32) = (
+    3 * MARGIN + ROW_COLUMNS * canvas::text_advance(TEXT_SCALE) + 8 * LINE_HEIGHT,
+    (MEMORY_ROWS + 1) * LINE_HEIGHT + 2 * MARGIN,
+);
+
+/// Draws the registers, the timers and the stack, for the debugger.
+pub fn draw_registers(canvas: &mut Canvas, state: &MachineState) {
+    let mut lines = vec![
+        (format!("PC {:03X}  I {:03X}  SP {}", state.program_counter, state.index, state.stack_pointer), TEXT),
+        (format!("DT {:3}  ST {:3}", state.delay_timer, state.sound_timer), TEXT),
+        (String::new(), TEXT),
+    ];
+    for (row, values) in state.registers.chunks(4).enumerate() {
+        let registers: Vec<String> = values.iter().enumerate()
+            .map(|(i, value)| format!("V{:X} {:02X}", row * 4 + i, value))
+            .collect();
+        lines.push((registers.join("  "), TEXT));
+    }
+    lines.push((String::new(), TEXT));
+
+    let stack: Vec<String> = state.stack.iter().take(state.stack_pointer as usize)
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    if stack.is_empty() {
+        lines.push(("stack empty".to_string(), DIM));
+    } else {
+        lines.push((format!("stack {}", stack.join(" ")), TEXT));
+    }
+    if let Some(key) = state.awaited_key {
+        lines.push((format!("key {:X} down, awaiting release", key), DIM));
+    }
+
+    canvas.fill_rect(0, 0, canvas.width, canvas.height, BACKGROUND);
+    for (i, (line, color)) in lines.iter().enumerate() {
+        canvas.draw_text(MARGIN as i32, (MARGIN + i as u32 * LINE_HEIGHT) as i32, line, TEXT_SCALE, *color);
+    }
+}
+
+/// Draws memory in hex from the row I is in, and next to it the sprite
+/// that drawing at I would draw, as tall as it can be.
+pub fn draw_memory(canvas: &mut Canvas, state: &MachineState) {
+    let index = state.index as usize;
+    let start = index / BYTES_PER_ROW * BYTES_PER_ROW;
+
+    canvas.fill_rect(0, 0, canvas.width, canvas.height, BACKGROUND);
+    canvas.draw_text(MARGIN as i32, MARGIN as i32, &format!("memory at I, {:03X}", index), TEXT_SCALE, HEADING);
+
+    let sprite_x = 2 * MARGIN + ROW_COLUMNS * canvas::text_advance(TEXT_SCALE);
+    for row in 0..MEMORY_ROWS {
+        let y = (MARGIN + (row + 1) * LINE_HEIGHT) as i32;
+
+        let address = start + row as usize * BYTES_PER_ROW;
+        if let Some(bytes) = state.memory.get(address..address + BYTES_PER_ROW) {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            canvas.draw_text(MARGIN as i32, y, &format!("{:03X}  {}", address, hex.join(" ")), TEXT_SCALE, TEXT);
+        }
+
+        // a sprite is a byte a row, up to 15 rows from I.
+        let byte = match state.memory.get(index + row as usize) {
+            Some(&byte) if row < 15 => byte,
+            _ => continue,
+        };
+        for bit in 0..8 {
+            let color = if byte & (0x80 >> bit) != 0 { TEXT } else { UNLIT };
+            canvas.fill_rect((sprite_x + bit * LINE_HEIGHT) as i32, y, LINE_HEIGHT - 1, LINE_HEIGHT - 1, color);
+        }
+    }
+}
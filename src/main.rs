@@ -1,48 +1,577 @@
-/// Chippy, a CHIP-8 emulator
-///
-/// Notes:
-///
-/// Recommended input mapping:
-/// ```
-/// Keypad       Keyboard
-/// +-+-+-+-+    +-+-+-+-+
-/// |1|2|3|C|    |1|2|3|4|
-/// +-+-+-+-+    +-+-+-+-+
-/// |4|5|6|D|    |Q|W|E|R|
-/// +-+-+-+-+ => +-+-+-+-+
-/// |7|8|9|E|    |A|S|D|F|
-/// +-+-+-+-+    +-+-+-+-+
-/// |A|0|B|F|    |Z|X|C|V|
-/// +-+-+-+-+    +-+-+-+-+
-/// ```
+//! Chippy, a CHIP-8 emulator
+//!
+//! Notes:
+//!
+//! Recommended input mapping:
+//! ```text
+//! Keypad       Keyboard
+//! +-+-+-+-+    +-+-+-+-+
+//! |1|2|3|C|    |1|2|3|4|
+//! +-+-+-+-+    +-+-+-+-+
+//! |4|5|6|D|    |Q|W|E|R|
+//! +-+-+-+-+ => +-+-+-+-+
+//! |7|8|9|E|    |A|S|D|F|
+//! +-+-+-+-+    +-+-+-+-+
+//! |A|0|B|F|    |Z|X|C|V|
+//! +-+-+-+-+    +-+-+-+-+
+//! ```
+//!
+//! A second player presses the same keypad with the numeric keypad: the
+//! digits are themselves, and / * - + Enter . are A to F (see
+//! `keymap::PLAYER_TWO`, or `player_two` in chippy.toml to change them).
+//!
+//! Emulator keys:
+//! - F2: save the state to the current slot
+//! - F3: load the state from the current slot
+//! - F4: switch to the next save-state slot
+//! - Shift+F2: save the workspace, the ROM with its quirks, keys, speed,
+//!   breakpoints and save states and the machine, for `chippy open`
+//! - Backspace (hold): rewind
+//! - F7: switch post-process shader (none, then the bundled ones and
+//!   those in shaders/)
+//! - F8: switch display filter (none, blend)
+//! - G: show / hide the pixel grid
+//! - B: show / hide the border around the display
+//! - F11: borderless fullscreen / windowed
+//! - Tab: show / hide the speed bar (drag it to set the instructions a
+//!   frame, which is remembered for the game)
+//! - [ / ]: slower / faster
+//! - T: show / hide how long the last frames took to emulate and draw,
+//!   and which ran late
+//! - Minus / Equals: volume down / up
+//! - M: mute / unmute
+//!
+//! Debugger keys:
+//! - F1: dump memory, the registers and the stack to a timestamped file
+//! - Shift+F1: write a bug report, a timestamped zip of the machine, the
+//!   config, the last instructions run and a screenshot
+//! - F5: pause / resume
+//! - F6: export the machine state as JSON
+//! - F9: toggle a breakpoint at the program counter
+//! - F10: step a single instruction while paused
+//! - K: show / hide the frame and a checksum of the machine's state, to
+//!   check machines meant to run in lockstep are still together
+//! - F12: show / hide the disassembly panel (click a line to toggle
+//!   a breakpoint there, hover over one to see what its instruction
+//!   does) and the timers under the display
+//! - Shift+F12: move the debugger's panels into windows of their own,
+//!   with the registers and memory at I too, or back
+//! - H: open the opcode reference (type to search, Escape closes it)
+//! - Ctrl+V: run the program on the clipboard, written in hex
+//!
+//! With `chippy dev watch`, saving the source assembles it again and
+//! loads it, and assembly errors show over the display until it works.
+//!
+//! Replay keys, while an input script plays:
+//! - Period: step a whole frame while paused
+//! - Comma: slow motion (full speed, 1/2, 1/4, 1/8)
+//! - Page Up / Page Down: seek a second back / forward
+//! - Home: seek back to the start
+
+use std::fs;
+use std::io::{ self, Read };
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant };
+
+use clap::Parser;
 
 use winit::dpi::LogicalSize;
-use winit::event::{ Event, VirtualKeyCode };
+use winit::event::{ ElementState, Event, KeyboardInput, TouchPhase, VirtualKeyCode, WindowEvent };
 use winit::event_loop::{ ControlFlow, EventLoop };
-use winit::window::WindowBuilder;
+use winit::window::{ Fullscreen, Window, WindowBuilder };
 
 use winit_input_helper::WinitInputHelper;
 
-use pixels::{ Pixels, SurfaceTexture };
+use pixels::{ Pixels, PixelsBuilder, SurfaceTexture };
+
+use chippy::chip8::{ self, Chip8 };
+use chippy::quirks::Quirks;
+use chippy::state::{ self, MachineState };
 
-use std::time::Duration;
+mod achievements;
+#[cfg(target_os = "android")]
+mod android;
+mod audio;
+mod bug_report;
+mod canvas;
+mod check;
+mod checksum;
+mod cli;
+mod command;
+mod compare;
+mod config;
+mod debug_server;
+mod debug_windows;
+mod debugger;
+mod disasm_view;
+mod dump;
+mod font;
+mod frame_graph;
+mod headless;
+mod input_script;
+mod keymap;
+mod latency;
+mod machine_view;
+mod opcode_reference;
+mod paste;
+mod placement;
+mod post_process;
+mod present;
+mod remote;
+mod rom_info;
+mod rom_set;
+mod scheduler;
+mod screenshot;
+mod speed_bar;
+mod speeds;
+mod stream;
+mod timer_scope;
+mod toast;
+mod touch_keypad;
+mod watch;
+mod workspace;
+mod zip;
 
-mod chip8;
-use chip8::Chip8;
+use audio::{ Buzzer, Sound, Volume };
+use canvas::Canvas;
+use cli::{ Cli, Command, DevCommand, HeadlessOptions, RomsCommand, RunOptions, StateCommand };
+use achievements::Achievements;
+use command::{ Action, Reply, Session };
+use compare::Side;
+use config::Config;
+use debug_server::DebugServer;
+use debug_windows::{ DebugWindows, Panel, PanelEvent };
+use opcode_reference::OpcodeReference;
+use placement::Placement;
+use disasm_view::DisassemblyView;
+use frame_graph::FrameGraph;
+use input_script::InputScript;
+use latency::LatencyMeter;
+use post_process::PostProcess;
+use present::Presenter;
+use remote::RemoteControl;
+use rom_info::{ RomId, RomInfo };
+use scheduler::FrameScheduler;
+use speed_bar::{ IpsMeter, SpeedBar };
+use speeds::Speeds;
+use stream::StreamServer;
+use timer_scope::TimerScope;
+use toast::Toasts;
+use touch_keypad::TouchKeypad;
+use watch::Watch;
+use workspace::Workspace;
 
 const SCALE: u8 = 10;
 
+const DISPLAY_WIDTH: u32 = chip8::VIDEO_WIDTH * SCALE as u32;
+const DISPLAY_HEIGHT: u32 = chip8::VIDEO_HEIGHT * SCALE as u32;
+
+/// Where F6 writes the machine state.
+const STATE_EXPORT_PATH: &str = "chippy-state.json";
+
+/// Where save states go, in a directory per ROM.
+const SAVES_DIR: &str = "saves";
+const SAVE_SLOTS: u32 = 10;
+
+/// What the random number generator starts from in scripted runs.
+const INPUT_SCRIPT_SEED: u64 = 0;
+
+/// The slowest slow motion runs a frame for every this many.
+const SLOWEST_MOTION: u32 = 8;
+
+/// How far Page Up and Page Down seek, in frames.
+const SEEK_FRAMES: u64 = 60;
+
+/// The finger the mouse stands in for on the touch keypad.
+const MOUSE_FINGER: u64 = u64::MAX;
+
+/// How long to wait between steps back while rewinding.
+const REWIND_STEP: Duration = Duration::from_millis(1000 / 30);
+
+/// How long to wait for a server to connect, and then for each read,
+/// when a ROM is fetched over HTTP.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[cfg_attr(target_os = "android", ndk_glue::main)]
 fn main() { 
+    let cli = Cli::parse();
+
+    let config = Config::load(config::CONFIG_PATH).unwrap_or_else(|e| {
+        fail(&format!("failed to load {}: {}", config::CONFIG_PATH, e))
+    });
+
+    let command = cli.command.unwrap_or(Command::Run {
+        rom: cli::DEFAULT_ROM.to_string(),
+        options: RunOptions::default(),
+        headless: HeadlessOptions::default(),
+    });
+    match command {
+        Command::Run { rom, options, headless } if headless.frames.is_some() => {
+            let mut session = start(&rom, &config, config.quirks);
+            session.instructions_per_frame = instructions_per_frame(&session, &options, &config, &Speeds::load(speeds::SPEEDS_PATH));
+            load_input_script(&mut session, &options);
+            headless::run(&mut session, &headless).unwrap_or_else(|e| fail(&e.to_string()));
+            if options.dump_on_exit {
+                dump_for_exit(&session);
+            }
+            if options.report_on_exit {
+                report_for_exit(&session);
+            }
+            session.save_battery();
+        },
+        Command::Run { rom, options, .. } => run(start(&rom, &config, config.quirks), &options, &config, None, None),
+        Command::Info { rom } => {
+            let bytes = read_rom(&rom).unwrap_or_else(|e| fail(&format!("failed to load {}: {}", rom, e)));
+            let id = RomId::of(&bytes);
+            println!("sha1:  {}", id.sha1);
+            println!("crc32: {:08x}", id.crc32);
+
+            match RomInfo::lookup(&id) {
+                Some(info) => {
+                    println!("title: {}", info);
+                    if let Some(keys) = &info.keys {
+                        println!("keys:  {}", keys);
+                    }
+                    if let Some(platform) = info.platform {
+                        println!("platform: {}", platform);
+                    }
+                },
+                None => println!("not in the index"),
+            }
+        },
+        Command::Check { rom, frames } => {
+            let bytes = read_rom(&rom).unwrap_or_else(|e| fail(&format!("failed to load {}: {}", rom, e)));
+            let analysis = chippy::analysis::analyze(&bytes);
+            let executed = frames.map(|frames| {
+                let mut chippy = boot_bytes(&rom, &bytes, &config);
+                let ipf = config.instructions_per_frame.unwrap_or_else(|| analysis.platform.instructions_per_frame());
+                (frames, check::run(&mut chippy, frames, ipf))
+            });
+            print!("{}", check::report(&analysis, &bytes, executed.as_ref().map(|(frames, executed)| (*frames, &executed[..]))));
+        },
+        Command::Roms(RomsCommand::Fetch { dir }) => {
+            rom_set::fetch(&dir).unwrap_or_else(|e| fail(&e.to_string()));
+        },
+        Command::Roms(RomsCommand::List { dir }) => {
+            for entry in rom_set::entries() {
+                let status = match entry.status(&dir) {
+                    rom_set::Status::Fetched => "fetched",
+                    rom_set::Status::Missing => "not fetched",
+                    rom_set::Status::Different => "different file",
+                };
+                let title = entry.info().map(|info| info.to_string()).unwrap_or_default();
+                println!("{:<24} {:<14} {}", dir.join(&entry.file).display(), status, title);
+            }
+        },
+        Command::State(StateCommand::Export { input, cycles, output }) => {
+            // a binary save state is converted, anything else is booted as a ROM.
+            let is_save_state = fs::read(&input).map(|bytes| state::is_binary(&bytes)).unwrap_or(false);
+            let mut chippy = if is_save_state {
+                restore(Path::new(&input), &config)
+            } else {
+                boot(&input, &config)
+            };
+
+            for cycle in 0..cycles {
+                if let Err(trap) = chippy.cycle() {
+                    eprintln!("chippy: stopped after {} cycles: {}", cycle, trap);
+                    break;
+                }
+                if (cycle + 1) % chip8::INSTRUCTIONS_PER_FRAME as u64 == 0 {
+                    chippy.tick_timers();
+                }
+            }
+
+            let json = chippy.save_state().to_json();
+            match output {
+                Some(path) => fs::write(&path, json).unwrap_or_else(|e| {
+                    fail(&format!("failed to write {}: {}", path.display(), e))
+                }),
+                None => println!("{}", json),
+            }
+        },
+        Command::Compare { rom, left, right } => {
+            // read once, standard input can't be read twice.
+            let bytes = read_rom(&rom).unwrap_or_else(|e| fail(&format!("failed to load {}: {}", rom, e)));
+            let side = |name: &str| {
+                let quirks = config.profile(name).unwrap_or_else(|| fail(&format!(
+                    "there's no quirk profile called {:?}, try vip, amiga or one from {}",
+                    name, config::CONFIG_PATH)));
+
+                let mut chippy = boot_bytes(&rom, &bytes, &config);
+                chippy.quirks = quirks;
+                Side::new(name, chippy, config.palette)
+            };
+
+            compare::run(side(&left), side(&right), keymap::keymap(&config.player_two));
+        },
+        Command::Open { path, options } => {
+            let workspace = Workspace::load(&path)
+                .unwrap_or_else(|e| fail(&format!("failed to open {}: {}", path.display(), e)));
+            run(open(&workspace, &config), &options, &config, None, Some(workspace));
+        },
+        Command::State(StateCommand::Import { path, options }) => {
+            let mut session = Session::new(restore(&path, &config), &path.to_string_lossy());
+            session.palette = config.palette;
+            run(session, &options, &config, None, None);
+        },
+        Command::Dev(DevCommand::Watch { source, assembler, options }) => {
+            let assembler = assembler.or_else(|| config.assembler.clone()).unwrap_or_else(|| fail(&format!(
+                "there's no assembler to run, give one with --assembler or assembler in {}, e.g. \"octo {{source}} {{rom}}\"",
+                config::CONFIG_PATH)));
+            let mut watch = Watch::new(&source, &assembler);
+            let rom = watch.rom().to_string_lossy().into_owned();
+
+            // a program that doesn't assemble yet waits on an empty machine
+            // behind the errors, for the fix to be saved.
+            let session = match watch.assemble() {
+                Ok(()) => start(&rom, &config, config.quirks),
+                Err(_) => {
+                    let mut session = Session::new(boot_bytes(&rom, &[], &config), &rom);
+                    session.palette = config.palette;
+                    session.debugger.paused = true;
+                    session
+                },
+            };
+            run(session, &options, &config, Some(watch), None);
+        },
+    }
+}
+
+/// Prints an error and exits.
+fn fail(message: &str) -> ! {
+    eprintln!("chippy: {}", message);
+    std::process::exit(1);
+}
+
+/// Tells the player something, over the display for a few seconds (see
+/// `Toasts::show`) and on the terminal.
+fn notify(toasts: &mut Toasts, topic: &'static str, message: &str) {
+    eprintln!("chippy: {}", message);
+    toasts.show(Instant::now(), topic, message);
+}
+
+/// Starts a session on a ROM, knowing which ROM it is, with `quirks`
+/// rather than the config's.
+fn start(rom: &str, config: &Config, quirks: Quirks) -> Session {
+    let bytes = read_rom(rom).unwrap_or_else(|e| fail(&format!("failed to load {}: {}", rom, e)));
+    let mut chippy = boot_bytes(rom, &bytes, config);
+    chippy.quirks = quirks;
+    let mut session = Session::for_rom(chippy, rom, &bytes);
+    session.palette = config.palette;
+    session
+}
+
+/// Starts a session from a workspace: its ROM with its quirks and
+/// breakpoints, and the machine where it was left.
+fn open(workspace: &Workspace, config: &Config) -> Session {
+    // the quirks are in place before the session takes its boot state
+    // and puts back battery-backed memory.
+    let quirks = match &workspace.profile {
+        Some(name) => config.profile(name).unwrap_or_else(|| fail(&format!(
+            "there's no quirk profile called {:?}, try vip, amiga or one from {}", name, config::CONFIG_PATH))),
+        None => workspace.quirks.unwrap_or(config.quirks),
+    };
+    let mut session = start(&workspace.rom_path(), config, quirks);
+    for &address in &workspace.breakpoints {
+        session.debugger.toggle_breakpoint(address as usize);
+    }
+    if let Some(machine) = workspace.machine() {
+        let machine = machine.unwrap_or_else(|e| fail(&format!(
+            "failed to restore the machine from {}: {}", workspace.path.display(), e)));
+        session.chippy.load_state(&machine);
+    }
+    session
+}
+
+/// Loads a ROM into a machine set up according to the config.
+fn boot(rom: &str, config: &Config) -> Chip8 {
+    let bytes = read_rom(rom).unwrap_or_else(|e| fail(&format!("failed to load {}: {}", rom, e)));
+    boot_bytes(rom, &bytes, config)
+}
+
+/// Loads a ROM that's already been read, called `rom` in errors.
+fn boot_bytes(rom: &str, bytes: &[u8], config: &Config) -> Chip8 {
+    let chippy = Chip8::initialize(bytes).unwrap_or_else(|e| fail(&format!("failed to load {}: {}", rom, e)));
+    configure(chippy, config)
+}
+
+/// Reads a ROM from standard input for `-`, over HTTP for an http:// or
+/// https:// URL, or else from a file (from the app's assets on Android).
+fn read_rom(path: &str) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        return read_at_most_a_rom(io::stdin());
+    }
+    if path.starts_with("http://") || path.starts_with("https://") {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(HTTP_TIMEOUT)
+            .timeout_read(HTTP_TIMEOUT)
+            .build();
+        let response = agent.get(path).call().map_err(|e| match e {
+            ureq::Error::Status(code, _) => io::Error::other(format!("the server answered {}", code)),
+            e => io::Error::other(e),
+        })?;
+        return read_at_most_a_rom(response.into_reader());
+    }
+
+    #[cfg(target_os = "android")]
+    return android::read_asset(path);
+
+    #[cfg(not(target_os = "android"))]
+    fs::read(path)
+}
+
+/// Reads up to the biggest ROM that fits in memory, and fails rather
+/// than read on past it, so a stream that doesn't end can't fill memory.
+fn read_at_most_a_rom(reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut rom = Vec::new();
+    reader.take(chip8::MAX_ROM_SIZE as u64 + 1).read_to_end(&mut rom)?;
+    if rom.len() > chip8::MAX_ROM_SIZE {
+        return Err(io::Error::other(format!("it's more than the {} bytes that fit in memory", chip8::MAX_ROM_SIZE)));
+    }
+    Ok(rom)
+}
+
+/// Restores a machine from a JSON or binary state file.
+fn restore(path: &Path, config: &Config) -> Chip8 {
+    let state = MachineState::load(path)
+        .unwrap_or_else(|e| fail(&format!("failed to import {}: {}", path.display(), e)));
+
+    let mut chippy = configure(Chip8::new(), config);
+    chippy.load_state(&state);
+    chippy
+}
+
+fn configure(mut chippy: Chip8, config: &Config) -> Chip8 {
+    chippy.quirks = config.quirks;
+    chippy.memory_policy = config.memory_policy;
+    chippy.opcode_policy = config.opcode_policy;
+    chippy
+}
+
+/// Runs the emulator in a window until it's closed. Save states and
+/// speeds are kept for the session's game (see `Session::game_key`).
+/// With a `Watch`, the ROM is assembled and loaded again whenever its
+/// source is saved. With a `Workspace`, save states go in it, and it's
+/// written back with the machine when the emulator stops.
+fn run(mut session: Session, options: &RunOptions, config: &Config, mut watch: Option<Watch>, mut workspace: Option<Workspace>) -> ! {
     // initialization //
 
-    let mut chippy = Chip8::initialize("test_roms/Tetris [Fran Dachille, 1991].ch8");
+    let mut volume = Volume::new(config.volume);
+    let mut buzzer = Buzzer::start(Duration::from_millis(config.audio_latency_ms))
+        .unwrap_or_else(|e| fail(&format!("failed to start the sound: {}", e)));
+    buzzer.set_volume(volume);
+
+    let stream = options.stream.as_ref().map(|address| {
+        let server = StreamServer::start(address, config.palette)
+            .unwrap_or_else(|e| fail(&format!("failed to stream on {}: {}", address, e)));
+        eprintln!("chippy: streaming the display on {}", server.address());
+        server
+    });
+    let remote = options.remote.map(|port| {
+        let remote = RemoteControl::start(port, options.remote_public)
+            .unwrap_or_else(|e| fail(&format!("failed to listen for remote control on port {}: {}", port, e)));
+        eprintln!("chippy: listening for remote control on ws://{}/", remote.address());
+        remote
+    });
+    let debug_server = options.debug_server.map(|port| {
+        let server = DebugServer::start(port, config.palette)
+            .unwrap_or_else(|e| fail(&format!("failed to start the debug server on port {}: {}", port, e)));
+        eprintln!("chippy: debug server on http://{}/", server.address());
+        server
+    });
+
+    let uncapped = options.uncapped;
+    let dump_on_exit = options.dump_on_exit;
+    let report_on_exit = options.report_on_exit;
+    let vsync = config.vsync && !options.no_vsync && !uncapped;
+
+    let mut speeds = Speeds::load(speeds::SPEEDS_PATH);
+    let mut speeds_changed = false;
+    let mut game = session.game_key();
+    let player_two = workspace.as_ref().and_then(|workspace| workspace.player_two.clone());
+    let player_two = player_two.unwrap_or_else(|| config.player_two.clone());
+    let keys = keymap::keymap(&player_two);
+    let mut achievements = Achievements::load(&game);
+    let mut toasts = Toasts::default();
+    let info = session.rom.as_ref().and_then(RomInfo::lookup);
+    if let Some(info) = &info {
+        match &info.keys {
+            Some(keys) => eprintln!("chippy: {}, keys: {}", info, keys),
+            None => eprintln!("chippy: {}", info),
+        }
+    }
+
+    session.instructions_per_frame = instructions_per_frame(&session, options, config, &speeds);
+    if let (None, Some(ipf)) = (options.ipf, workspace.as_ref().and_then(|workspace| workspace.instructions_per_frame)) {
+        session.instructions_per_frame = ipf;
+    }
+    load_input_script(&mut session, options);
+    let mut save_slot = workspace.as_ref().map_or(0, |workspace| workspace.slot);
+    let mut rewinding = false;
+    // how many frames of the scheduler each emulated one takes, and how
+    // many have gone by towards the next.
+    let mut slow_motion = 1;
+    let mut slow_frames = 0;
+    let mut last_rewind = Instant::now();
+    let mut scheduler = FrameScheduler::new(Instant::now());
+    // whether the loop was waiting for events rather than frames.
+    let mut idle = false;
+
+    let mut show_checksum = false;
+
+    let mut latency = if options.latency { Some(LatencyMeter::default()) } else { None };
+    // when the last key went down, as the keyboard event came in.
+    let mut last_key_event = Instant::now();
+
+    let mut speed_bar = SpeedBar::new(DISPLAY_HEIGHT, DISPLAY_WIDTH);
+    let mut frame_graph = FrameGraph::new(DISPLAY_HEIGHT - speed_bar::BAR_HEIGHT, DISPLAY_WIDTH);
+    let mut ips_meter = IpsMeter::new(Instant::now(), 0);
+
+    let mut presenter = Presenter::new(config.display_filter, config.blend_weight);
+    presenter.grid = config.pixel_grid;
+    presenter.border = config.border;
+    presenter.border_width = config.border_width;
+    presenter.border_color = config.border_color;
+    presenter.palette = config.palette;
+    let shaders = post_process::load_shaders(Path::new(post_process::SHADERS_DIR));
+    let mut shader = config.shader.as_ref().and_then(|name| {
+        let index = shaders.iter().position(|shader| &shader.name == name);
+        if index.is_none() {
+            eprintln!("chippy: there's no shader called {:?}", name);
+        }
+        index
+    });
+    // made when it's first drawn with, and again whenever the surface changes.
+    let mut post_process = None;
+    let mut reference = OpcodeReference::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    // the line of the disassembly under the mouse
+    let mut hovered_address = None;
+    let mut disasm_view = DisassemblyView::new(DISPLAY_WIDTH as i32, DISPLAY_HEIGHT + timer_scope::SCOPE_HEIGHT);
+    let mut timer_scope = TimerScope::new(DISPLAY_HEIGHT as i32, DISPLAY_WIDTH);
+    let mut placement = Placement::load(placement::PLACEMENT_PATH);
+    let mut panels = DebugWindows::new(placement.separate_panels);
+    place_panels(&panels, &mut disasm_view, &mut timer_scope);
+    // whether the panels are in the game's window, which is bigger then.
+    let mut docked = panels.docked(&session.debugger);
+    let mut touch_keypad = if options.touch_keypad || cfg!(target_os = "android") {
+        Some(TouchKeypad::new(DISPLAY_HEIGHT as i32, DISPLAY_WIDTH))
+    } else {
+        None
+    };
+    // the game and its keys, once they're known
+    let title = match &info {
+        Some(RomInfo { keys: Some(keys), .. }) => format!("Chippy - {} - {}", info.as_ref().unwrap(), keys),
+        Some(info) => format!("Chippy - {}", info),
+        None => "Chippy".to_string(),
+    };
+    let mut title_status = None;
 
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     let window = {
-        let size = LogicalSize::new(
-            chip8::VIDEO_WIDTH * SCALE as u32, 
-            chip8::VIDEO_HEIGHT * SCALE as u32);
+        let (width, height) = frame_size(docked, touch_keypad.is_some());
+        let size = LogicalSize::new(width, height);
 
         WindowBuilder::new()
             .with_title("Chippy")
@@ -51,167 +580,653 @@ fn main() {
             .build(&event_loop)
             .unwrap()
     };
+    let monitor = options.monitor.as_ref().or(config.monitor.as_ref());
+    placement.apply(&window, monitor.map(String::as_str), options.fullscreen || config.fullscreen);
 
-    let mut pixels = {
-        let window_size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(
-            window_size.width, 
-            window_size.height, 
-            &window);
-
-        Pixels::new(
-            chip8::VIDEO_WIDTH * SCALE as u32, 
-            chip8::VIDEO_HEIGHT * SCALE as u32, 
-            surface_texture).unwrap()
+    // on Android the window has no surface until the app is resumed.
+    let mut pixels = if cfg!(target_os = "android") {
+        None
+    } else {
+        Some(create_pixels(&window, frame_size(docked, touch_keypad.is_some()), vsync))
     };
 
+    // for working out the speed of ROMs loaded later.
+    let (options, config) = (options.clone(), config.clone());
+
     // event loop //
 
-    event_loop.run(move |event, _, control_flow| {
-        chippy.cycle();
+    event_loop.run(move |event, target, control_flow| {
+        // the panels' windows open and close with the debugger
+        panels.update(target, &session.debugger, &mut placement, vsync);
+        let panel_event = panels.handle(&event);
+        if let Some(PanelEvent::Clicked(Panel::Disassembly, (x, y))) = panel_event {
+            if let Some(address) = disasm_view.address_at(x, y) {
+                session.execute(Action::ToggleBreakpoint { address });
+            }
+        }
 
-        // draw the current frame
-        if let Event::RedrawRequested(_) = event {
-            let frame = pixels.get_frame();
-            for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-                let x = (i % (chip8::VIDEO_WIDTH * SCALE as u32) as usize) / SCALE as usize;
-                let y = (i / (chip8::VIDEO_WIDTH * SCALE as u32) as usize) / SCALE as usize;
-
-                let rgba = if chippy.display_memory[
-                    (y * chip8::VIDEO_WIDTH as usize) + x as usize
-                ] == 0xFF {
-                    [0x5E, 0x48, 0xE8, 0xFF]
-                } else {
-                    [0x48, 0xB2, 0xE8, 0xFF] 
-                };
+        if let Event::WindowEvent { event: WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, .. }, .. }, .. } = event {
+            last_key_event = Instant::now();
+        }
 
-                pixel.copy_from_slice(&rgba);
-            }
-            if pixels
-                .render()
-                .map_err(|e| eprintln!("pixels.render() failed: {}", e))
-                .is_err()
-            {
-                *control_flow = ControlFlow::Exit;
-                return;
+        if let Some(remote) = &remote {
+            remote.serve(&mut session);
+        }
+        if let Some(debug_server) = &debug_server {
+            debug_server.serve(&session.chippy);
+        }
+        if let Some(watch) = &mut watch {
+            if watch.poll(Instant::now()) {
+                let path = watch.rom().to_string_lossy().into_owned();
+                match session.execute(Action::LoadRom { path: path.clone() }) {
+                    Reply::Error { message } => notify(&mut toasts, "watch", &message),
+                    _ => notify(&mut toasts, "watch", &format!("assembled and loaded {}", path)),
+                }
             }
         }
 
-        if input.update(&event) {
-            // close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
-                *control_flow = ControlFlow::Exit;
-                return ()
-            }
-            
-            // resize the window
-            if let Some(size) = input.window_resized() {
-                pixels.resize(size.width, size.height);
-            }
+        // a ROM loaded since, by the watcher or a paste say, runs at the
+        // speed remembered for it, and the speed bar remembers for it.
+        if session.game_key() != game {
+            game = session.game_key();
+            session.instructions_per_frame = instructions_per_frame(&session, &options, &config, &speeds);
+        }
 
-            // if a key is pressed, set the corresponding keypad mappings.
-            if input.key_pressed(VirtualKeyCode::Key1) {
-                chippy.keypad[0x1] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::Key2) {
-                chippy.keypad[0x2] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::Key3) {
-                chippy.keypad[0x3] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::Key4) {
-                chippy.keypad[0xC] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::Q) {
-                chippy.keypad[0x4] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::W) {
-                chippy.keypad[0x5] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::E) {
-                chippy.keypad[0x6] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::R) {
-                chippy.keypad[0xD] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::A) {
-                chippy.keypad[0x7] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::S) {
-                chippy.keypad[0x8] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::D) {
-                chippy.keypad[0x9] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::F) {
-                chippy.keypad[0xE] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::Z) {
-                chippy.keypad[0xA] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::X) {
-                chippy.keypad[0x0] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::C) {
-                chippy.keypad[0xB] = true;
-            }
-            if input.key_pressed(VirtualKeyCode::V) {
-                chippy.keypad[0xF] = true;
+        // emulation runs in frames at 60Hz, however often the window is
+        // drawn, or a frame every so many in slow motion
+        let frames = scheduler.due(Instant::now());
+        slow_frames += frames;
+        let mut emulated = slow_frames / slow_motion;
+        slow_frames %= slow_motion;
+        // waiting for events, each one runs a frame, which only does what
+        // was asked for while paused, like a step.
+        if idle {
+            emulated = emulated.max(1);
+        }
+        if !rewinding {
+            for frame in 0..emulated {
+                let started = Instant::now();
+                session.run_frame();
+                if let Some(latency) = &mut latency {
+                    latency.observe(session.chippy.take_observed_keys(), Instant::now());
+                }
+                if !session.debugger.paused {
+                    timer_scope.record(&session.chippy);
+                }
+                for name in achievements.check(&session.chippy) {
+                    eprintln!("chippy: achievement: {}", name);
+                    toasts.push(Instant::now(), &name);
+                }
+                // frames past the first were due before now, catching up.
+                frame_graph.record_frame(started.elapsed(), frame > 0);
             }
+        }
+        ips_meter.update(Instant::now(), session.instructions);
 
-            // same for key releases.
-            if input.key_released(VirtualKeyCode::Key1) {
-                chippy.keypad[0x1] = false;
-            }
-            if input.key_released(VirtualKeyCode::Key2) {
-                chippy.keypad[0x2] = false;
-            }
-            if input.key_released(VirtualKeyCode::Key3) {
-                chippy.keypad[0x3] = false;
-            }
-            if input.key_released(VirtualKeyCode::Key4) {
-                chippy.keypad[0xC] = false;
+        buzzer.update(Sound::of(&session.chippy));
+
+        if rewinding && last_rewind.elapsed() >= REWIND_STEP {
+            session.rewind.step_back(&mut session.chippy);
+            last_rewind = Instant::now();
+        }
+
+        // show the debugger status in the title whenever it changes
+        let status = session.debugger.status();
+        if status != title_status {
+            match &status {
+                Some(status) => window.set_title(&format!("{} - {}", title, status)),
+                None => window.set_title(&title),
             }
-            if input.key_released(VirtualKeyCode::Q) {
-                chippy.keypad[0x4] = false;
+            // the debugger has already printed why it paused.
+            toasts.show(Instant::now(), "status", status.as_deref().unwrap_or("resumed"));
+            title_status = status;
+        }
+
+        // the surface goes away while the app is in the background on mobile
+        match event {
+            Event::LoopDestroyed => {
+                if let Some(summary) = latency.as_ref().and_then(LatencyMeter::summary) {
+                    eprintln!("chippy: {}", summary);
+                }
+                if dump_on_exit {
+                    dump_for_exit(&session);
+                }
+                if report_on_exit {
+                    report_for_exit(&session);
+                }
+                session.save_battery();
+                if let Some(workspace) = &mut workspace {
+                    workspace.update(&session, save_slot);
+                    if let Err(e) = workspace.save() {
+                        eprintln!("chippy: failed to save {}: {}", workspace.path.display(), e);
+                    }
+                }
+
+                panels.remember(&mut placement);
+                placement.update(&window);
+                if let Err(e) = placement.save(placement::PLACEMENT_PATH) {
+                    eprintln!("chippy: failed to save {}: {}", placement::PLACEMENT_PATH, e);
+                }
+                if speeds_changed {
+                    if let Err(e) = speeds.save(speeds::SPEEDS_PATH) {
+                        eprintln!("chippy: failed to save {}: {}", speeds::SPEEDS_PATH, e);
+                    }
+                }
+            },
+            Event::Suspended => {
+                pixels = None;
+                post_process = None;
+            },
+            Event::Resumed if pixels.is_none() => {
+                pixels = Some(create_pixels(&window, frame_size(docked, touch_keypad.is_some()), vsync));
+            },
+            _ => (),
+        }
+
+        // fingers on the touch keypad
+        if let (Some(touch_keypad), Some(pixels), Event::WindowEvent { event: WindowEvent::Touch(touch), .. })
+            = (&mut touch_keypad, &pixels, &event)
+        {
+            let position = match touch.phase {
+                TouchPhase::Started | TouchPhase::Moved => pixels
+                    .window_pos_to_pixel((touch.location.x as f32, touch.location.y as f32))
+                    .ok(),
+                TouchPhase::Ended | TouchPhase::Cancelled => None,
+            };
+            touch_keypad.touch(touch.id, position, &mut session.chippy.keypad);
+        }
+
+        // draw the panels that are in windows of their own
+        if let Event::RedrawRequested(id) = event {
+            let hovered = panels.cursor(Panel::Disassembly).and_then(|(x, y)| disasm_view.address_at(x, y));
+            panels.redraw(id, |panel, canvas| match panel {
+                Panel::Disassembly => {
+                    disasm_view.draw(canvas, &session.chippy, &session.debugger);
+                    if let Some(address) = hovered {
+                        let opcode = (session.chippy.peek(address) as u16) << 8 | session.chippy.peek(address + 1) as u16;
+                        opcode_reference::draw_tooltip(canvas, opcode, (0, canvas.height as i32), disasm_view::PANEL_WIDTH);
+                    }
+                },
+                Panel::Timers => timer_scope.draw(canvas),
+                Panel::Registers => machine_view::draw_registers(canvas, &session.chippy.save_state()),
+                Panel::Memory => machine_view::draw_memory(canvas, &session.chippy.save_state()),
+            });
+        }
+
+        // draw the current frame
+        if matches!(event, Event::RedrawRequested(id) if id == window.id()) {
+            if let Some(stream) = &stream {
+                stream.broadcast(&session.chippy.display_memory);
             }
-            if input.key_released(VirtualKeyCode::W) {
-                chippy.keypad[0x5] = false;
+
+            if let Some(pixels) = &mut pixels {
+                let started = Instant::now();
+                let (width, height) = frame_size(docked, touch_keypad.is_some());
+                let mut canvas = Canvas::new(pixels.get_frame(), width, height);
+
+                presenter.draw(&mut canvas, &session.chippy.display_memory, session.frame, 0, SCALE as u32);
+                if speed_bar.visible {
+                    speed_bar.draw(&mut canvas, session.instructions_per_frame, ips_meter.ips);
+                }
+                if frame_graph.visible {
+                    frame_graph.draw(&mut canvas);
+                }
+                if show_checksum {
+                    checksum::draw(&mut canvas, DISPLAY_WIDTH as i32, session.frame, &session.chippy.save_state());
+                }
+                if docked {
+                    disasm_view.draw(&mut canvas, &session.chippy, &session.debugger);
+                    timer_scope.draw(&mut canvas);
+                }
+                if let Some(touch_keypad) = &touch_keypad {
+                    touch_keypad.draw(&mut canvas, &session.chippy.keypad);
+                }
+                if let Some(address) = hovered_address.filter(|_| docked) {
+                    let opcode = (session.chippy.peek(address) as u16) << 8 | session.chippy.peek(address + 1) as u16;
+                    let bottom = (DISPLAY_HEIGHT + timer_scope::SCOPE_HEIGHT) as i32;
+                    opcode_reference::draw_tooltip(&mut canvas, opcode, (DISPLAY_WIDTH as i32, bottom), disasm_view::PANEL_WIDTH);
+                }
+                if reference.visible {
+                    reference.draw(&mut canvas);
+                }
+                if let Some(watch) = &watch {
+                    watch.draw(&mut canvas, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+                }
+                toasts.draw(&mut canvas, Instant::now());
+
+                if let (None, Some(shader)) = (&post_process, shader) {
+                    let size = window.inner_size();
+                    post_process = Some(PostProcess::new(
+                        pixels, &shaders[shader], (size.width, size.height), (DISPLAY_WIDTH, DISPLAY_HEIGHT)));
+                }
+
+                let rendered = pixels.render_with(|encoder, render_target, context| {
+                    match &post_process {
+                        Some(post_process) => post_process.render(encoder, render_target, context),
+                        None => context.scaling_renderer.render(encoder, render_target),
+                    }
+                    Ok(())
+                });
+                if rendered
+                    .map_err(|e| eprintln!("pixels.render() failed: {}", e))
+                    .is_err()
+                {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                frame_graph.record_render(started.elapsed());
+                if let Some(latency) = &mut latency {
+                    for sample in latency.present(Instant::now()) {
+                        notify(&mut toasts, "latency", &sample.describe());
+                    }
+                }
             }
-            if input.key_released(VirtualKeyCode::E) {
-                chippy.keypad[0x6] = false;
+        }
+
+        if panel_event.is_none() && input.update(&event) {
+            // close events
+            if input.key_pressed(VirtualKeyCode::Escape) && reference.visible {
+                reference.visible = false;
+            } else if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                *control_flow = ControlFlow::Exit;
+                return;
             }
-            if input.key_released(VirtualKeyCode::R) {
-                chippy.keypad[0xD] = false;
+            
+            // the opcode reference has the keyboard while it's open
+            if reference.visible {
+                reference.update(&input);
+            } else {
+                // save states and rewinding
+                let slot_path = save_states_dir(&session).join(format!("slot{}.state", save_slot));
+                if input.key_pressed(VirtualKeyCode::F2) && input.held_shift() {
+                    // the whole setup, to carry on from with chippy open
+                    let workspace = workspace.get_or_insert_with(|| Workspace::new(&session, &player_two));
+                    workspace.update(&session, save_slot);
+                    match workspace.save() {
+                        Ok(()) => notify(&mut toasts, "state", &format!("saved the workspace to {}", workspace.path.display())),
+                        Err(e) => notify(&mut toasts, "state", &format!("failed to save the workspace: {}", e)),
+                    }
+                } else if input.key_pressed(VirtualKeyCode::F2) {
+                    let state = session.chippy.save_state();
+                    let saved = match &mut workspace {
+                        Some(workspace) => {
+                            workspace.set_state(save_slot, &state);
+                            workspace.save()
+                        },
+                        None => state.save(&slot_path),
+                    };
+                    match saved {
+                        Ok(()) => notify(&mut toasts, "state", &format!("saved state to slot {}", save_slot)),
+                        Err(e) => notify(&mut toasts, "state", &format!("failed to save state: {}", e)),
+                    }
+                }
+                if input.key_pressed(VirtualKeyCode::F3) {
+                    let state = match &workspace {
+                        Some(workspace) => workspace.state(save_slot),
+                        None => MachineState::load(&slot_path),
+                    };
+                    match state {
+                        Ok(state) => {
+                            session.chippy.load_state(&state);
+                            notify(&mut toasts, "state", &format!("loaded state from slot {}", save_slot));
+                        },
+                        Err(e) => notify(&mut toasts, "state", &format!("failed to load slot {}: {}", save_slot, e)),
+                    }
+                }
+                if input.key_pressed(VirtualKeyCode::F4) {
+                    save_slot = (save_slot + 1) % SAVE_SLOTS;
+                    notify(&mut toasts, "state", &format!("save-state slot {}", save_slot));
+                }
+                rewinding = input.key_held(VirtualKeyCode::Back);
+
+                // post-process shader
+                if input.key_pressed(VirtualKeyCode::F7) {
+                    shader = match shader {
+                        None => Some(0),
+                        Some(i) if i + 1 < shaders.len() => Some(i + 1),
+                        Some(_) => None,
+                    };
+                    post_process = None;
+
+                    match shader {
+                        Some(i) => notify(&mut toasts, "shader", &format!("shader {}", shaders[i].name)),
+                        None => notify(&mut toasts, "shader", "no shader"),
+                    }
+                }
+
+                // display filter
+                if input.key_pressed(VirtualKeyCode::F8) {
+                    presenter.filter = presenter.filter.next();
+                    notify(&mut toasts, "filter", &format!("display filter {}", presenter.filter));
+                }
+                if input.key_pressed(VirtualKeyCode::G) {
+                    presenter.grid = !presenter.grid;
+                    toasts.show(Instant::now(), "grid", if presenter.grid { "pixel grid on" } else { "pixel grid off" });
+                }
+                if input.key_pressed(VirtualKeyCode::B) {
+                    presenter.border = !presenter.border;
+                    toasts.show(Instant::now(), "border", if presenter.border { "border on" } else { "border off" });
+                }
+                if input.key_pressed(VirtualKeyCode::K) {
+                    show_checksum = !show_checksum;
+                }
+
+                // fullscreen, on the monitor the window is on
+                if input.key_pressed(VirtualKeyCode::F11) {
+                    if window.fullscreen().is_some() {
+                        window.set_fullscreen(None);
+                    } else {
+                        // the windowed position is gone once it's fullscreen.
+                        placement.update(&window);
+                        window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+                    }
+                }
+
+                // volume
+                let old_volume = volume;
+                if input.key_pressed(VirtualKeyCode::Minus) {
+                    volume.down();
+                }
+                if input.key_pressed(VirtualKeyCode::Equals) {
+                    volume.up();
+                }
+                if input.key_pressed(VirtualKeyCode::M) {
+                    volume.toggle_mute();
+                }
+                if volume != old_volume {
+                    buzzer.set_volume(volume);
+                    notify(&mut toasts, "volume", &volume.to_string());
+                }
+
+                // debugger controls
+                if input.key_pressed(VirtualKeyCode::F5) {
+                    session.execute(Action::TogglePause);
+                }
+                if input.key_pressed(VirtualKeyCode::F9) {
+                    let address = session.chippy.program_counter();
+                    session.execute(Action::ToggleBreakpoint { address });
+                }
+                if input.key_pressed(VirtualKeyCode::F10) {
+                    session.execute(Action::Step);
+                }
+                // replays
+                if input.key_pressed(VirtualKeyCode::Period) {
+                    session.execute(Action::StepFrame);
+                }
+                if input.key_pressed(VirtualKeyCode::Comma) {
+                    slow_motion = if slow_motion == SLOWEST_MOTION { 1 } else { slow_motion * 2 };
+                    slow_frames = 0;
+                    match slow_motion {
+                        1 => notify(&mut toasts, "speed", "full speed"),
+                        _ => notify(&mut toasts, "speed", &format!("slow motion, 1/{} speed", slow_motion)),
+                    }
+                }
+                let seek = if input.key_pressed(VirtualKeyCode::PageUp) {
+                    Some(session.frame.saturating_sub(SEEK_FRAMES))
+                } else if input.key_pressed(VirtualKeyCode::PageDown) {
+                    Some(session.frame + SEEK_FRAMES)
+                } else if input.key_pressed(VirtualKeyCode::Home) {
+                    Some(0)
+                } else {
+                    None
+                };
+                if let Some(frame) = seek {
+                    match session.execute(Action::Seek { frame }) {
+                        Reply::Error { message } => notify(&mut toasts, "seek", &message),
+                        _ => notify(&mut toasts, "seek", &format!("frame {}", session.frame)),
+                    }
+                }
+
+                if input.key_pressed(VirtualKeyCode::F1) && input.held_shift() {
+                    match session.execute(Action::BugReport) {
+                        Reply::Dumped { path } => notify(&mut toasts, "dump", &format!("wrote a bug report to {}", path.display())),
+                        Reply::Error { message } => notify(&mut toasts, "dump", &message),
+                        _ => (),
+                    }
+                } else if input.key_pressed(VirtualKeyCode::F1) {
+                    match session.execute(Action::Dump) {
+                        Reply::Dumped { path } => notify(&mut toasts, "dump", &format!("dumped the machine to {}", path.display())),
+                        Reply::Error { message } => notify(&mut toasts, "dump", &message),
+                        _ => (),
+                    }
+                }
+                if input.key_pressed(VirtualKeyCode::F6) {
+                    match session.execute(Action::ExportState { path: PathBuf::from(STATE_EXPORT_PATH) }) {
+                        Reply::Error { message } => notify(&mut toasts, "dump", &message),
+                        _ => notify(&mut toasts, "dump", &format!("exported machine state to {}", STATE_EXPORT_PATH)),
+                    }
+                }
+                // Ctrl+V runs a program pasted as hex, from a forum post say
+                if input.key_pressed(VirtualKeyCode::V) && input.held_control() {
+                    match paste::read_clipboard().and_then(|text| paste::parse_hex(&text)) {
+                        Ok(program) => match session.load_program(&program, "clipboard") {
+                            Reply::Error { message } => notify(&mut toasts, "paste", &message),
+                            _ => notify(&mut toasts, "paste", &format!("running {} bytes from the clipboard", program.len())),
+                        },
+                        Err(message) => notify(&mut toasts, "paste", &message),
+                    }
+                }
+                if input.key_pressed(VirtualKeyCode::F12) && input.held_shift() {
+                    panels.separate = !panels.separate;
+                    session.debugger.visible = true;
+                    let message = if panels.separate { "debugger panels in windows of their own" } else { "debugger panels next to the display" };
+                    notify(&mut toasts, "panels", message);
+                } else if input.key_pressed(VirtualKeyCode::F12) {
+                    session.debugger.visible = !session.debugger.visible;
+                }
+
+                let mouse_pixel = input.mouse().and_then(|position| {
+                    pixels.as_ref().and_then(|pixels| pixels.window_pos_to_pixel(position).ok())
+                });
+
+                // the speed bar, and the keys for stepping the speed
+                if input.key_pressed(VirtualKeyCode::Tab) {
+                    speed_bar.visible = !speed_bar.visible;
+                }
+                if input.key_pressed(VirtualKeyCode::T) {
+                    frame_graph.visible = !frame_graph.visible;
+                }
+                let mut ipf = session.instructions_per_frame;
+                if input.key_pressed(VirtualKeyCode::LBracket) {
+                    ipf = speed_bar::slower(ipf);
+                }
+                if input.key_pressed(VirtualKeyCode::RBracket) {
+                    ipf = speed_bar::faster(ipf);
+                }
+                if input.mouse_held(0) {
+                    if let Some(dragged) = mouse_pixel.and_then(|(x, y)| speed_bar.ipf_at(x, y)) {
+                        ipf = dragged;
+                    }
+                }
+                if ipf != session.instructions_per_frame {
+                    session.instructions_per_frame = ipf;
+                    speeds.set(&game, ipf);
+                    speeds_changed = true;
+                    if !speed_bar.visible {
+                        notify(&mut toasts, "ipf", &format!("{} instructions a frame", ipf));
+                    }
+                }
+
+                // H opens the opcode reference, and hovering over a line of
+                // the disassembly explains its instruction
+                if input.key_pressed(VirtualKeyCode::H) {
+                    reference.visible = true;
+                    session.chippy.keypad = [false; 16];
+                }
+                hovered_address = mouse_pixel.filter(|_| docked).and_then(|(x, y)| disasm_view.address_at(x, y));
+
+                // clicking a line in the disassembly toggles a breakpoint there
+                if docked && input.mouse_pressed(0) {
+                    if let Some(address) = mouse_pixel.and_then(|(x, y)| disasm_view.address_at(x, y)) {
+                        session.execute(Action::ToggleBreakpoint { address });
+                    }
+                }
+
+                // the mouse presses keys on the touch keypad, like a finger
+                if let Some(touch_keypad) = &mut touch_keypad {
+                    if input.mouse_held(0) {
+                        touch_keypad.touch(MOUSE_FINGER, mouse_pixel, &mut session.chippy.keypad);
+                    } else if input.mouse_released(0) {
+                        touch_keypad.touch(MOUSE_FINGER, None, &mut session.chippy.keypad);
+                    }
+                }
             }
-            if input.key_released(VirtualKeyCode::A) {
-                chippy.keypad[0x7] = false;
+
+            // resize the window
+            if let (Some(size), Some(pixels)) = (input.window_resized(), &mut pixels) {
+                pixels.resize_surface(size.width, size.height);
+                post_process = None;
             }
-            if input.key_released(VirtualKeyCode::S) {
-                chippy.keypad[0x8] = false;
+
+            // the keypad
+            if !reference.visible {
+                let before = session.chippy.keypad;
+                keymap::update_keypad(&input, &keys, &mut session.chippy.keypad);
+                if let Some(latency) = &mut latency {
+                    latency.update_keypad(&before, &session.chippy.keypad, last_key_event);
+                }
             }
-            if input.key_released(VirtualKeyCode::D) {
-                chippy.keypad[0x9] = false;
+        }
+
+        // make room for the panels when they come into the window, or take
+        // it back when they go, the new frame needs a new pixel buffer.
+        if panels.docked(&session.debugger) != docked {
+            docked = !docked;
+            place_panels(&panels, &mut disasm_view, &mut timer_scope);
+            if let Some(touch_keypad) = &mut touch_keypad {
+                touch_keypad.move_to(keypad_y(docked));
             }
-            if input.key_released(VirtualKeyCode::F) {
-                chippy.keypad[0xE] = false;
+            let (width, height) = frame_size(docked, touch_keypad.is_some());
+            window.set_inner_size(LogicalSize::new(width, height));
+            if pixels.is_some() {
+                pixels = Some(create_pixels(&window, (width, height), vsync));
+                post_process = None;
             }
-            if input.key_released(VirtualKeyCode::Z) {
-                chippy.keypad[0xA] = false;
+        }
+
+        // while paused (or halted) nothing changes but what the user does,
+        // so wait for that rather than waking every frame, unless something
+        // still needs the clock: a toast to take down, a rewind, the speed
+        // meter, or a connection or a source file to look at.
+        let was_idle = idle;
+        idle = session.debugger.paused && !rewinding && !speed_bar.visible
+            && !toasts.showing(Instant::now())
+            && remote.is_none() && debug_server.is_none() && watch.is_none();
+
+        // draw every new frame and wait for the next one, or draw as
+        // often as possible when uncapped
+        if uncapped {
+            window.request_redraw();
+            panels.request_redraw();
+        } else if idle {
+            // draw once more for the last of the toasts, then as things happen.
+            if !was_idle || matches!(event, Event::WindowEvent { .. }) {
+                window.request_redraw();
+                panels.request_redraw();
             }
-            if input.key_released(VirtualKeyCode::X) {
-                chippy.keypad[0x0] = false;
+            *control_flow = ControlFlow::Wait;
+        } else {
+            // frames start again from now, rather than catching up.
+            if was_idle {
+                scheduler = FrameScheduler::new(Instant::now());
             }
-            if input.key_released(VirtualKeyCode::C) {
-                chippy.keypad[0xB] = false;
+            if frames > 0 {
+                window.request_redraw();
+                panels.request_redraw();
             }
-            if input.key_released(VirtualKeyCode::V) {
-                chippy.keypad[0xF] = false;
-            } 
+            *control_flow = ControlFlow::WaitUntil(scheduler.next_frame());
         }
-
-        // request a redraw and sleep for some duration
-        window.request_redraw(); 
-        //std::thread::sleep(Duration::from_millis(1000/60));
     });
 }
+
+/// How many instructions run a frame: as many as the user asked for, as
+/// many as were set for the game with the speed bar, or else the default
+/// for the ROM's platform, which the ROM index may know.
+fn instructions_per_frame(session: &Session, options: &RunOptions, config: &Config, speeds: &Speeds) -> u32 {
+    let platform = options.platform
+        .or_else(|| session.rom.as_ref().and_then(RomInfo::lookup).and_then(|info| info.platform))
+        .unwrap_or(config.platform);
+    options.ipf
+        .or_else(|| speeds.get(&session.game_key()))
+        .or(config.instructions_per_frame)
+        .unwrap_or_else(|| platform.instructions_per_frame())
+}
+
+/// Gives the session the input script the options name, if any. Scripted
+/// runs roll the same random numbers every time, so they play out the same.
+fn load_input_script(session: &mut Session, options: &RunOptions) {
+    if let Some(path) = &options.input_script {
+        if path == "-" && session.source == "-" {
+            fail("the ROM and the input script can't both come from standard input");
+        }
+        let script = InputScript::load(path)
+            .unwrap_or_else(|e| fail(&format!("failed to read the input script {}: {}", path, e)));
+        session.input_script = Some(script);
+        session.chippy.reseed(INPUT_SCRIPT_SEED);
+    }
+}
+
+/// Dumps the machine as it was left, for --dump-on-exit.
+fn dump_for_exit(session: &Session) {
+    match session.dump("exit") {
+        Ok(path) => eprintln!("chippy: dumped the machine to {}", path.display()),
+        Err(e) => eprintln!("chippy: failed to dump the machine: {}", e),
+    }
+}
+
+fn report_for_exit(session: &Session) {
+    match session.bug_report("exit") {
+        Ok(path) => eprintln!("chippy: wrote a bug report to {}", path.display()),
+        Err(e) => eprintln!("chippy: failed to write a bug report: {}", e),
+    }
+}
+
+/// The directory save states for the session's game go in. Directories
+/// named after the ROM's file, from before games were told apart by
+/// their hash, are still used if they're there.
+fn save_states_dir(session: &Session) -> PathBuf {
+    let dir = Path::new(SAVES_DIR).join(session.game_key());
+    let legacy = Path::new(SAVES_DIR).join(command::source_name(&session.source));
+    if !dir.exists() && legacy.exists() { legacy } else { dir }
+}
+
+/// The size of the frame, with room for the debugger's panels if they're
+/// docked in the window and the touch keypad if there is one.
+fn frame_size(docked: bool, touch_keypad: bool) -> (u32, u32) {
+    let width = if docked { DISPLAY_WIDTH + disasm_view::PANEL_WIDTH } else { DISPLAY_WIDTH };
+    let height = if touch_keypad { keypad_y(docked) as u32 + touch_keypad::KEYPAD_HEIGHT } else { keypad_y(docked) as u32 };
+
+    (width, height)
+}
+
+/// Where the touch keypad goes: under the display, and the timers when
+/// the debugger is showing them in the window.
+fn keypad_y(docked: bool) -> i32 {
+    let scope = if docked { timer_scope::SCOPE_HEIGHT } else { 0 };
+    (DISPLAY_HEIGHT + scope) as i32
+}
+
+/// Puts the disassembly and the timers next to and under the display, or
+/// in the corner of windows of their own.
+fn place_panels(panels: &DebugWindows, disasm_view: &mut DisassemblyView, timer_scope: &mut TimerScope) {
+    if panels.separate {
+        disasm_view.move_to(0);
+        timer_scope.move_to(0);
+    } else {
+        disasm_view.move_to(DISPLAY_WIDTH as i32);
+        timer_scope.move_to(DISPLAY_HEIGHT as i32);
+    }
+}
+
+fn create_pixels(window: &Window, (width, height): (u32, u32), vsync: bool) -> Pixels {
+    let window_size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(
+        window_size.width, 
+        window_size.height, 
+        window);
+
+    PixelsBuilder::new(width, height, surface_texture)
+        .enable_vsync(vsync)
+        .build()
+        .unwrap()
+}
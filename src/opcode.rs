@@ -0,0 +1,226 @@
+use crate::platform::Platform;
+
+/// The instructions the interpreter knows. The operands stay in the
+/// opcode, `decode` only tells which instruction it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Cls,       // 00E0
+    Ret,       // 00EE
+    Jp,        // 1NNN
+    Call,      // 2NNN
+    SeVxByte,  // 3XKK
+    SneVxByte, // 4XKK
+    SeVxVy,    // 5XY0
+    LdVxByte,  // 6XKK
+    AddVxByte, // 7XKK
+    LdVxVy,    // 8XY0
+    Or,        // 8XY1
+    And,       // 8XY2
+    Xor,       // 8XY3
+    AddVxVy,   // 8XY4
+    Sub,       // 8XY5
+    Shr,       // 8XY6
+    Subn,      // 8XY7
+    Shl,       // 8XYE
+    SneVxVy,   // 9XY0
+    LdI,       // ANNN
+    JpV0,      // BNNN
+    Rnd,       // CXKK
+    Drw,       // DXYN
+    Skp,       // EX9E
+    Sknp,      // EXA1
+    Plane,     // FN01
+    Audio,     // F002
+    LdVxDt,    // FX07
+    LdVxK,     // FX0A
+    LdDtVx,    // FX15
+    LdStVx,    // FX18
+    AddIVx,    // FX1E
+    LdFVx,     // FX29
+    LdBVx,     // FX33
+    Pitch,     // FX3A
+    LdIVx,     // FX55
+    LdVxI,     // FX65
+}
+
+/// Works out which instruction an opcode is, if any.
+pub fn decode(opcode: u16) -> Option<Op> {
+    let op = match opcode {
+        0x00E0 => Op::Cls,
+        0x00EE => Op::Ret,
+        _ => {
+            match opcode & 0xF000 {
+                0x1000 => Op::Jp,
+                0x2000 => Op::Call,
+                0x3000 => Op::SeVxByte,
+                0x4000 => Op::SneVxByte,
+                0x5000 => Op::SeVxVy,
+                0x6000 => Op::LdVxByte,
+                0x7000 => Op::AddVxByte,
+                0x8000 => {
+                    match opcode & 0x000F {
+                        0x0000 => Op::LdVxVy,
+                        0x0001 => Op::Or,
+                        0x0002 => Op::And,
+                        0x0003 => Op::Xor,
+                        0x0004 => Op::AddVxVy,
+                        0x0005 => Op::Sub,
+                        0x0006 => Op::Shr,
+                        0x0007 => Op::Subn,
+                        0x000E => Op::Shl,
+                        _ => return None,
+                    }
+                },
+                0x9000 => Op::SneVxVy,
+                0xA000 => Op::LdI,
+                0xB000 => Op::JpV0,
+                0xC000 => Op::Rnd,
+                0xD000 => Op::Drw,
+                0xE000 => {
+                    match opcode & 0x00FF {
+                        0x009E => Op::Skp,
+                        0x00A1 => Op::Sknp,
+                        _ => return None,
+                    }
+                },
+                0xF000 => {
+                    match opcode & 0x00FF {
+                        0x0001 => Op::Plane,
+                        0x0002 if opcode == 0xF002 => Op::Audio,
+                        0x0007 => Op::LdVxDt,
+                        0x000A => Op::LdVxK,
+                        0x0015 => Op::LdDtVx,
+                        0x0018 => Op::LdStVx,
+                        0x001E => Op::AddIVx,
+                        0x0029 => Op::LdFVx,
+                        0x0033 => Op::LdBVx,
+                        0x003A => Op::Pitch,
+                        0x0055 => Op::LdIVx,
+                        0x0065 => Op::LdVxI,
+                        _ => return None,
+                    }
+                },
+                _ => return None,
+            }
+        },
+    };
+
+    Some(op)
+}
+
+/// What an instruction does, for the opcode reference.
+pub struct OpcodeDoc {
+    pub op: Op,
+    /// The opcode with its operands as letters, e.g. `8XY4`.
+    pub pattern: &'static str,
+    pub mnemonic: &'static str,
+    pub description: &'static str,
+    /// The platform the instruction first came with.
+    pub platform: Platform,
+    /// How the quirks change it, if they do.
+    pub quirk: Option<&'static str>,
+}
+
+/// Every instruction the interpreter knows, in opcode order.
+pub const REFERENCE: &[OpcodeDoc] = &[
+    doc(Op::Cls, "00E0", "CLS", "Clear the display, on XO-CHIP just the selected planes."),
+    doc(Op::Ret, "00EE", "RET", "Return from a subroutine, to the address on top of the stack."),
+    doc(Op::Jp, "1NNN", "JP addr", "Jump to NNN."),
+    doc(Op::Call, "2NNN", "CALL addr", "Call the subroutine at NNN, pushing the address after this one."),
+    doc(Op::SeVxByte, "3XKK", "SE VX, byte", "Skip the next instruction if VX is KK."),
+    doc(Op::SneVxByte, "4XKK", "SNE VX, byte", "Skip the next instruction if VX isn't KK."),
+    doc(Op::SeVxVy, "5XY0", "SE VX, VY", "Skip the next instruction if VX is VY."),
+    doc(Op::LdVxByte, "6XKK", "LD VX, byte", "Set VX to KK."),
+    doc(Op::AddVxByte, "7XKK", "ADD VX, byte", "Add KK to VX, wrapping around. VF is left alone."),
+    doc(Op::LdVxVy, "8XY0", "LD VX, VY", "Set VX to VY."),
+    doc(Op::Or, "8XY1", "OR VX, VY", "Set VX to VX OR VY."),
+    doc(Op::And, "8XY2", "AND VX, VY", "Set VX to VX AND VY."),
+    doc(Op::Xor, "8XY3", "XOR VX, VY", "Set VX to VX XOR VY."),
+    doc(Op::AddVxVy, "8XY4", "ADD VX, VY", "Add VY to VX, then set VF to 1 if it carried and 0 if not."),
+    doc(Op::Sub, "8XY5", "SUB VX, VY", "Subtract VY from VX, then set VF to 0 if it borrowed and 1 if not."),
+    doc(Op::Shr, "8XY6", "SHR VX", "Shift VX right by one, then set VF to the bit shifted out."),
+    doc(Op::Subn, "8XY7", "SUBN VX, VY", "Set VX to VY minus VX, then set VF to 0 if it borrowed and 1 if not."),
+    doc(Op::Shl, "8XYE", "SHL VX", "Shift VX left by one, then set VF to the bit shifted out."),
+    doc(Op::SneVxVy, "9XY0", "SNE VX, VY", "Skip the next instruction if VX isn't VY."),
+    doc(Op::LdI, "ANNN", "LD I, addr", "Set I to NNN."),
+    doc(Op::JpV0, "BNNN", "JP V0, addr", "Jump to NNN plus V0."),
+    OpcodeDoc {
+        quirk: Some("random (vip_paced, fixed_random): from a PRNG, the VIP's way, or counting up"),
+        ..doc(Op::Rnd, "CXKK", "RND VX, byte", "Set VX to a random byte AND KK.")
+    },
+    OpcodeDoc {
+        quirk: Some("sprites_per_frame (vip_paced): waits for the next frame once that many have been drawn"),
+        ..doc(Op::Drw, "DXYN", "DRW VX, VY, n",
+            "Draw the N-byte sprite at I at (VX, VY), XORed onto the display. VF is set to 1 if a lit pixel was turned off. \
+            On XO-CHIP it's drawn on every selected plane, the sprite for plane 2 right after the one for plane 1.")
+    },
+    doc(Op::Skp, "EX9E", "SKP VX", "Skip the next instruction if the key in VX is held."),
+    doc(Op::Sknp, "EXA1", "SKNP VX", "Skip the next instruction if the key in VX isn't held."),
+    OpcodeDoc {
+        platform: Platform::XoChip,
+        ..doc(Op::Plane, "FN01", "PLANE n",
+            "Select the planes CLS and DRW work on, N being 1 for the first, 2 for the second and 3 for both. \
+            Where both are lit, pixels show in a blend of their colors.")
+    },
+    OpcodeDoc {
+        platform: Platform::XoChip,
+        ..doc(Op::Audio, "F002", "AUDIO",
+            "Load the 16 bytes at I as the audio pattern: 128 one-bit samples, played in a loop instead of the buzzer \
+            while the sound timer runs.")
+    },
+    doc(Op::LdVxDt, "FX07", "LD VX, DT", "Set VX to the delay timer."),
+    OpcodeDoc {
+        quirk: Some("fx0a_on_press: done as soon as a key is held"),
+        ..doc(Op::LdVxK, "FX0A", "LD VX, K", "Wait for a key to be pressed and released, and put it in VX.")
+    },
+    doc(Op::LdDtVx, "FX15", "LD DT, VX", "Set the delay timer to VX."),
+    doc(Op::LdStVx, "FX18", "LD ST, VX", "Set the sound timer to VX. The buzzer sounds while it's above zero."),
+    OpcodeDoc {
+        quirk: Some("fx1e_overflow_vf (amiga): VF is 1 if I went past 0xFFF, else 0"),
+        ..doc(Op::AddIVx, "FX1E", "ADD I, VX", "Add VX to I.")
+    },
+    doc(Op::LdFVx, "FX29", "LD F, VX", "Set I to the font sprite for the hex digit in VX."),
+    doc(Op::LdBVx, "FX33", "LD B, VX", "Write VX in decimal to I, I+1 and I+2: hundreds, tens and ones."),
+    OpcodeDoc {
+        platform: Platform::XoChip,
+        ..doc(Op::Pitch, "FX3A", "PITCH VX",
+            "Set the pitch the audio pattern plays at to VX: 4000 samples a second at 64, an octave higher every 48 more.")
+    },
+    doc(Op::LdIVx, "FX55", "LD [I], VX", "Write V0 to VX to memory from I on. I is left alone."),
+    doc(Op::LdVxI, "FX65", "LD VX, [I]", "Read V0 to VX from memory from I on. I is left alone."),
+];
+
+const fn doc(op: Op, pattern: &'static str, mnemonic: &'static str, description: &'static str) -> OpcodeDoc {
+    OpcodeDoc { op, pattern, mnemonic, description, platform: Platform::Chip8, quirk: None }
+}
+
+impl OpcodeDoc {
+    /// The reference for an instruction.
+    pub fn of(op: Op) -> &'static OpcodeDoc {
+        REFERENCE.iter().find(|doc| doc.op == op).expect("every instruction is in the reference")
+    }
+
+    /// Whether the entry mentions `query` anywhere, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [self.pattern, self.mnemonic, self.description, self.quirk.unwrap_or("")].iter()
+            .any(|text| text.to_lowercase().contains(&query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_instruction_is_in_the_reference_under_its_pattern() {
+        for doc in REFERENCE {
+            // the pattern with every operand as 0 decodes to the entry
+            let opcode = doc.pattern.chars()
+                .fold(0, |opcode, c| opcode << 4 | c.to_digit(16).unwrap_or(0) as u16);
+            assert_eq!(decode(opcode), Some(doc.op), "{}", doc.pattern);
+        }
+        assert_eq!(OpcodeDoc::of(Op::Drw).pattern, "DXYN");
+        assert!(OpcodeDoc::of(Op::AddIVx).matches("AMIGA"));
+    }
+}
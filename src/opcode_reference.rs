@@ -0,0 +1,169 @@
+use winit::event::VirtualKeyCode;
+
+use winit_input_helper::{ TextChar, WinitInputHelper };
+
+use chippy::opcode::{ self, OpcodeDoc, REFERENCE };
+
+use crate::canvas::{ self, Canvas };
+
+const TEXT_SCALE: u32 = 2;
+const MARGIN: i32 = 8;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const HEADING: [u8; 4] = [0x48, 0xB2, 0xE8, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+const QUIRK: [u8; 4] = [0xE8, 0xA0, 0x48, 0xFF];
+const DIM: [u8; 4] = [0x80, 0x80, 0x90, 0xFF];
+
+/// A searchable list of every instruction, drawn over the display on the
+/// canvas like the other panels. While it's open, typing goes to the
+/// search instead of the keypad.
+pub struct OpcodeReference {
+    pub visible: bool,
+    query: String,
+    // the first line showing.
+    scroll: usize,
+    width: u32,
+    height: u32,
+}
+
+impl OpcodeReference {
+    /// A reference covering `width` by `height` pixels at the top left of
+    /// the frame.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { visible: false, query: String::new(), scroll: 0, width, height }
+    }
+
+    /// Types into the search and scrolls, with the arrow keys or the
+    /// mouse wheel.
+    pub fn update(&mut self, input: &WinitInputHelper) {
+        for c in input.text() {
+            match c {
+                TextChar::Char(c) if !c.is_control() => self.query.push(c),
+                TextChar::Back => {
+                    self.query.pop();
+                },
+                TextChar::Char(_) => (),
+            }
+            self.scroll = 0;
+        }
+
+        let lines = self.lines().len();
+        if input.key_pressed(VirtualKeyCode::Down) || input.scroll_diff() < 0.0 {
+            self.scroll = (self.scroll + 1).min(lines.saturating_sub(1));
+        }
+        if input.key_pressed(VirtualKeyCode::Up) || input.scroll_diff() > 0.0 {
+            self.scroll = self.scroll.saturating_sub(1);
+        }
+    }
+
+    fn columns(&self) -> usize {
+        ((self.width - 2 * MARGIN as u32) / canvas::text_advance(TEXT_SCALE)) as usize
+    }
+
+    /// The entries matching the search, a line at a time.
+    fn lines(&self) -> Vec<(String, [u8; 4])> {
+        let mut lines = Vec::new();
+        for doc in REFERENCE.iter().filter(|doc| doc.matches(&self.query)) {
+            let heading = format!("{}  {}", doc.pattern, doc.mnemonic);
+            let platform = doc.platform.to_string();
+            let gap = self.columns().saturating_sub(heading.len() + platform.len());
+            lines.push((format!("{}{}{}", heading, " ".repeat(gap), platform), HEADING));
+
+            for line in wrap(doc.description, self.columns() - 2) {
+                lines.push((format!("  {}", line), TEXT));
+            }
+            if let Some(quirk) = doc.quirk {
+                for line in wrap(quirk, self.columns() - 2) {
+                    lines.push((format!("  {}", line), QUIRK));
+                }
+            }
+        }
+        lines
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        let line_height = canvas::line_height(TEXT_SCALE) as i32;
+        canvas.fill_rect(0, 0, self.width, self.height, BACKGROUND);
+        canvas.draw_text(MARGIN, MARGIN, &format!("Search: {}_", self.query), TEXT_SCALE, TEXT);
+        canvas.draw_text(MARGIN, MARGIN + line_height, "Up/Down scroll, Esc closes", TEXT_SCALE, DIM);
+
+        let lines = self.lines();
+        if lines.is_empty() {
+            canvas.draw_text(MARGIN, MARGIN + 3 * line_height, "No instruction matches.", TEXT_SCALE, DIM);
+        }
+
+        let top = MARGIN + 3 * line_height;
+        let fits = ((self.height as i32 - top) / line_height).max(0) as usize;
+        for (i, (line, color)) in lines.iter().skip(self.scroll).take(fits).enumerate() {
+            canvas.draw_text(MARGIN, top + i as i32 * line_height, line, TEXT_SCALE, *color);
+        }
+    }
+}
+
+/// Draws what an opcode does in a box along the bottom of an area of
+/// the frame, for hovering over the disassembly.
+pub fn draw_tooltip(canvas: &mut Canvas, opcode: u16, (x, bottom): (i32, i32), width: u32) {
+    let doc = match opcode::decode(opcode) {
+        Some(op) => OpcodeDoc::of(op),
+        None => return,
+    };
+
+    let columns = ((width - 2 * MARGIN as u32) / canvas::text_advance(TEXT_SCALE)) as usize;
+    let mut lines = vec![(format!("{}  {}", doc.pattern, doc.mnemonic), HEADING)];
+    lines.extend(wrap(doc.description, columns).into_iter().map(|line| (line, TEXT)));
+    if let Some(quirk) = doc.quirk {
+        lines.extend(wrap(quirk, columns).into_iter().map(|line| (line, QUIRK)));
+    }
+
+    let line_height = canvas::line_height(TEXT_SCALE);
+    let height = lines.len() as u32 * line_height + MARGIN as u32;
+    let top = bottom - height as i32;
+    canvas.fill_rect(x, top, width, height, BACKGROUND);
+    for (i, (line, color)) in lines.iter().enumerate() {
+        canvas.draw_text(x + MARGIN, top + MARGIN / 2 + (i as u32 * line_height) as i32, line, TEXT_SCALE, *color);
+    }
+}
+
+/// Breaks text into lines of at most `columns` characters, between words.
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > columns {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_wraps_between_words() {
+        assert_eq!(wrap("Add VY to VX, then set VF", 12), vec!["Add VY to", "VX, then set", "VF"]);
+        assert_eq!(wrap("", 12), Vec::<String>::new());
+    }
+
+    #[test]
+    fn the_search_narrows_the_list() {
+        let mut reference = OpcodeReference::new(640, 320);
+        let everything = reference.lines().len();
+
+        reference.query = "fx1e".to_string();
+        let lines = reference.lines();
+
+        assert!(lines.len() < everything);
+        assert!(lines[0].0.starts_with("FX1E  ADD I, VX"));
+        assert!(lines[0].0.ends_with("chip8"));
+    }
+}
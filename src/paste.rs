@@ -0,0 +1,67 @@
+use std::process;
+
+/// The commands that print the clipboard, tried in turn: Wayland's, then
+/// X11's, macOS's and Windows'.
+const PASTE_COMMANDS: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-out"],
+    &["xsel", "--clipboard", "--output"],
+    &["pbpaste"],
+    &["powershell", "-NoProfile", "-Command", "Get-Clipboard"],
+];
+
+/// The text on the clipboard, from whichever of `PASTE_COMMANDS` is
+/// there.
+pub fn read_clipboard() -> Result<String, String> {
+    for command in PASTE_COMMANDS {
+        let output = match process::Command::new(command[0]).args(&command[1..]).output() {
+            Ok(output) if output.status.success() => output,
+            _ => continue,
+        };
+        return String::from_utf8(output.stdout).map_err(|_| "the clipboard isn't text".to_string());
+    }
+    Err("couldn't read the clipboard, install wl-paste, xclip or xsel".to_string())
+}
+
+/// Reads a program written out in hex, as it's shared on forums and in
+/// tutorials: bytes like `00E0 A22A` or `0x00 0xE0`, separated by spaces,
+/// commas or lines. Octo's `#` comments and `: label` lines, as in its
+/// hex output, are skipped.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut program = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.starts_with(':') {
+            continue;
+        }
+
+        for word in line.split(|c: char| c.is_whitespace() || c == ',').filter(|word| !word.is_empty()) {
+            let digits = word.trim_start_matches("0x").trim_start_matches("0X");
+            if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("{} isn't hex bytes", word));
+            }
+            for i in (0..digits.len()).step_by(2) {
+                program.push(u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| e.to_string())?);
+            }
+        }
+    }
+
+    if program.is_empty() {
+        return Err("there's no program on the clipboard".to_string());
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_is_read_however_its_spaced() {
+        assert_eq!(parse_hex("00E0 a22a\n6000,").unwrap(), vec![0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x00]);
+        assert_eq!(parse_hex(": main # from Octo\n0x00 0xE0 0x12 0x00\n").unwrap(), vec![0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(parse_hex("00E0 CLS").unwrap_err(), "CLS isn't hex bytes");
+        assert_eq!(parse_hex("0E0").unwrap_err(), "0E0 isn't hex bytes");
+        assert!(parse_hex("  \n").is_err());
+    }
+}
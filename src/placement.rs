@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+
+use serde::{ Deserialize, Serialize };
+
+use winit::dpi::PhysicalPosition;
+use winit::monitor::MonitorHandle;
+use winit::window::{ Fullscreen, Window };
+
+/// Where the window's placement is kept between runs, relative to the
+/// working directory.
+pub const PLACEMENT_PATH: &str = "chippy-window.toml";
+
+/// Where the window was when Chippy last closed, so it opens there again,
+/// and how the debugger's panels were laid out.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Placement {
+    /// The name of the monitor the window was on.
+    pub monitor: Option<String>,
+    /// Where the window was on the desktop, the last time it wasn't fullscreen.
+    pub position: Option<(i32, i32)>,
+    /// Whether the debugger's panels were in windows of their own.
+    pub separate_panels: bool,
+    /// Where each panel's window was, by the panel's name.
+    pub panels: BTreeMap<String, (i32, i32)>,
+}
+
+impl Placement {
+    /// Loads the placement at `path`. A missing or broken file is no
+    /// reason not to start, the window just goes where it's put.
+    pub fn load(path: &str) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                eprintln!("chippy: failed to read {}: {}", path, e);
+                return Self::default();
+            },
+        };
+
+        toml::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("chippy: failed to read {}: {}", path, e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Puts the window on a monitor: the one `monitor` picks (see
+    /// `pick_monitor`), or else the one it was on last time. Windows go
+    /// back where they were if that's still on the monitor, or in the
+    /// middle of it if not.
+    pub fn apply(&self, window: &Window, monitor: Option<&str>, fullscreen: bool) {
+        let monitors: Vec<MonitorHandle> = window.available_monitors().collect();
+        let names: Vec<String> = monitors.iter().map(|monitor| monitor.name().unwrap_or_default()).collect();
+
+        let chosen = match monitor {
+            Some(query) => pick_monitor(&names, query).or_else(|| {
+                let available: Vec<String> = names.iter().enumerate()
+                    .map(|(i, name)| format!("{}: {}", i, name))
+                    .collect();
+                eprintln!("chippy: there's no monitor {:?}, there's {}", query, available.join(", "));
+                None
+            }),
+            None => self.monitor.as_ref().and_then(|name| names.iter().position(|other| other == name)),
+        };
+        let chosen = chosen.map(|i| monitors[i].clone());
+
+        if fullscreen {
+            window.set_fullscreen(Some(Fullscreen::Borderless(chosen)));
+            return;
+        }
+
+        let position = self.position.filter(|&position| match &chosen {
+            Some(monitor) => contains(monitor, position),
+            None => monitors.iter().any(|monitor| contains(monitor, position)),
+        });
+        match (position, chosen) {
+            (Some((x, y)), _) => window.set_outer_position(PhysicalPosition::new(x, y)),
+            (None, Some(monitor)) => {
+                let (origin, size, window_size) = (monitor.position(), monitor.size(), window.outer_size());
+                window.set_outer_position(PhysicalPosition::new(
+                    origin.x + (size.width as i32 - window_size.width as i32) / 2,
+                    origin.y + (size.height as i32 - window_size.height as i32) / 2));
+            },
+            (None, None) => (),
+        }
+    }
+
+    /// Remembers where the window is now. The position of a fullscreen
+    /// window isn't worth remembering, so the windowed one is kept.
+    pub fn update(&mut self, window: &Window) {
+        if let Some(name) = window.current_monitor().and_then(|monitor| monitor.name()) {
+            self.monitor = Some(name);
+        }
+        if window.fullscreen().is_none() {
+            if let Ok(position) = window.outer_position() {
+                self.position = Some((position.x, position.y));
+            }
+        }
+    }
+}
+
+/// Which of the monitors called `names` a query picks: a number counts
+/// from 0, anything else is looked for in the names.
+fn pick_monitor(names: &[String], query: &str) -> Option<usize> {
+    if let Ok(index) = query.parse::<usize>() {
+        return Some(index).filter(|&index| index < names.len());
+    }
+
+    let query = query.to_lowercase();
+    names.iter().position(|name| name.to_lowercase().contains(&query))
+}
+
+fn contains(monitor: &MonitorHandle, (x, y): (i32, i32)) -> bool {
+    let (origin, size) = (monitor.position(), monitor.size());
+    (origin.x..origin.x + size.width as i32).contains(&x) && (origin.y..origin.y + size.height as i32).contains(&y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monitors_are_picked_by_number_or_name() {
+        let names = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+
+        assert_eq!(pick_monitor(&names, "1"), Some(1));
+        assert_eq!(pick_monitor(&names, "2"), None);
+        assert_eq!(pick_monitor(&names, "hdmi"), Some(1));
+        assert_eq!(pick_monitor(&names, "VGA"), None);
+    }
+
+    #[test]
+    fn panel_windows_are_kept_by_name() {
+        let mut placement = Placement { position: Some((10, 20)), separate_panels: true, ..Placement::default() };
+        placement.panels.insert("memory".to_string(), (700, -40));
+
+        let read: Placement = toml::from_str(&toml::to_string(&placement).unwrap()).unwrap();
+        assert_eq!(read.panels.get("memory"), Some(&(700, -40)));
+        assert!(read.separate_panels);
+    }
+}
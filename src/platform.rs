@@ -0,0 +1,68 @@
+use core::fmt;
+use core::str::FromStr;
+
+use serde::{ Deserialize, Serialize };
+
+use crate::chip8::INSTRUCTIONS_PER_FRAME;
+
+/// The machine a game was written for. Later interpreters ran on faster
+/// hardware, and their games expect to get more done in a frame. Later
+/// platforms compare greater than the ones they extend.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    /// The original CHIP-8, on the COSMAC VIP and its successors.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP, on the HP 48 calculators.
+    Schip,
+    /// XO-CHIP, Octo's extension, which assumes a modern computer.
+    XoChip,
+}
+
+impl Platform {
+    /// How many instructions a frame games for the platform usually
+    /// need, unless the user says otherwise.
+    pub fn instructions_per_frame(self) -> u32 {
+        match self {
+            Platform::Chip8 => INSTRUCTIONS_PER_FRAME,
+            Platform::Schip => 30,
+            Platform::XoChip => 200,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "chip8"),
+            Platform::Schip => write!(f, "schip"),
+            Platform::XoChip => write!(f, "xochip"),
+        }
+    }
+}
+
+/// A name that isn't one of the platforms.
+#[derive(Debug)]
+pub struct UnknownPlatform;
+
+impl fmt::Display for UnknownPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected chip8, schip or xochip")
+    }
+}
+
+impl core::error::Error for UnknownPlatform {}
+
+impl FromStr for Platform {
+    type Err = UnknownPlatform;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::Schip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(UnknownPlatform),
+        }
+    }
+}
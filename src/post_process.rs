@@ -0,0 +1,368 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+use naga::valid::{ Capabilities, ValidationFlags, Validator };
+
+use pixels::{ Pixels, PixelsContext, wgpu };
+use pixels::wgpu::util::DeviceExt;
+
+/// Where shaders of your own go, relative to the working directory.
+pub const SHADERS_DIR: &str = "shaders";
+
+/// What every shader is put after, see the comments in it for what
+/// shaders get to work with.
+const PRELUDE: &str = include_str!("shaders/prelude.wgsl");
+
+/// The shaders that come with Chippy, which ones in `SHADERS_DIR` with
+/// the same name replace.
+const BUNDLED: &[(&str, &str)] = &[
+    ("crt", include_str!("shaders/crt.wgsl")),
+    ("scanlines", include_str!("shaders/scanlines.wgsl")),
+];
+
+/// The only inputs the prelude's vertex shader has for `fs_main`.
+const FRAGMENT_LOCATIONS: &[u32] = &[0];
+
+/// A WGSL post-process shader, checked and ready to go on the GPU.
+pub struct Shader {
+    pub name: String,
+    /// The shader with the prelude in front of it.
+    source: String,
+}
+
+impl Shader {
+    /// Checks a shader, so mistakes in it come back as errors instead of
+    /// taking wgpu down. Errors are about lines of `source`, not of the
+    /// prelude in front of it.
+    pub fn new(name: &str, source: &str) -> Result<Self, String> {
+        let full = format!("{}\n{}", PRELUDE, source);
+        let prelude_lines = PRELUDE.lines().count() + 1;
+
+        let module = naga::front::wgsl::parse_str(&full).map_err(|e| {
+            let (line, column) = e.location(&full);
+            format!("line {}, column {}: {}", line.saturating_sub(prelude_lines), column, e)
+        })?;
+
+        Validator::new(ValidationFlags::all(), Capabilities::empty())
+            .validate(&module)
+            .map_err(|e| {
+                let mut message = e.to_string();
+                let mut source = std::error::Error::source(&e);
+                while let Some(cause) = source {
+                    message += &format!(": {}", cause);
+                    source = cause.source();
+                }
+                message
+            })?;
+
+        check_interface(&module)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            source: full,
+        })
+    }
+}
+
+/// Makes sure a shader only uses what the post-process pass gives it,
+/// which wgpu would otherwise only find out about by panicking.
+fn check_interface(module: &naga::Module) -> Result<(), String> {
+    let entry_point = module.entry_points.iter()
+        .find(|entry_point| entry_point.name == "fs_main" && entry_point.stage == naga::ShaderStage::Fragment)
+        .ok_or("there's no [[stage(fragment)]] fn fs_main")?;
+
+    // only the prelude gets to bind resources.
+    let bindings = module.global_variables.iter().filter(|(_, global)| global.binding.is_some()).count();
+    if bindings != 3 {
+        return Err("shaders can't bind resources of their own".to_string());
+    }
+
+    // fs_main takes inputs one by one, or as a struct like VertexOutput.
+    let mut locations = Vec::new();
+    for argument in &entry_point.function.arguments {
+        match (&argument.binding, &module.types[argument.ty].inner) {
+            (Some(binding), _) => locations.push(binding),
+            (None, naga::TypeInner::Struct { members, .. }) => {
+                locations.extend(members.iter().filter_map(|member| member.binding.as_ref()));
+            },
+            (None, _) => (),
+        }
+    }
+    for binding in locations {
+        if let naga::Binding::Location { location, .. } = binding {
+            if !FRAGMENT_LOCATIONS.contains(location) {
+                return Err(format!("fs_main reads location {}, only in.uv at location 0 is there", location));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The bundled shaders, and the ones in `dir` that check out. Shaders
+/// that don't are reported and left out.
+pub fn load_shaders(dir: &Path) -> Vec<Shader> {
+    let mut shaders: Vec<Shader> = BUNDLED.iter()
+        .map(|(name, source)| Shader::new(name, source).expect("bundled shaders are valid"))
+        .collect();
+
+    let mut paths = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "wgsl"))
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            eprintln!("chippy: failed to read {}: {}", dir.display(), e);
+            Vec::new()
+        },
+    };
+    paths.sort();
+
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let shader = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| Shader::new(&name, &source));
+
+        match shader {
+            Ok(shader) => match shaders.iter_mut().find(|other| other.name == shader.name) {
+                Some(other) => *other = shader,
+                None => shaders.push(shader),
+            },
+            Err(e) => eprintln!("chippy: {} is left out: {}", path.display(), e),
+        }
+    }
+
+    shaders
+}
+
+/// Runs a shader over the emulator's output: the frame is scaled to the
+/// window in a texture of its own, which the shader draws to the window.
+///
+/// It's made for a surface of one size, with one `Pixels`, and has to be
+/// made again for another.
+pub struct PostProcess {
+    texture: wgpu::TextureView,
+    locals: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    resolution: (u32, u32),
+    /// The size of the CHIP-8 display in the frame.
+    display: (u32, u32),
+    started: Instant,
+}
+
+impl PostProcess {
+    pub fn new(pixels: &Pixels, shader: &Shader, (width, height): (u32, u32), display: (u32, u32)) -> Self {
+        let device = pixels.device();
+        let format = pixels.render_texture_format();
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("chippy_post_process_texture"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let texture = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("chippy_post_process_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..wgpu::SamplerDescriptor::default()
+        });
+
+        let locals = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("chippy_post_process_locals"),
+            contents: &[0; LOCALS_SIZE],
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("chippy_post_process_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { filtering: true, comparison: false },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("chippy_post_process_bind_group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&texture) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: locals.as_entire_binding() },
+            ],
+        });
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some(&shader.name),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&shader.source)),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("chippy_post_process_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("chippy_post_process_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            texture,
+            locals,
+            bind_group,
+            pipeline,
+            resolution: (width, height),
+            display,
+            started: Instant::now(),
+        }
+    }
+
+    /// Draws the frame to `render_target` through the shader. Meant to be
+    /// called from `Pixels::render_with`.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, render_target: &wgpu::TextureView, context: &PixelsContext) {
+        context.scaling_renderer.render(encoder, &self.texture);
+
+        let frame = (context.texture_extent.width, context.texture_extent.height);
+        let locals = locals(
+            self.resolution,
+            self.started.elapsed().as_secs_f32(),
+            display_rect(context.scaling_renderer.clip_rect(), frame, self.display, self.resolution));
+        context.queue.write_buffer(&self.locals, 0, &locals);
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("chippy_post_process_pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// The size of `Locals` in the prelude: the resolution and time, padded
+/// to line the display up on 16 bytes, then the display.
+const LOCALS_SIZE: usize = 32;
+
+fn locals((width, height): (u32, u32), time: f32, display: [f32; 4]) -> [u8; LOCALS_SIZE] {
+    let values = [width as f32, height as f32, time, 0.0, display[0], display[1], display[2], display[3]];
+
+    let mut bytes = [0; LOCALS_SIZE];
+    for (chunk, value) in bytes.chunks_exact_mut(4).zip(values.iter()) {
+        chunk.copy_from_slice(&value.to_ne_bytes());
+    }
+    bytes
+}
+
+/// Where the display is on the surface, in uvs. The frame fills
+/// `clip_rect` of the surface, and the display is in its top left corner.
+fn display_rect(clip_rect: (u32, u32, u32, u32), frame: (u32, u32), display: (u32, u32), surface: (u32, u32)) -> [f32; 4] {
+    let (x, y, width, height) = clip_rect;
+    let (surface_width, surface_height) = (surface.0 as f32, surface.1 as f32);
+
+    [
+        x as f32 / surface_width,
+        y as f32 / surface_height,
+        width as f32 * display.0 as f32 / frame.0 as f32 / surface_width,
+        height as f32 * display.1 as f32 / frame.1 as f32 / surface_height,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_shaders_check_out() {
+        for (name, source) in BUNDLED {
+            if let Err(e) = Shader::new(name, source) {
+                panic!("{}: {}", name, e);
+            }
+        }
+    }
+
+    #[test]
+    fn shader_errors_point_into_the_shader() {
+        let error = Shader::new("broken", "\n[[stage(fragment)]]\nfn fs_main( -> {}").err().unwrap();
+        assert!(error.starts_with("line 3,"), "{}", error);
+
+        let error = Shader::new("empty", "").err().unwrap();
+        assert!(error.contains("fs_main"), "{}", error);
+    }
+
+    #[test]
+    fn shaders_only_get_the_uv() {
+        let source = "[[stage(fragment)]]
+            fn fs_main([[location(1)]] tint: vec4<f32>) -> [[location(0)]] vec4<f32> {
+                return tint;
+            }";
+
+        assert!(Shader::new("tinted", source).is_err());
+    }
+
+    #[test]
+    fn the_display_is_found_on_the_surface() {
+        // a 640x560 frame, doubled and centred on a 1400x1120 surface.
+        let rect = display_rect((60, 0, 1280, 1120), (640, 560), (640, 320), (1400, 1120));
+
+        assert_eq!(rect, [60.0 / 1400.0, 0.0, 1280.0 / 1400.0, 640.0 / 1120.0]);
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+use chippy::chip8::{ VIDEO_HEIGHT, VIDEO_WIDTH };
+
+use crate::canvas::Canvas;
+use crate::config;
+
+/// How the display is turned into a picture.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayFilter {
+    /// Every frame as it is.
+    #[default]
+    None,
+    /// Every frame mixed with the one before it, which smooths out the
+    /// flicker of sprites being erased and redrawn with XOR.
+    Blend,
+}
+
+impl DisplayFilter {
+    /// The filter after this one, for cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            DisplayFilter::None => DisplayFilter::Blend,
+            DisplayFilter::Blend => DisplayFilter::None,
+        }
+    }
+}
+
+impl fmt::Display for DisplayFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisplayFilter::None => write!(f, "none"),
+            DisplayFilter::Blend => write!(f, "blend"),
+        }
+    }
+}
+
+/// The colors of the display, one for each combination of XO-CHIP
+/// planes a pixel can be lit on, named as in Octo so an author's colors
+/// can be copied over. Programs for the other platforms only draw on
+/// the first plane, in `fill`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Palette {
+    #[serde(deserialize_with = "config::color")]
+    pub background: [u8; 4],
+    #[serde(deserialize_with = "config::color")]
+    pub fill: [u8; 4],
+    #[serde(deserialize_with = "config::color")]
+    pub fill2: [u8; 4],
+    #[serde(deserialize_with = "config::color")]
+    pub blend: [u8; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::CHIPPY
+    }
+}
+
+impl Palette {
+    pub const CHIPPY: Self = Self {
+        background: [0x48, 0xB2, 0xE8, 0xFF],
+        fill: [0x5E, 0x48, 0xE8, 0xFF],
+        fill2: [0xE8, 0xA0, 0x48, 0xFF],
+        blend: [0x1E, 0x1E, 0x28, 0xFF],
+    };
+
+    /// The palettes that come with Chippy: its own, then Octo's.
+    pub const NAMED: &'static [(&'static str, Palette)] = &[
+        ("chippy", Self::CHIPPY),
+        ("octo", Self::rgb(0x996600, 0xFFCC00, 0xFF6600, 0x662200)),
+        ("lcd", Self::rgb(0xF9FFB3, 0x3D8026, 0xABCC47, 0x00131A)),
+        ("hotdog", Self::rgb(0x000000, 0xFF0000, 0xFFFF00, 0xFFFFFF)),
+        ("gray", Self::rgb(0xAAAAAA, 0x000000, 0xFFFFFF, 0x666666)),
+        ("cga0", Self::rgb(0x000000, 0x00FF00, 0xFF0000, 0xFFFF00)),
+        ("cga1", Self::rgb(0x000000, 0xFF00FF, 0x00FFFF, 0xFFFFFF)),
+    ];
+
+    const fn rgb(background: u32, fill: u32, fill2: u32, blend: u32) -> Self {
+        const fn color(rgb: u32) -> [u8; 4] {
+            [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 0xFF]
+        }
+        Self { background: color(background), fill: color(fill), fill2: color(fill2), blend: color(blend) }
+    }
+
+    /// A palette that comes with Chippy, by name.
+    pub fn named(name: &str) -> Option<Self> {
+        Self::NAMED.iter().find(|(named, _)| named.eq_ignore_ascii_case(name)).map(|&(_, palette)| palette)
+    }
+
+    /// The color of a display pixel, which is the planes it's lit on.
+    pub fn color(&self, pixel: u8) -> [u8; 4] {
+        match pixel & 0b11 {
+            0 => self.background,
+            1 => self.fill,
+            2 => self.fill2,
+            _ => self.blend,
+        }
+    }
+}
+
+/// How much darker the lines of the pixel grid are than the pixels.
+const GRID_SHADE: f32 = 0.2;
+
+/// Draws the display into frames, remembering what the emulated frame
+/// before showed for the filters that need it.
+pub struct Presenter {
+    pub filter: DisplayFilter,
+    /// How much of the current frame goes into a blended one, the rest
+    /// is the previous frame.
+    pub blend_weight: f32,
+    /// Whether a line a frame pixel wide is drawn between the pixels.
+    pub grid: bool,
+    /// Whether the display is framed by a border, and how wide and what
+    /// color the border is. The display shrinks to fit inside it.
+    pub border: bool,
+    pub border_width: u32,
+    pub border_color: [u8; 4],
+    pub palette: Palette,
+    // the display at the end of the emulated frame before the one last
+    // drawn, and that one, with its number.
+    previous: Vec<u8>,
+    latest: Vec<u8>,
+    latest_frame: Option<u64>,
+}
+
+impl Presenter {
+    pub fn new(filter: DisplayFilter, blend_weight: f32) -> Self {
+        Self {
+            filter,
+            blend_weight: blend_weight.clamp(0.0, 1.0),
+            grid: false,
+            border: false,
+            border_width: 0,
+            border_color: Palette::CHIPPY.background,
+            palette: Palette::default(),
+            previous: vec![0; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize],
+            latest: vec![0; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize],
+            latest_frame: None,
+        }
+    }
+
+    /// Draws the display as emulated frame `frame` left it, every pixel
+    /// `scale` by `scale` pixels, at the top of the frame `left` pixels
+    /// from the left. With a border the pixels are smaller, and the
+    /// display is centered in the same space. Blending mixes in the
+    /// frame before `frame`, however often the same one is drawn.
+    pub fn draw(&mut self, canvas: &mut Canvas, display: &[u8], frame: u64, left: i32, scale: u32) {
+        if self.latest_frame != Some(frame) {
+            self.previous.copy_from_slice(&self.latest);
+            self.latest_frame = Some(frame);
+        }
+        self.latest.copy_from_slice(display);
+
+        let (width, height) = (VIDEO_WIDTH * scale, VIDEO_HEIGHT * scale);
+        let (left, top, scale) = if self.border && self.border_width > 0 {
+            canvas.fill_rect(left, 0, width, height, self.border_color);
+
+            let inner = |size: u32, pixels: u32| size.saturating_sub(2 * self.border_width) / pixels;
+            let scale = inner(width, VIDEO_WIDTH).min(inner(height, VIDEO_HEIGHT)).max(1);
+            let left = left + (width - VIDEO_WIDTH * scale) as i32 / 2;
+            let top = (height - VIDEO_HEIGHT * scale) as i32 / 2;
+            (left, top, scale)
+        } else {
+            (left, 0, scale)
+        };
+        // a grid needs pixels big enough to leave some of them showing.
+        let grid = self.grid && scale > 2;
+
+        for y in 0..VIDEO_HEIGHT {
+            for x in 0..VIDEO_WIDTH {
+                let i = (y * VIDEO_WIDTH + x) as usize;
+                let color = match self.filter {
+                    DisplayFilter::None => self.palette.color(display[i]),
+                    DisplayFilter::Blend => mix(self.palette.color(self.previous[i]),
+                        self.palette.color(display[i]), self.blend_weight),
+                };
+
+                let (x, y) = (left + (x * scale) as i32, top + (y * scale) as i32);
+                if grid {
+                    canvas.fill_rect(x, y, scale, scale, mix(color, [0, 0, 0, 0xFF], GRID_SHADE));
+                    canvas.fill_rect(x, y, scale - 1, scale - 1, color);
+                } else {
+                    canvas.fill_rect(x, y, scale, scale, color);
+                }
+            }
+        }
+    }
+}
+
+/// The color `amount` of the way from one color to another.
+fn mix(from: [u8; 4], to: [u8; 4], amount: f32) -> [u8; 4] {
+    let mut color = [0; 4];
+    for (channel, (&from, &to)) in color.iter_mut().zip(from.iter().zip(to.iter())) {
+        *channel = (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blending_mixes_in_the_previous_frame() {
+        let mut frame = vec![0; (VIDEO_WIDTH * VIDEO_HEIGHT * 4) as usize];
+        let mut presenter = Presenter::new(DisplayFilter::Blend, 0.5);
+        let mut display = vec![1; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize];
+
+        presenter.draw(&mut Canvas::new(&mut frame, VIDEO_WIDTH, VIDEO_HEIGHT), &display, 0, 0, 1);
+        display[0] = 0;
+        presenter.draw(&mut Canvas::new(&mut frame, VIDEO_WIDTH, VIDEO_HEIGHT), &display, 1, 0, 1);
+
+        let palette = Palette::default();
+        assert_eq!(&frame[..4], &mix(palette.background, palette.fill, 0.5));
+        assert_eq!(&frame[4..8], &palette.fill);
+    }
+
+    #[test]
+    fn drawing_a_frame_again_still_mixes_in_the_one_before() {
+        let mut frame = vec![0; (VIDEO_WIDTH * VIDEO_HEIGHT * 4) as usize];
+        let mut presenter = Presenter::new(DisplayFilter::Blend, 0.5);
+        let mut display = vec![1; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize];
+
+        presenter.draw(&mut Canvas::new(&mut frame, VIDEO_WIDTH, VIDEO_HEIGHT), &display, 0, 0, 1);
+        display[0] = 0;
+        for _ in 0..2 {
+            presenter.draw(&mut Canvas::new(&mut frame, VIDEO_WIDTH, VIDEO_HEIGHT), &display, 1, 0, 1);
+        }
+
+        let palette = Palette::default();
+        assert_eq!(&frame[..4], &mix(palette.background, palette.fill, 0.5));
+
+        // once the next frame is drawn, frame 1 is the one mixed in.
+        presenter.draw(&mut Canvas::new(&mut frame, VIDEO_WIDTH, VIDEO_HEIGHT), &display, 2, 0, 1);
+        assert_eq!(&frame[..4], &palette.background);
+    }
+
+    #[test]
+    fn every_combination_of_planes_has_its_color() {
+        let mut frame = vec![0; (VIDEO_WIDTH * VIDEO_HEIGHT * 4) as usize];
+        let mut presenter = Presenter::new(DisplayFilter::None, 1.0);
+        presenter.palette = Palette::named("Octo").unwrap();
+        let mut display = vec![0; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize];
+        display[..4].copy_from_slice(&[0, 1, 2, 3]);
+
+        presenter.draw(&mut Canvas::new(&mut frame, VIDEO_WIDTH, VIDEO_HEIGHT), &display, 0, 0, 1);
+
+        assert_eq!(&frame[..16], &[
+            0x99, 0x66, 0x00, 0xFF,
+            0xFF, 0xCC, 0x00, 0xFF,
+            0xFF, 0x66, 0x00, 0xFF,
+            0x66, 0x22, 0x00, 0xFF,
+        ]);
+    }
+
+    #[test]
+    fn a_border_shrinks_the_display_to_fit() {
+        let (width, height) = (VIDEO_WIDTH * 4, VIDEO_HEIGHT * 4);
+        let mut frame = vec![0; (width * height * 4) as usize];
+        let mut presenter = Presenter::new(DisplayFilter::None, 1.0);
+        presenter.border = true;
+        presenter.border_width = 16;
+        presenter.border_color = [1, 2, 3, 0xFF];
+        let display = vec![1; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize];
+
+        presenter.draw(&mut Canvas::new(&mut frame, width, height), &display, 0, 0, 4);
+
+        // 3 by 3 pixels now, 32 frame pixels from the sides and 16 from the top.
+        let pixel = |x: u32, y: u32| &frame[((y * width + x) * 4) as usize..][..4];
+        assert_eq!(pixel(31, 64), &[1, 2, 3, 0xFF]);
+        assert_eq!(pixel(32, 15), &[1, 2, 3, 0xFF]);
+        assert_eq!(pixel(32, 16), &Palette::CHIPPY.fill);
+        assert_eq!(pixel(width - 33, height - 17), &Palette::CHIPPY.fill);
+        assert_eq!(pixel(width - 32, height - 17), &[1, 2, 3, 0xFF]);
+    }
+}
@@ -0,0 +1,87 @@
+use serde::{ Deserialize, Serialize };
+
+/// Behaviours that differ between CHIP-8 interpreters. Everything
+/// defaults to what the original COSMAC VIP interpreter did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    /// FX0A completes as soon as a key is held down, instead of waiting
+    /// for the key to be pressed and then released again.
+    pub fx0a_on_press: bool,
+    /// FX1E sets VF to 1 when I overflows past 0xFFF and to 0 otherwise,
+    /// like the Amiga interpreter. Spacefight 2091! relies on this.
+    pub fx1e_overflow_vf: bool,
+    /// Memory kept between runs as if it were battery-backed, so homebrew
+    /// games can save high scores. No interpreter did this; by convention
+    /// a game that wants it keeps its saves at 0xE00 to 0xFFF, past any
+    /// ROM that fits in 3.5K, and finds them zero the first time it runs.
+    pub battery_ram: Option<MemoryRange>,
+    /// At most this many sprites are drawn a frame (between timer ticks),
+    /// a DXYN past that waiting for the next one, the way interpreters on
+    /// slow hardware held games back. Some games are only as hard as they
+    /// were meant to be at that pace.
+    pub sprites_per_frame: Option<u32>,
+    /// Where CXKK's random bytes come from. Some games play noticeably
+    /// differently with the VIP's.
+    pub random: Random,
+}
+
+/// How CXKK rolls its random byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Random {
+    /// A modern pseudo-random number generator, seeded from the OS.
+    #[default]
+    Prng,
+    /// Like the VIP interpreter, which added a byte of its own code, picked
+    /// by the last number rolled, to a count the display interrupt stepped
+    /// every frame. Numbers depend on the frame they're rolled in, and
+    /// repeat from run to run. The interpreter's code isn't in memory here,
+    /// so the program's first page stands in for it.
+    Vip,
+    /// 0, 1, 2 and so on, the same every run, for tests.
+    Sequence,
+}
+
+/// Addresses from `start` to `end`, both included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl MemoryRange {
+    /// The conventional range for battery-backed memory.
+    pub const BATTERY: MemoryRange = MemoryRange { start: 0xE00, end: 0xFFF };
+}
+
+/// The quirks of well-known interpreters, by name.
+pub const PROFILES: &[(&str, Quirks)] = &[
+    ("vip", Quirks {
+        fx0a_on_press: false, fx1e_overflow_vf: false, battery_ram: None, sprites_per_frame: None, random: Random::Prng,
+    }),
+    ("amiga", Quirks {
+        fx0a_on_press: false, fx1e_overflow_vf: true, battery_ram: None, sprites_per_frame: None, random: Random::Prng,
+    }),
+    // the VIP with the conventional battery-backed memory, for homebrew.
+    ("homebrew", Quirks {
+        fx0a_on_press: false, fx1e_overflow_vf: false, battery_ram: Some(MemoryRange::BATTERY), sprites_per_frame: None,
+        random: Random::Prng,
+    }),
+    // the VIP drawing a sprite a frame, as it did waiting for the display,
+    // and rolling its own random numbers.
+    ("vip_paced", Quirks {
+        fx0a_on_press: false, fx1e_overflow_vf: false, battery_ram: None, sprites_per_frame: Some(1), random: Random::Vip,
+    }),
+    // random numbers that are the same every run, for testing games.
+    ("fixed_random", Quirks {
+        fx0a_on_press: false, fx1e_overflow_vf: false, battery_ram: None, sprites_per_frame: None, random: Random::Sequence,
+    }),
+];
+
+impl Quirks {
+    /// One of the built-in `PROFILES`.
+    pub fn profile(name: &str) -> Option<Self> {
+        PROFILES.iter().find(|(profile, _)| *profile == name).map(|&(_, quirks)| quirks)
+    }
+}
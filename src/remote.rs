@@ -0,0 +1,193 @@
+use std::io;
+use std::net::{ Ipv4Addr, SocketAddr, TcpListener, TcpStream };
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::thread;
+
+use tungstenite::Message;
+use tungstenite::handshake::server::{ ErrorResponse, Request as Handshake, Response };
+use tungstenite::http::StatusCode;
+
+use crate::command::{ Action, Reply, Session };
+
+/// An action from a client, and where its reply goes.
+type Request = (Action, Sender<Reply>);
+
+/// Lets external tools and test scripts drive the emulator over a
+/// WebSocket. Clients send actions as JSON text messages and get a
+/// JSON reply to each, see `command::Action` and `command::Reply`.
+///
+/// Actions are only carried out when the emulator gets round to them
+/// with `serve`, so clients see them in the order they're handled. There's
+/// no authentication, so it listens on localhost unless told otherwise,
+/// actions that touch files are refused (see `Action::allowed_remotely`),
+/// and browsers are only let in from pages on this machine, so a web
+/// page can't drive the emulator.
+pub struct RemoteControl {
+    requests: Receiver<Request>,
+    address: SocketAddr,
+}
+
+impl RemoteControl {
+    /// Starts listening on `port` on localhost, or on every interface
+    /// if `public`.
+    pub fn start(port: u16, public: bool) -> io::Result<Self> {
+        let host = if public { Ipv4Addr::UNSPECIFIED } else { Ipv4Addr::LOCALHOST };
+        let listener = TcpListener::bind((host, port))?;
+        let address = listener.local_addr()?;
+        let (sender, requests) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+
+                // clients going away is business as usual, not an error.
+                thread::spawn(move || {
+                    let _ = serve_client(stream, sender);
+                });
+            }
+        });
+
+        Ok(Self { requests, address })
+    }
+
+    /// The address clients connect to.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Carries out the actions clients have sent since the last call.
+    pub fn serve(&self, session: &mut Session) {
+        for (action, reply) in self.requests.try_iter() {
+            let _ = reply.send(session.execute(action));
+        }
+    }
+}
+
+fn serve_client(stream: TcpStream, requests: Sender<Request>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut socket = tungstenite::accept_hdr(stream, check_origin)?;
+
+    loop {
+        let text = match socket.read()? {
+            Message::Text(text) => text,
+            Message::Close(_) => return Ok(()),
+            _ => continue,
+        };
+
+        let reply = match serde_json::from_str::<Action>(&text) {
+            Ok(action) if !action.allowed_remotely() => Reply::Error {
+                message: "that action touches files, which remote control isn't allowed to do".to_string(),
+            },
+            Ok(action) => {
+                let (sender, reply) = mpsc::channel();
+                if requests.send((action, sender)).is_err() {
+                    return Ok(());
+                }
+                reply.recv().unwrap_or_else(|_| Reply::Error {
+                    message: "the emulator stopped before replying".to_string(),
+                })
+            },
+            Err(e) => Reply::Error { message: format!("not an action: {}", e) },
+        };
+
+        socket.send(Message::Text(serde_json::to_string(&reply)?))?;
+    }
+}
+
+/// Refuses handshakes from web pages that aren't served from this
+/// machine. Browsers always say where a page is from, other clients
+/// usually don't say anything.
+// the signature is tungstenite's.
+#[allow(clippy::result_large_err)]
+fn check_origin(request: &Handshake, response: Response) -> Result<Response, ErrorResponse> {
+    let origin = request.headers().get("origin").map(|origin| origin.to_str().unwrap_or(""));
+    match origin {
+        Some(origin) if !is_local(origin.split("://").nth(1).unwrap_or("")) => {
+            let mut refusal = ErrorResponse::new(Some("pages from elsewhere can't use remote control".to_string()));
+            *refusal.status_mut() = StatusCode::FORBIDDEN;
+            Err(refusal)
+        },
+        _ => Ok(response),
+    }
+}
+
+/// Whether a host, as in a `Host` header or a URL, with or without a
+/// port, is this machine.
+pub fn is_local(host: &str) -> bool {
+    let host = host.split('/').next().unwrap_or("");
+    let name = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    };
+    matches!(name.to_ascii_lowercase().as_str(), "localhost" | "127.0.0.1" | "::1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tungstenite::client::IntoClientRequest;
+
+    use chippy::chip8::Chip8;
+
+    #[test]
+    fn clients_get_replies() {
+        let remote = RemoteControl::start(0, false).unwrap();
+        assert!(remote.address().ip().is_loopback());
+        let url = format!("ws://{}/", remote.address());
+        let client = thread::spawn(move || {
+            let (mut socket, _) = tungstenite::connect(url).unwrap();
+            socket.send(Message::Text(r#"{ "action": "press_key", "key": 5 }"#.to_string())).unwrap();
+            let pressed = socket.read().unwrap();
+            socket.send(Message::Text("nonsense".to_string())).unwrap();
+            let nonsense = socket.read().unwrap();
+            let mut forbidden = Vec::new();
+            let actions = [
+                r#"{ "action": "load_rom", "path": "/etc/passwd" }"#,
+                r#"{ "action": "export_state", "path": "/tmp/state.json" }"#,
+                r#"{ "action": "dump" }"#,
+                r#"{ "action": "bug_report" }"#,
+            ];
+            for action in actions {
+                socket.send(Message::Text(action.to_string())).unwrap();
+                forbidden.push(socket.read().unwrap().into_text().unwrap());
+            }
+            (pressed.into_text().unwrap(), nonsense.into_text().unwrap(), forbidden)
+        });
+
+        let mut session = Session::new(Chip8::new(), "test");
+        while !client.is_finished() {
+            remote.serve(&mut session);
+        }
+        let (pressed, nonsense, forbidden) = client.join().unwrap();
+
+        assert!(session.chippy.keypad[5]);
+        assert_eq!(session.source, "test");
+        assert_eq!(pressed, r#"{"reply":"done"}"#);
+        assert!(nonsense.starts_with(r#"{"reply":"error""#));
+        for reply in forbidden {
+            assert!(reply.contains("isn't allowed"), "{}", reply);
+        }
+    }
+
+    #[test]
+    fn pages_from_elsewhere_are_refused() {
+        let remote = RemoteControl::start(0, false).unwrap();
+        let request = |origin: &str| {
+            let mut request = format!("ws://{}/", remote.address()).into_client_request().unwrap();
+            request.headers_mut().insert("origin", origin.parse().unwrap());
+            request
+        };
+
+        assert!(tungstenite::connect(request("https://example.com")).is_err());
+        assert!(tungstenite::connect(request("http://localhost:8000")).is_ok());
+    }
+
+    #[test]
+    fn local_hosts_are_told_apart() {
+        assert!(is_local("localhost"));
+        assert!(is_local("127.0.0.1:8065"));
+        assert!(is_local("[::1]:8065"));
+        assert!(!is_local("localhost.example.com"));
+        assert!(!is_local("192.168.1.2:8065"));
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::VecDeque;
+
+use crate::chip8::Chip8;
+use crate::state::MachineState;
+
+/// How many snapshots the rewind buffer holds.
+pub const REWIND_SNAPSHOTS: usize = 600;
+
+/// How many frames run between snapshots, so rewinding goes back the
+/// same time whatever the speed.
+pub const REWIND_INTERVAL: u32 = 30;
+
+/// A ring buffer of compressed snapshots that the emulator can step
+/// back through, oldest snapshots making room for new ones.
+pub struct Rewind {
+    snapshots: VecDeque<Vec<u8>>,
+    countdown: u32,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rewind {
+    pub fn new() -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(REWIND_SNAPSHOTS),
+            countdown: REWIND_INTERVAL,
+        }
+    }
+
+    /// Called after every frame, takes a snapshot every
+    /// `REWIND_INTERVAL` of them.
+    pub fn record(&mut self, chippy: &Chip8) {
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return;
+        }
+        self.countdown = REWIND_INTERVAL;
+
+        if self.snapshots.len() == REWIND_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(chippy.save_state().to_bytes());
+    }
+
+    /// Puts the machine back to the most recent snapshot, and forgets
+    /// it. Returns false when there's nothing left to rewind to.
+    pub fn step_back(&mut self, chippy: &mut Chip8) -> bool {
+        let snapshot = match self.snapshots.pop_back() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+
+        let state = MachineState::from_bytes(&snapshot).expect("rewind snapshots are always valid");
+        chippy.load_state(&state);
+        self.countdown = REWIND_INTERVAL;
+
+        true
+    }
+}
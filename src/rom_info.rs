@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+
+use chippy::platform::Platform;
+
+/// The index of known ROMs that comes with Chippy.
+const INDEX: &str = include_str!("roms.toml");
+
+/// What a ROM is by its contents, whatever the file is called.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomId {
+    /// The SHA-1 of the ROM, in hex.
+    pub sha1: String,
+    pub crc32: u32,
+}
+
+impl RomId {
+    pub fn of(rom: &[u8]) -> Self {
+        Self {
+            sha1: sha1_smol::Sha1::from(rom).digest().to_string(),
+            crc32: crc32fast::hash(rom),
+        }
+    }
+}
+
+/// What the index says about a ROM.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub year: Option<u16>,
+    /// The keys to play with, e.g. "W left, E right".
+    pub keys: Option<String>,
+    pub platform: Option<Platform>,
+    /// How many sprites it should draw a frame, for a game paced by a slow
+    /// interpreter (see `Quirks::sprites_per_frame`).
+    pub sprites_per_frame: Option<u32>,
+}
+
+impl RomInfo {
+    /// Looks a ROM up in the bundled index.
+    pub fn lookup(id: &RomId) -> Option<Self> {
+        Self::by_sha1(&id.sha1)
+    }
+
+    pub fn by_sha1(sha1: &str) -> Option<Self> {
+        let mut index: HashMap<String, RomInfo> = toml::from_str(INDEX).expect("the ROM index is valid TOML");
+        index.remove(sha1)
+    }
+}
+
+/// The title, then the author and year if they're known, e.g.
+/// `Tetris (Fran Dachille, 1991)`.
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        match (&self.author, self.year) {
+            (Some(author), Some(year)) => write!(f, " ({}, {})", author, year),
+            (Some(author), None) => write!(f, " ({})", author),
+            (None, Some(year)) => write!(f, " ({})", year),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_index_knows_the_test_roms() {
+        let rom = std::fs::read("test_roms/Tetris [Fran Dachille, 1991].ch8").unwrap();
+        let id = RomId::of(&rom);
+        assert_eq!(id.crc32, 0x0CE7_0772);
+
+        let info = RomInfo::lookup(&id).unwrap();
+        assert_eq!(info.to_string(), "Tetris (Fran Dachille, 1991)");
+        assert!(RomInfo::lookup(&RomId::of(&[0x12, 0x00])).is_none());
+    }
+
+    #[test]
+    fn the_index_is_keyed_by_sha1() {
+        let index: HashMap<String, RomInfo> = toml::from_str(INDEX).unwrap();
+        for sha1 in index.keys() {
+            assert!(sha1.len() == 40 && sha1.chars().all(|c| c.is_ascii_hexdigit()), "{:?} isn't a SHA-1", sha1);
+        }
+    }
+}
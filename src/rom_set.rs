@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::rom_info::{ RomId, RomInfo };
+
+/// The ROMs that can be downloaded, which come with Chippy.
+const SET: &str = include_str!("rom_set.toml");
+
+/// A ROM that can be downloaded, and what it has to hash to.
+#[derive(Debug, Deserialize)]
+pub struct RomEntry {
+    /// The name it's saved under.
+    pub file: String,
+    pub url: String,
+    pub sha1: String,
+}
+
+#[derive(Deserialize)]
+struct RomSet {
+    rom: Vec<RomEntry>,
+}
+
+/// Where a ROM of the set stands in a directory.
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Fetched,
+    Missing,
+    /// There's a file by its name, but it isn't the ROM.
+    Different,
+}
+
+impl RomEntry {
+    /// What the ROM index says it is.
+    pub fn info(&self) -> Option<RomInfo> {
+        RomInfo::by_sha1(&self.sha1)
+    }
+
+    pub fn status(&self, dir: &Path) -> Status {
+        match fs::read(dir.join(&self.file)) {
+            Ok(bytes) if RomId::of(&bytes).sha1 == self.sha1 => Status::Fetched,
+            Ok(_) => Status::Different,
+            Err(_) => Status::Missing,
+        }
+    }
+
+    /// Checks a download is the ROM.
+    fn verify(&self, bytes: &[u8]) -> Result<(), String> {
+        let sha1 = RomId::of(bytes).sha1;
+        if sha1 != self.sha1 {
+            return Err(format!("{} has SHA-1 {}, expected {}, so it's been left out", self.url, sha1, self.sha1));
+        }
+
+        Ok(())
+    }
+}
+
+pub fn entries() -> Vec<RomEntry> {
+    let set: RomSet = toml::from_str(SET).expect("the ROM set is valid TOML");
+    set.rom
+}
+
+/// Downloads the ROMs of the set that aren't in `dir` yet. A ROM that
+/// fails to download or doesn't hash to what it should is reported and
+/// skipped, and so is a file by the same name that's something else.
+/// Fails if any ROM couldn't be fetched.
+pub fn fetch(dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+
+    let mut failed = 0;
+    for entry in entries() {
+        let path = dir.join(&entry.file);
+        match entry.status(dir) {
+            Status::Fetched => continue,
+            Status::Different => {
+                eprintln!("chippy: {} is already there, and isn't the ROM", path.display());
+                failed += 1;
+                continue;
+            },
+            Status::Missing => (),
+        }
+
+        let fetched = crate::read_rom(&entry.url)
+            .map_err(|e| format!("failed to download {}: {}", entry.file, e))
+            .and_then(|bytes| entry.verify(&bytes).map(|()| bytes))
+            .and_then(|bytes| fs::write(&path, bytes).map_err(|e| format!("failed to write {}: {}", path.display(), e)));
+        match fetched {
+            Ok(()) => eprintln!("chippy: fetched {}", path.display()),
+            Err(message) => {
+                eprintln!("chippy: {}", message);
+                failed += 1;
+            },
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} of the ROMs couldn't be fetched", failed).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_set_is_in_the_index() {
+        for entry in entries() {
+            assert!(entry.info().is_some(), "{} isn't in roms.toml", entry.file);
+        }
+    }
+
+    #[test]
+    fn downloads_are_checked_against_their_hash() {
+        let entry = entries().into_iter().find(|entry| entry.file == "test_opcode.ch8").unwrap();
+
+        assert_eq!(entry.verify(&fs::read("test_roms/test_opcode.ch8").unwrap()), Ok(()));
+        assert!(entry.verify(&[0x12, 0x00]).is_err());
+        assert_eq!(entry.status(Path::new("test_roms")), Status::Fetched);
+        assert_eq!(entry.status(Path::new("nowhere")), Status::Missing);
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::{ Duration, Instant };
+
+use chippy::chip8;
+
+/// How long a frame is: the timers count down 60 times a second.
+pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / chip8::TIMER_HZ as u64);
+
+/// The most frames run at once to catch up. Any further behind (after the
+/// window was dragged, say) and the rest are dropped, rather than the
+/// emulator racing to make up for them.
+const MAX_CATCH_UP: u32 = 4;
+
+/// Keeps emulation at 60 frames a second, however often the window is
+/// drawn or the event loop comes round.
+pub struct FrameScheduler {
+    next: Instant,
+}
+
+impl FrameScheduler {
+    pub fn new(now: Instant) -> Self {
+        Self { next: now }
+    }
+
+    /// How many frames are due by `now`, which count as run.
+    pub fn due(&mut self, now: Instant) -> u32 {
+        let mut frames = 0;
+        while self.next <= now && frames < MAX_CATCH_UP {
+            self.next += FRAME;
+            frames += 1;
+        }
+
+        if self.next <= now {
+            self.next = now + FRAME;
+        }
+        frames
+    }
+
+    /// When the next frame is due.
+    pub fn next_frame(&self) -> Instant {
+        self.next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_come_at_60hz_and_dont_pile_up() {
+        let start = Instant::now();
+        let mut scheduler = FrameScheduler::new(start);
+
+        assert_eq!(scheduler.due(start), 1);
+        assert_eq!(scheduler.due(start + FRAME / 2), 0);
+        assert_eq!(scheduler.due(start + FRAME * 3), 3);
+
+        // a second behind only catches up a few frames
+        assert_eq!(scheduler.due(start + Duration::from_secs(1)), MAX_CATCH_UP);
+        assert_eq!(scheduler.due(start + Duration::from_secs(1)), 0);
+    }
+}
@@ -0,0 +1,44 @@
+use chippy::chip8::{ VIDEO_HEIGHT, VIDEO_WIDTH };
+
+use crate::present::Palette;
+
+/// Encodes the display as a PNG in the palette's colors, with every
+/// pixel blown up to a `scale` by `scale` square.
+pub fn encode_png(display: &[u8], palette: &Palette, scale: u32) -> Vec<u8> {
+    let (width, height) = (VIDEO_WIDTH * scale, VIDEO_HEIGHT * scale);
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().expect("writing to a Vec can't fail");
+    writer.write_image_data(&rgb(display, palette, scale)).expect("the image is the size of the header");
+    writer.finish().expect("writing to a Vec can't fail");
+
+    bytes
+}
+
+/// Encodes the display as a binary PPM, like `encode_png`. PPMs are
+/// easy to read back anywhere, without a PNG decoder.
+pub fn encode_ppm(display: &[u8], palette: &Palette, scale: u32) -> Vec<u8> {
+    let (width, height) = (VIDEO_WIDTH * scale, VIDEO_HEIGHT * scale);
+
+    let mut bytes = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    bytes.extend(rgb(display, palette, scale));
+    bytes
+}
+
+/// The display as rows of RGB pixels, top to bottom.
+fn rgb(display: &[u8], palette: &Palette, scale: u32) -> Vec<u8> {
+    let (width, height) = (VIDEO_WIDTH * scale, VIDEO_HEIGHT * scale);
+
+    let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = display[((y / scale) * VIDEO_WIDTH + x / scale) as usize];
+            rgb.extend_from_slice(&palette.color(pixel)[..3]);
+        }
+    }
+    rgb
+}
@@ -0,0 +1,23 @@
+// An old monitor: the display bulges out, with scanlines and darker
+// corners. Whatever's around the display is left alone.
+
+let curvature: f32 = 0.08;
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let position = display_position(in.uv);
+
+    // bend the display outwards from its centre
+    let centered = position * 2.0 - vec2<f32>(1.0, 1.0);
+    let bent = centered * (1.0 + curvature * dot(centered, centered));
+    let curved = (bent + vec2<f32>(1.0, 1.0)) * 0.5;
+
+    let display = r_locals.display;
+    let uv = select(in.uv, display.xy + curved * display.zw, on_display(position));
+    let color = textureSample(r_texture, r_sampler, uv);
+
+    let scanline = 1.0 - 0.3 * smoothStep(0.5, 1.0, fract(curved.y * 32.0));
+    let vignette = clamp(1.0 - 0.3 * dot(centered, centered), 0.0, 1.0);
+    let shade = select(scanline * vignette, 0.0, !on_display(curved));
+    return vec4<f32>(color.rgb * select(1.0, shade, on_display(position)), color.a);
+}
@@ -0,0 +1,44 @@
+// Goes in front of every post-process shader. It covers the window with
+// a triangle and gives the fragment shader, `fs_main`:
+// - in.uv: where the fragment is, from (0, 0) top left to (1, 1) bottom right
+// - r_texture and r_sampler: the emulator's output, to sample at a uv
+// - r_locals.resolution: the size of the window in pixels
+// - r_locals.time: seconds since the shader was switched on
+// - r_locals.display: where the CHIP-8 display is, as x, y, width and
+//   height in uvs, to tell it apart from the panels around it
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[block]]
+struct Locals {
+    resolution: vec2<f32>;
+    time: f32;
+    display: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var r_texture: texture_2d<f32>;
+[[group(0), binding(1)]] var r_sampler: sampler;
+[[group(0), binding(2)]] var<uniform> r_locals: Locals;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Where a uv is on the CHIP-8 display, from (0, 0) top left to (1, 1)
+// bottom right.
+fn display_position(uv: vec2<f32>) -> vec2<f32> {
+    return (uv - r_locals.display.xy) / r_locals.display.zw;
+}
+
+fn on_display(position: vec2<f32>) -> bool {
+    return all(position >= vec2<f32>(0.0, 0.0)) && all(position < vec2<f32>(1.0, 1.0));
+}
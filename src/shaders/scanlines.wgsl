@@ -0,0 +1,10 @@
+// Darkens the bottom of every row of CHIP-8 pixels.
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(r_texture, r_sampler, in.uv);
+    let position = display_position(in.uv);
+
+    let shade = 1.0 - 0.35 * smoothStep(0.6, 0.9, fract(position.y * 32.0));
+    return vec4<f32>(color.rgb * select(1.0, shade, on_display(position)), color.a);
+}
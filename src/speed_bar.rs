@@ -0,0 +1,150 @@
+use std::time::{ Duration, Instant };
+
+use crate::canvas::{ self, Canvas };
+
+/// Height of the speed bar, in frame pixels.
+pub const BAR_HEIGHT: u32 = 32;
+
+/// The slowest and fastest speeds the bar goes between, in instructions
+/// a frame.
+pub const MIN_IPF: u32 = 1;
+pub const MAX_IPF: u32 = 1000;
+
+/// How often the instructions a second are counted again.
+const METER_PERIOD: Duration = Duration::from_secs(1);
+
+const TEXT_SCALE: u32 = 2;
+const LABEL_WIDTH: u32 = 112;
+const READOUT_WIDTH: u32 = 136;
+const KNOB_WIDTH: u32 = 8;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const TRACK: [u8; 4] = [0x3C, 0x3C, 0x64, 0xFF];
+const KNOB: [u8; 4] = [0x5E, 0x48, 0xE8, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+
+/// A slider over the bottom of the display that sets how many
+/// instructions run a frame, next to how many actually ran in the last
+/// second. The track is logarithmic, so slow speeds can be dialed in as
+/// finely as fast ones.
+pub struct SpeedBar {
+    pub visible: bool,
+    y: i32,
+    width: u32,
+}
+
+impl SpeedBar {
+    /// A bar whose bottom edge is at `bottom`, as wide as `width`.
+    pub fn new(bottom: u32, width: u32) -> Self {
+        Self { visible: false, y: (bottom - BAR_HEIGHT) as i32, width }
+    }
+
+    fn track(&self) -> (i32, u32) {
+        (LABEL_WIDTH as i32, self.width - LABEL_WIDTH - READOUT_WIDTH)
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, ipf: u32, ips: u64) {
+        canvas.fill_rect(0, self.y, self.width, BAR_HEIGHT, BACKGROUND);
+
+        let text_y = self.y + (BAR_HEIGHT - canvas::line_height(TEXT_SCALE)) as i32 / 2 + TEXT_SCALE as i32;
+        canvas.draw_text(8, text_y, &format!("IPF {}", ipf), TEXT_SCALE, TEXT);
+        canvas.draw_text((self.width - READOUT_WIDTH) as i32 + 8, text_y, &format!("{} IPS", ips), TEXT_SCALE, TEXT);
+
+        let (x, width) = self.track();
+        let middle = self.y + BAR_HEIGHT as i32 / 2;
+        canvas.fill_rect(x, middle - 2, width, 4, TRACK);
+
+        let knob = x + ((width - KNOB_WIDTH) as f64 * fraction(ipf)) as i32;
+        canvas.fill_rect(knob, self.y + 6, KNOB_WIDTH, BAR_HEIGHT - 12, KNOB);
+    }
+
+    /// The speed the point (x, y) on the track stands for, if the bar is
+    /// showing and the point is on it.
+    pub fn ipf_at(&self, x: usize, y: usize) -> Option<u32> {
+        let (track_x, width) = self.track();
+        let on_bar = (self.y..self.y + BAR_HEIGHT as i32).contains(&(y as i32));
+        if !self.visible || !on_bar {
+            return None;
+        }
+
+        let offset = (x as f64 - track_x as f64 - KNOB_WIDTH as f64 / 2.0) / (width - KNOB_WIDTH) as f64;
+        Some(ipf_from_fraction(offset.clamp(0.0, 1.0)))
+    }
+}
+
+/// How far along the track a speed is, from 0 to 1.
+fn fraction(ipf: u32) -> f64 {
+    let ipf = ipf.clamp(MIN_IPF, MAX_IPF) as f64;
+    (ipf / MIN_IPF as f64).ln() / (MAX_IPF as f64 / MIN_IPF as f64).ln()
+}
+
+fn ipf_from_fraction(fraction: f64) -> u32 {
+    let ipf = MIN_IPF as f64 * (MAX_IPF as f64 / MIN_IPF as f64).powf(fraction);
+    (ipf.round() as u32).clamp(MIN_IPF, MAX_IPF)
+}
+
+/// A quarter or so faster, for stepping the speed with a key.
+pub fn faster(ipf: u32) -> u32 {
+    (ipf + 1).max(ipf * 5 / 4).min(MAX_IPF)
+}
+
+/// A quarter or so slower.
+pub fn slower(ipf: u32) -> u32 {
+    ipf.saturating_sub(1).min(ipf * 4 / 5).max(MIN_IPF)
+}
+
+/// Counts how many instructions actually run a second, which falls short
+/// of the speed set when the debugger stops the program.
+pub struct IpsMeter {
+    since: Instant,
+    counted_from: u64,
+    /// Instructions run in the last whole second.
+    pub ips: u64,
+}
+
+impl IpsMeter {
+    pub fn new(now: Instant, instructions: u64) -> Self {
+        Self { since: now, counted_from: instructions, ips: 0 }
+    }
+
+    /// Takes the count of instructions run so far, and works out the
+    /// rate again once a second has gone by.
+    pub fn update(&mut self, now: Instant, instructions: u64) {
+        let elapsed = now.duration_since(self.since);
+        if elapsed < METER_PERIOD {
+            return;
+        }
+
+        self.ips = ((instructions - self.counted_from) as f64 / elapsed.as_secs_f64()).round() as u64;
+        self.since = now;
+        self.counted_from = instructions;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_track_runs_from_slowest_to_fastest() {
+        let bar = SpeedBar { visible: true, ..SpeedBar::new(320, 640) };
+        let (x, width) = bar.track();
+
+        assert_eq!(bar.ipf_at(0, 300), Some(MIN_IPF));
+        assert_eq!(bar.ipf_at((x as u32 + width) as usize, 300), Some(MAX_IPF));
+        assert_eq!(bar.ipf_at(x as usize, 100), None);
+        for ipf in [1, 10, 30, 200, 1000] {
+            assert_eq!(ipf_from_fraction(fraction(ipf)), ipf);
+        }
+    }
+
+    #[test]
+    fn keys_step_the_speed_within_bounds() {
+        assert_eq!(faster(1), 2);
+        assert_eq!(faster(100), 125);
+        assert_eq!(faster(MAX_IPF), MAX_IPF);
+        assert_eq!(slower(100), 80);
+        assert_eq!(slower(2), 1);
+        assert_eq!(slower(MIN_IPF), MIN_IPF);
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+
+use serde::{ Deserialize, Serialize };
+
+/// Where the speeds dialed in for games are kept, relative to the
+/// working directory.
+pub const SPEEDS_PATH: &str = "chippy-speeds.toml";
+
+/// The instructions a frame set for each game with the speed bar, by
+/// the game's key (see `Session::game_key`), so it runs that fast
+/// next time too.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Speeds {
+    games: BTreeMap<String, u32>,
+}
+
+impl Speeds {
+    /// Loads the speeds at `path`. Like the window placement, a missing or
+    /// broken file just means there are none yet.
+    pub fn load(path: &str) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                eprintln!("chippy: failed to read {}: {}", path, e);
+                return Self::default();
+            },
+        };
+
+        toml::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("chippy: failed to read {}: {}", path, e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, game: &str) -> Option<u32> {
+        self.games.get(game).copied()
+    }
+
+    pub fn set(&mut self, game: &str, ipf: u32) {
+        self.games.insert(game.to_string(), ipf);
+    }
+}
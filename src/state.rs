@@ -0,0 +1,831 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::format;
+
+#[cfg(feature = "std")]
+use std::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io::{ Read, Write };
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(feature = "std")]
+use flate2::Compression;
+#[cfg(feature = "std")]
+use flate2::read::DeflateDecoder;
+#[cfg(feature = "std")]
+use flate2::write::DeflateEncoder;
+
+use serde::{ Deserialize, Deserializer, Serialize, Serializer };
+use serde::de;
+
+use crate::chip8::{ VIDEO_HEIGHT, VIDEO_WIDTH };
+use crate::quirks::Quirks;
+
+const MEMORY_SIZE: usize = 4096;
+const DISPLAY_SIZE: usize = (VIDEO_WIDTH * VIDEO_HEIGHT) as usize;
+
+/// The first bytes of every binary save state.
+pub const MAGIC: &[u8; 4] = b"CHPY";
+
+/// The version of the save-state schema. It's written right after the
+/// magic in binary states and as `version` in JSON states, and goes up
+/// whenever the fields of `MachineState` change. Older versions are
+/// migrated when they're loaded, see `v1` to `v6`.
+///
+/// - 1: the first version, JSON states didn't have a version yet.
+/// - 2: added the quirks the machine was running with.
+/// - 3: added battery-backed memory to the quirks.
+/// - 4: display pixels became the XO-CHIP planes they're lit on, rather
+///   than 0xFF for lit, and the selected planes were added.
+/// - 5: added the sprites drawn a frame to the quirks.
+/// - 6: added where random numbers come from to the quirks.
+/// - 7: added the XO-CHIP audio pattern and pitch.
+pub const FORMAT_VERSION: u8 = 7;
+
+/// How many bytes of memory go on a line of hex in human-readable formats.
+const HEX_BYTES_PER_LINE: usize = 32;
+
+/// Everything needed to put a machine back exactly the way it was.
+///
+/// Human-readable formats (JSON) show memory as lines of hex and the
+/// display as lines of `.` for unlit pixels, `#` for the first plane,
+/// `+` for the second and `@` for both, so dumps can be read and diffed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MachineState {
+    pub program_counter: u16,
+    pub index: u16,
+    pub stack_pointer: u8,
+    pub stack: [u16; 16],
+    pub registers: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub awaited_key: Option<u8>,
+    #[serde(serialize_with = "serialize_memory", deserialize_with = "deserialize_memory")]
+    pub memory: Vec<u8>,
+    #[serde(serialize_with = "serialize_display", deserialize_with = "deserialize_display")]
+    pub display: Vec<u8>,
+    /// The planes drawing works on. JSON states from before planes were
+    /// saved only had the first.
+    #[serde(default = "first_plane")]
+    pub planes: u8,
+    /// The XO-CHIP audio pattern loaded with F002, a bit a sample, or
+    /// None while the plain buzzer plays.
+    #[serde(default)]
+    pub audio_pattern: Option<[u8; 16]>,
+    /// The XO-CHIP pitch set with FX3A, 64 for 4000 samples a second.
+    #[serde(default = "default_pitch")]
+    pub pitch: u8,
+    /// None for states migrated from before quirks were saved, which
+    /// leave the quirks of the machine they're loaded into alone.
+    pub quirks: Option<Quirks>,
+}
+
+/// How many differing bytes of memory `MachineState::diff` lists before
+/// it just counts the rest.
+const DIFF_MEMORY_BYTES: usize = 16;
+
+impl MachineState {
+    /// A checksum of everything the program can see, 32-bit FNV-1a, to
+    /// cheaply tell whether machines meant to run in lockstep have drifted
+    /// apart. The quirks are left out, they're settings rather than state.
+    pub fn checksum(&self) -> u32 {
+        let mut hash: u32 = 0x811C_9DC5;
+        let mut add = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+            }
+        };
+
+        add(&self.program_counter.to_be_bytes());
+        add(&self.index.to_be_bytes());
+        add(&[self.stack_pointer]);
+        for address in &self.stack {
+            add(&address.to_be_bytes());
+        }
+        add(&self.registers);
+        add(&[self.delay_timer, self.sound_timer, self.awaited_key.map_or(0xFF, |key| key), self.planes]);
+        add(&self.audio_pattern.map_or([0; 16], |pattern| pattern));
+        add(&[self.audio_pattern.is_some() as u8, self.pitch]);
+        add(&self.memory);
+        add(&self.display);
+        hash
+    }
+
+    /// What's different in `other`, a line each, like `V3: 0x05 vs 0x07`,
+    /// leaving out the quirks like `checksum` does.
+    pub fn diff(&self, other: &Self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut compare = |name: &str, this: String, that: String| {
+            if this != that {
+                lines.push(format!("{}: {} vs {}", name, this, that));
+            }
+        };
+
+        compare("PC", format!("{:#05x}", self.program_counter), format!("{:#05x}", other.program_counter));
+        compare("I", format!("{:#05x}", self.index), format!("{:#05x}", other.index));
+        compare("SP", format!("{}", self.stack_pointer), format!("{}", other.stack_pointer));
+        for (level, (this, that)) in self.stack.iter().zip(other.stack.iter()).enumerate() {
+            compare(&format!("stack {}", level), format!("{:#05x}", this), format!("{:#05x}", that));
+        }
+        for (register, (this, that)) in self.registers.iter().zip(other.registers.iter()).enumerate() {
+            compare(&format!("V{:X}", register), format!("{:#04x}", this), format!("{:#04x}", that));
+        }
+        compare("DT", format!("{}", self.delay_timer), format!("{}", other.delay_timer));
+        compare("ST", format!("{}", self.sound_timer), format!("{}", other.sound_timer));
+        compare("awaited key", format!("{:?}", self.awaited_key), format!("{:?}", other.awaited_key));
+        compare("planes", format!("{}", self.planes), format!("{}", other.planes));
+        compare("audio pattern", pattern_hex(self.audio_pattern), pattern_hex(other.audio_pattern));
+        compare("pitch", format!("{}", self.pitch), format!("{}", other.pitch));
+
+        let bytes: Vec<usize> = (0..self.memory.len().min(other.memory.len()))
+            .filter(|&address| self.memory[address] != other.memory[address])
+            .collect();
+        for &address in bytes.iter().take(DIFF_MEMORY_BYTES) {
+            lines.push(format!("memory {:#05x}: {:#04x} vs {:#04x}", address, self.memory[address], other.memory[address]));
+        }
+        if bytes.len() > DIFF_MEMORY_BYTES {
+            let more = bytes.len() - DIFF_MEMORY_BYTES;
+            lines.push(format!("and {} more byte{} of memory", more, if more == 1 { "" } else { "s" }));
+        }
+
+        let pixels = self.display.iter().zip(other.display.iter()).filter(|(this, that)| this != that).count();
+        if pixels > 0 {
+            lines.push(format!("{} pixel{} of the display", pixels, if pixels == 1 { "" } else { "s" }));
+        }
+
+        lines
+    }
+}
+
+/// A JSON state, with the schema version in front of the fields.
+#[cfg(feature = "std")]
+#[derive(Serialize)]
+struct Versioned<'a> {
+    version: u8,
+    #[serde(flatten)]
+    state: &'a MachineState,
+}
+
+/// Encoding and decoding need std, for compression and JSON.
+#[cfg(feature = "std")]
+impl MachineState {
+    /// Encodes the state in the binary save-state format: the magic, the
+    /// format version, then the state as deflated bincode. Most of memory
+    /// is usually zero, so states compress to a few hundred bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+
+        let mut encoder = DeflateEncoder::new(bytes, Compression::fast());
+        bincode::serialize_into(&mut encoder, self).expect("machine state is always serializable");
+        encoder.finish().expect("writing to a Vec can't fail")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if !is_binary(bytes) {
+            return Err("not a Chippy save state".into());
+        }
+
+        let version = bytes[MAGIC.len()];
+        check_version(version)?;
+
+        let mut decoded = Vec::new();
+        DeflateDecoder::new(&bytes[MAGIC.len() + 1..]).read_to_end(&mut decoded)?;
+
+        let state: Self = match version {
+            1 => bincode::deserialize::<v1::MachineState>(&decoded)?.into(),
+            2 => bincode::deserialize::<v2::MachineState>(&decoded)?.into(),
+            3 => bincode::deserialize::<v3::MachineState>(&decoded)?.into(),
+            4 => bincode::deserialize::<v4::MachineState>(&decoded)?.into(),
+            5 => bincode::deserialize::<v5::MachineState>(&decoded)?.into(),
+            6 => bincode::deserialize::<v6::MachineState>(&decoded)?.into(),
+            _ => bincode::deserialize(&decoded)?,
+        };
+        state.validate()?;
+
+        Ok(state)
+    }
+
+    /// Reads a state from a file in either format.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bytes = fs::read(path)?;
+
+        if is_binary(&bytes) {
+            Self::from_bytes(&bytes)
+        } else {
+            Self::from_json(std::str::from_utf8(&bytes)?)
+        }
+    }
+
+    /// Writes the state to a file in the binary format.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::File::create(path)?.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let versioned = Versioned { version: FORMAT_VERSION, state: self };
+        serde_json::to_string_pretty(&versioned).expect("machine state is always serializable")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+
+        // states from before versioning don't say which version they are.
+        let version = match value.as_object_mut().and_then(|fields| fields.remove("version")) {
+            Some(version) => version.as_u64()
+                .and_then(|version| u8::try_from(version).ok())
+                .ok_or("the state's version isn't a number")?,
+            None => 1,
+        };
+        check_version(version)?;
+
+        // a missing quirk, planes or sound read as their defaults in JSON, and
+        // lit pixels have always been `#`, so states from version 2 on
+        // read as they are.
+        let state: Self = match version {
+            1 => serde_json::from_value::<v1::MachineState>(value)?.into(),
+            _ => serde_json::from_value(value)?,
+        };
+        state.validate()?;
+
+        Ok(state)
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.stack_pointer as usize > self.stack.len() {
+            return Err(format!("stack pointer {} is past the end of the stack", self.stack_pointer).into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Refuses states written by a newer Chippy, rather than reading
+/// fields that mean something else.
+#[cfg(feature = "std")]
+fn check_version(version: u8) -> Result<(), Box<dyn Error>> {
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(format!(
+            "save state has format version {}, this version of Chippy reads versions 1 to {}",
+            version, FORMAT_VERSION).into());
+    }
+
+    Ok(())
+}
+
+/// A state as versions 2 to 6 wrote it. The fields didn't change, only
+/// the quirks in them and whether the display's planes were kept, which
+/// is `()` before version 4 as bincode writes nothing for it. Each old
+/// version below is this with its own quirks, migrated to the next.
+#[cfg(feature = "std")]
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct OldState<Q, P> {
+    program_counter: u16,
+    index: u16,
+    stack_pointer: u8,
+    stack: [u16; 16],
+    registers: [u8; 16],
+    delay_timer: u8,
+    sound_timer: u8,
+    awaited_key: Option<u8>,
+    #[serde(serialize_with = "serialize_memory", deserialize_with = "deserialize_memory")]
+    memory: Vec<u8>,
+    #[serde(serialize_with = "serialize_display", deserialize_with = "deserialize_display")]
+    display: Vec<u8>,
+    planes: P,
+    quirks: Option<Q>,
+}
+
+#[cfg(feature = "std")]
+impl<Q, P> OldState<Q, P> {
+    /// The state in the next version: its quirks migrated, with `planes`
+    /// and the display changed by `display`.
+    fn migrate<R: From<Q>, S>(self, planes: S, display: impl FnOnce(Vec<u8>) -> Vec<u8>) -> OldState<R, S> {
+        OldState {
+            program_counter: self.program_counter,
+            index: self.index,
+            stack_pointer: self.stack_pointer,
+            stack: self.stack,
+            registers: self.registers,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            awaited_key: self.awaited_key,
+            memory: self.memory,
+            display: display(self.display),
+            planes,
+            quirks: self.quirks.map(Into::into),
+        }
+    }
+}
+
+/// Version 1 states, from before quirks were saved. JSON states from
+/// then don't say their version.
+#[cfg(feature = "std")]
+mod v1 {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct MachineState {
+        pub program_counter: u16,
+        pub index: u16,
+        pub stack_pointer: u8,
+        pub stack: [u16; 16],
+        pub registers: [u8; 16],
+        pub delay_timer: u8,
+        pub sound_timer: u8,
+        pub awaited_key: Option<u8>,
+        #[serde(deserialize_with = "super::deserialize_memory")]
+        pub memory: Vec<u8>,
+        #[serde(deserialize_with = "super::deserialize_display")]
+        pub display: Vec<u8>,
+    }
+
+    impl From<MachineState> for super::v2::MachineState {
+        fn from(state: MachineState) -> Self {
+            Self {
+                program_counter: state.program_counter,
+                index: state.index,
+                stack_pointer: state.stack_pointer,
+                stack: state.stack,
+                registers: state.registers,
+                delay_timer: state.delay_timer,
+                sound_timer: state.sound_timer,
+                awaited_key: state.awaited_key,
+                memory: state.memory,
+                display: state.display,
+                planes: (),
+                quirks: None,
+            }
+        }
+    }
+
+    impl From<MachineState> for super::MachineState {
+        fn from(state: MachineState) -> Self {
+            super::v2::MachineState::from(state).into()
+        }
+    }
+}
+
+/// Version 2 states, whose quirks didn't have battery-backed memory yet.
+#[cfg(feature = "std")]
+mod v2 {
+    use serde::Deserialize;
+    #[cfg(test)]
+    use serde::Serialize;
+
+    #[derive(Deserialize)]
+    #[cfg_attr(test, derive(Serialize))]
+    pub struct Quirks {
+        pub fx0a_on_press: bool,
+        pub fx1e_overflow_vf: bool,
+    }
+
+    impl From<Quirks> for super::v3::Quirks {
+        fn from(quirks: Quirks) -> Self {
+            Self { fx0a_on_press: quirks.fx0a_on_press, fx1e_overflow_vf: quirks.fx1e_overflow_vf, battery_ram: None }
+        }
+    }
+
+    pub type MachineState = super::OldState<Quirks, ()>;
+
+    impl From<MachineState> for super::MachineState {
+        fn from(state: MachineState) -> Self {
+            state.migrate::<super::v3::Quirks, _>((), |display| display).into()
+        }
+    }
+}
+
+/// Version 3 states, whose lit pixels were 0xFF and which didn't have
+/// planes yet.
+#[cfg(feature = "std")]
+mod v3 {
+    pub use super::v4::Quirks;
+
+    pub type MachineState = super::OldState<Quirks, ()>;
+
+    impl From<MachineState> for super::MachineState {
+        fn from(state: MachineState) -> Self {
+            state.migrate::<Quirks, _>(1, super::on_first_plane).into()
+        }
+    }
+}
+
+/// Version 4 states, whose quirks didn't limit the sprites drawn a frame
+/// yet.
+#[cfg(feature = "std")]
+mod v4 {
+    use serde::Deserialize;
+    #[cfg(test)]
+    use serde::Serialize;
+
+    use crate::quirks::MemoryRange;
+
+    #[derive(Deserialize)]
+    #[cfg_attr(test, derive(Serialize))]
+    pub struct Quirks {
+        pub fx0a_on_press: bool,
+        pub fx1e_overflow_vf: bool,
+        pub battery_ram: Option<MemoryRange>,
+    }
+
+    impl From<Quirks> for super::v5::Quirks {
+        fn from(quirks: Quirks) -> Self {
+            Self {
+                fx0a_on_press: quirks.fx0a_on_press,
+                fx1e_overflow_vf: quirks.fx1e_overflow_vf,
+                battery_ram: quirks.battery_ram,
+                sprites_per_frame: None,
+            }
+        }
+    }
+
+    pub type MachineState = super::OldState<Quirks, u8>;
+
+    impl From<MachineState> for super::MachineState {
+        fn from(state: MachineState) -> Self {
+            let planes = state.planes;
+            state.migrate::<super::v5::Quirks, _>(planes, |display| display).into()
+        }
+    }
+}
+
+/// Version 5 states, whose quirks didn't say where random numbers come
+/// from yet.
+#[cfg(feature = "std")]
+mod v5 {
+    use serde::Deserialize;
+    #[cfg(test)]
+    use serde::Serialize;
+
+    use crate::quirks::{ MemoryRange, Random };
+
+    #[derive(Deserialize)]
+    #[cfg_attr(test, derive(Serialize))]
+    pub struct Quirks {
+        pub fx0a_on_press: bool,
+        pub fx1e_overflow_vf: bool,
+        pub battery_ram: Option<MemoryRange>,
+        pub sprites_per_frame: Option<u32>,
+    }
+
+    impl From<Quirks> for super::Quirks {
+        fn from(quirks: Quirks) -> Self {
+            Self {
+                fx0a_on_press: quirks.fx0a_on_press,
+                fx1e_overflow_vf: quirks.fx1e_overflow_vf,
+                battery_ram: quirks.battery_ram,
+                sprites_per_frame: quirks.sprites_per_frame,
+                random: Random::default(),
+            }
+        }
+    }
+
+    pub type MachineState = super::OldState<Quirks, u8>;
+
+    impl From<MachineState> for super::MachineState {
+        fn from(state: MachineState) -> Self {
+            let planes = state.planes;
+            state.migrate::<super::Quirks, _>(planes, |display| display).into()
+        }
+    }
+}
+
+/// Version 6 states, from before the XO-CHIP audio pattern and pitch.
+#[cfg(feature = "std")]
+mod v6 {
+    pub type MachineState = super::OldState<super::Quirks, u8>;
+
+    impl From<MachineState> for super::MachineState {
+        fn from(state: MachineState) -> Self {
+            Self {
+                program_counter: state.program_counter,
+                index: state.index,
+                stack_pointer: state.stack_pointer,
+                stack: state.stack,
+                registers: state.registers,
+                delay_timer: state.delay_timer,
+                sound_timer: state.sound_timer,
+                awaited_key: state.awaited_key,
+                memory: state.memory,
+                display: state.display,
+                planes: state.planes,
+                audio_pattern: None,
+                pitch: super::default_pitch(),
+                quirks: state.quirks,
+            }
+        }
+    }
+}
+
+/// A display from before planes, with every lit pixel on the first.
+#[cfg(feature = "std")]
+fn on_first_plane(display: Vec<u8>) -> Vec<u8> {
+    display.into_iter().map(|pixel| (pixel != 0) as u8).collect()
+}
+
+fn first_plane() -> u8 {
+    1
+}
+
+fn default_pitch() -> u8 {
+    64
+}
+
+/// An audio pattern for `MachineState::diff`, as hex.
+fn pattern_hex(pattern: Option<[u8; 16]>) -> String {
+    match pattern {
+        Some(pattern) => pattern.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        None => String::from("none"),
+    }
+}
+
+/// Whether some bytes start like a binary save state.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.len() > MAGIC.len() && bytes.starts_with(MAGIC)
+}
+
+fn serialize_memory<S: Serializer>(memory: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if !serializer.is_human_readable() {
+        return serializer.serialize_bytes(memory);
+    }
+
+    let lines: Vec<String> = memory.chunks(HEX_BYTES_PER_LINE)
+        .map(|line| line.iter().map(|byte| format!("{:02X}", byte)).collect())
+        .collect();
+    lines.serialize(serializer)
+}
+
+fn deserialize_memory<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let memory = if deserializer.is_human_readable() {
+        let lines = Vec::<String>::deserialize(deserializer)?;
+        let hex: String = lines.concat();
+
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+            return Err(de::Error::custom("memory isn't a list of hex bytes"));
+        }
+
+        (0..hex.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(de::Error::custom)?
+    } else {
+        serde_bytes_vec(deserializer)?
+    };
+
+    if memory.len() != MEMORY_SIZE {
+        return Err(de::Error::invalid_length(memory.len(), &"4096 bytes of memory"));
+    }
+
+    Ok(memory)
+}
+
+fn serialize_display<S: Serializer>(display: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if !serializer.is_human_readable() {
+        return serializer.serialize_bytes(display);
+    }
+
+    let rows: Vec<String> = display.chunks(VIDEO_WIDTH as usize)
+        .map(|row| row.iter().map(|&pixel| match pixel & 0b11 {
+            0 => '.',
+            1 => '#',
+            2 => '+',
+            _ => '@',
+        }).collect())
+        .collect();
+    rows.serialize(serializer)
+}
+
+fn deserialize_display<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let display = if deserializer.is_human_readable() {
+        let rows = Vec::<String>::deserialize(deserializer)?;
+
+        rows.concat().chars()
+            .map(|c| match c {
+                '.' => Ok(0),
+                '#' => Ok(1),
+                '+' => Ok(2),
+                '@' => Ok(3),
+                _ => Err(de::Error::custom(format!("unexpected {:?} in display", c))),
+            })
+            .collect::<Result<Vec<u8>, D::Error>>()?
+    } else {
+        serde_bytes_vec(deserializer)?
+    };
+
+    if display.len() != DISPLAY_SIZE {
+        return Err(de::Error::invalid_length(display.len(), &"2048 display pixels"));
+    }
+
+    Ok(display)
+}
+
+/// Reads back a byte buffer written with `serialize_bytes`.
+fn serde_bytes_vec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    struct BytesVisitor;
+
+    impl<'de> de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            f.write_str("a byte buffer")
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(bytes)
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::new();
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+
+    deserializer.deserialize_byte_buf(BytesVisitor)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::quirks::{ MemoryRange, Random };
+
+    use super::*;
+
+    fn sample() -> MachineState {
+        MachineState {
+            program_counter: 0x2A4,
+            index: 0x300,
+            stack_pointer: 1,
+            stack: [0x202, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            registers: [7; 16],
+            delay_timer: 3,
+            sound_timer: 0,
+            awaited_key: None,
+            memory: vec![0xAB; MEMORY_SIZE],
+            display: vec![1; DISPLAY_SIZE],
+            planes: 1,
+            audio_pattern: None,
+            pitch: 64,
+            quirks: Some(Quirks::default()),
+        }
+    }
+
+    #[test]
+    fn unversioned_json_is_migrated_from_version_1() {
+        let mut value: serde_json::Value = serde_json::from_str(&sample().to_json()).unwrap();
+        let fields = value.as_object_mut().unwrap();
+        fields.remove("version");
+        fields.remove("quirks");
+        fields.remove("planes");
+
+        let state = MachineState::from_json(&value.to_string()).unwrap();
+
+        assert_eq!(state, MachineState { quirks: None, ..sample() });
+    }
+
+    /// `sample` as an older version kept it, with its quirks, planes and
+    /// display.
+    fn old_sample<Q, P>(quirks: Q, planes: P, display: Vec<u8>) -> OldState<Q, P> {
+        let state = sample();
+        OldState {
+            program_counter: state.program_counter,
+            index: state.index,
+            stack_pointer: state.stack_pointer,
+            stack: state.stack,
+            registers: state.registers,
+            delay_timer: state.delay_timer,
+            sound_timer: state.sound_timer,
+            awaited_key: state.awaited_key,
+            memory: state.memory,
+            display,
+            planes,
+            quirks: Some(quirks),
+        }
+    }
+
+    /// A binary state, as a version of Chippy that wrote `version` would.
+    fn old_bytes(version: u8, state: &impl Serialize) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(version);
+        let mut encoder = DeflateEncoder::new(bytes, Compression::fast());
+        bincode::serialize_into(&mut encoder, state).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn binary_version_2_states_are_migrated() {
+        // lit pixels were 0xFF before planes.
+        let old: v2::MachineState = old_sample(
+            v2::Quirks { fx0a_on_press: true, fx1e_overflow_vf: false },
+            (), vec![0xFF; DISPLAY_SIZE]);
+
+        let quirks = Quirks { fx0a_on_press: true, ..Quirks::default() };
+        let state = MachineState::from_bytes(&old_bytes(2, &old)).unwrap();
+        assert_eq!(state, MachineState { quirks: Some(quirks), ..sample() });
+    }
+
+    #[test]
+    fn binary_version_3_states_are_migrated() {
+        let battery_ram = Some(MemoryRange { start: 0xF00, end: 0xFFF });
+        let old: v3::MachineState = old_sample(
+            v3::Quirks { fx0a_on_press: false, fx1e_overflow_vf: true, battery_ram },
+            (), vec![0xFF; DISPLAY_SIZE]);
+
+        let quirks = Quirks { fx1e_overflow_vf: true, battery_ram, ..Quirks::default() };
+        let state = MachineState::from_bytes(&old_bytes(3, &old)).unwrap();
+        assert_eq!(state, MachineState { quirks: Some(quirks), ..sample() });
+    }
+
+    #[test]
+    fn binary_version_4_states_are_migrated() {
+        let battery_ram = Some(MemoryRange { start: 0xF00, end: 0xFFF });
+        let old: v4::MachineState = old_sample(
+            v4::Quirks { fx0a_on_press: true, fx1e_overflow_vf: false, battery_ram },
+            3, vec![2; DISPLAY_SIZE]);
+
+        let quirks = Quirks { fx0a_on_press: true, battery_ram, ..Quirks::default() };
+        let state = MachineState::from_bytes(&old_bytes(4, &old)).unwrap();
+        assert_eq!(state, MachineState { planes: 3, display: vec![2; DISPLAY_SIZE], quirks: Some(quirks), ..sample() });
+    }
+
+    #[test]
+    fn binary_version_5_states_are_migrated() {
+        let old: v5::MachineState = old_sample(
+            v5::Quirks { fx0a_on_press: false, fx1e_overflow_vf: false, battery_ram: None, sprites_per_frame: Some(1) },
+            1, vec![1; DISPLAY_SIZE]);
+
+        let quirks = Quirks { sprites_per_frame: Some(1), random: Random::Prng, ..Quirks::default() };
+        let state = MachineState::from_bytes(&old_bytes(5, &old)).unwrap();
+        assert_eq!(state, MachineState { quirks: Some(quirks), ..sample() });
+    }
+
+    #[test]
+    fn binary_version_6_states_are_migrated() {
+        let quirks = Quirks { random: Random::Vip, ..Quirks::default() };
+        let old: v6::MachineState = old_sample(quirks, 2, vec![1; DISPLAY_SIZE]);
+
+        let state = MachineState::from_bytes(&old_bytes(6, &old)).unwrap();
+        assert_eq!(state, MachineState { planes: 2, quirks: Some(quirks), ..sample() });
+    }
+
+    #[test]
+    fn states_keep_the_audio_pattern_and_pitch() {
+        let state = MachineState { audio_pattern: Some([0xF0; 16]), pitch: 112, ..sample() };
+
+        assert_eq!(MachineState::from_bytes(&state.to_bytes()).unwrap(), state);
+        assert_eq!(MachineState::from_json(&state.to_json()).unwrap(), state);
+        assert_ne!(state.checksum(), sample().checksum());
+        assert_eq!(sample().diff(&state), vec![
+            "audio pattern: none vs f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0",
+            "pitch: 64 vs 112",
+        ]);
+    }
+
+    #[test]
+    fn diffs_list_what_the_checksum_sees() {
+        let mut other = sample();
+        other.registers[3] = 9;
+        other.memory[0x3A0] = 0;
+        other.display[0] = 0;
+        other.quirks = None;
+
+        assert_ne!(other.checksum(), sample().checksum());
+        assert_eq!(sample().diff(&other), vec![
+            "V3: 0x07 vs 0x09",
+            "memory 0x3a0: 0xab vs 0x00",
+            "1 pixel of the display",
+        ]);
+
+        other = MachineState { quirks: None, ..sample() };
+        assert_eq!(other.checksum(), sample().checksum());
+        assert!(sample().diff(&other).is_empty());
+    }
+
+    #[test]
+    fn states_from_newer_versions_are_refused() {
+        let json = sample().to_json().replacen(
+            &format!("\"version\": {}", FORMAT_VERSION),
+            &format!("\"version\": {}", FORMAT_VERSION + 1), 1);
+        assert!(MachineState::from_json(&json).is_err());
+
+        let mut bytes = sample().to_bytes();
+        bytes[MAGIC.len()] = FORMAT_VERSION + 1;
+        assert!(MachineState::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::io::{ self, Read, Write };
+use std::net::{ SocketAddr, TcpListener, TcpStream };
+use std::sync::{ Arc, Mutex };
+use std::sync::mpsc::{ self, Receiver, SyncSender };
+use std::thread;
+use std::time::Duration;
+
+use serde_json::json;
+use tungstenite::Message;
+
+use chippy::chip8::{ VIDEO_HEIGHT, VIDEO_WIDTH };
+
+use crate::present::Palette;
+
+/// The size of a streamed frame: two bits per pixel, the XO-CHIP planes
+/// it's lit on, rows top to bottom, the leftmost pixel of every byte in
+/// its highest two bits.
+pub const FRAME_SIZE: usize = (VIDEO_WIDTH * VIDEO_HEIGHT / 4) as usize;
+
+/// A page that connects back over a WebSocket and shows the stream.
+const VIEWER: &str = include_str!("stream_viewer.html");
+
+/// How long a new connection gets to start an HTTP request before
+/// it's treated as a raw TCP client.
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How many frames can wait for a spectator, a second's worth, before
+/// it's treated as too slow to keep up and let go.
+const FRAME_QUEUE: usize = 60;
+
+type Frame = Arc<Vec<u8>>;
+
+#[derive(Default)]
+struct Spectators {
+    clients: Vec<SyncSender<Frame>>,
+    last_frame: Option<Frame>,
+}
+
+/// Streams the display to anyone who connects, for spectating a
+/// session from another machine. Connections can be:
+/// - a browser, which gets a viewer page at `/`,
+/// - a WebSocket, which first gets the palette as a text message, then
+///   a binary message per frame,
+/// - anything else over TCP, which gets raw frames back to back.
+///
+/// Frames are only sent when the display changes, and new spectators
+/// get the current frame straight away. Nothing is read from spectators.
+pub struct StreamServer {
+    spectators: Arc<Mutex<Spectators>>,
+    address: SocketAddr,
+}
+
+impl StreamServer {
+    /// Starts streaming on `address`, telling viewers to show it in
+    /// `palette`.
+    pub fn start(address: &str, palette: Palette) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let spectators = Arc::new(Mutex::new(Spectators::default()));
+        let palette = Arc::new(palette_message(&palette));
+
+        let accepting = Arc::clone(&spectators);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (sender, receiver) = mpsc::sync_channel(FRAME_QUEUE);
+
+                let mut spectators = accepting.lock().unwrap();
+                if let Some(frame) = &spectators.last_frame {
+                    let _ = sender.try_send(Arc::clone(frame));
+                }
+                spectators.clients.push(sender);
+
+                // spectators leaving is business as usual, not an error.
+                let palette = Arc::clone(&palette);
+                thread::spawn(move || {
+                    let _ = serve(stream, receiver, &palette);
+                });
+            }
+        });
+
+        Ok(Self {
+            spectators,
+            address,
+        })
+    }
+
+    /// The address spectators connect to.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Sends the display to every spectator, if it changed since the
+    /// last frame. Spectators that have gone away, or that have fallen
+    /// `FRAME_QUEUE` frames behind, are forgotten.
+    pub fn broadcast(&self, display: &[u8]) {
+        let frame = pack(display);
+
+        let mut spectators = self.spectators.lock().unwrap();
+        if spectators.last_frame.as_deref() == Some(&frame) {
+            return;
+        }
+
+        let frame = Arc::new(frame);
+        spectators.clients.retain(|client| client.try_send(Arc::clone(&frame)).is_ok());
+        spectators.last_frame = Some(frame);
+    }
+}
+
+/// Packs display memory into a frame.
+pub fn pack(display: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0; FRAME_SIZE];
+    for (i, &pixel) in display.iter().enumerate() {
+        frame[i / 4] |= (pixel & 0b11) << (6 - i % 4 * 2);
+    }
+    frame
+}
+
+/// The palette as viewers get it: Octo's four colors as `#rrggbb`.
+fn palette_message(palette: &Palette) -> String {
+    let hex = |[r, g, b, _]: [u8; 4]| format!("#{:02x}{:02x}{:02x}", r, g, b);
+    json!({
+        "background": hex(palette.background),
+        "fill": hex(palette.fill),
+        "fill2": hex(palette.fill2),
+        "blend": hex(palette.blend),
+    }).to_string()
+}
+
+fn serve(mut stream: TcpStream, frames: Receiver<Frame>, palette: &str) -> Result<(), Box<dyn Error>> {
+    let request = peek_request(&stream)?;
+
+    match request {
+        Some(request) if request.to_ascii_lowercase().contains("upgrade: websocket") => {
+            let mut socket = tungstenite::accept(stream)?;
+            socket.send(Message::Text(palette.to_string()))?;
+            for frame in frames {
+                socket.send(Message::Binary(frame.to_vec()))?;
+            }
+        },
+        Some(_) => {
+            // read the request out of the way before answering it.
+            let mut head = vec![0; request_length(&stream)?];
+            stream.read_exact(&mut head)?;
+
+            write!(stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                VIEWER.len(), VIEWER)?;
+        },
+        None => {
+            for frame in frames {
+                stream.write_all(&frame)?;
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Looks at the start of what a client sent without consuming it.
+/// Returns the request head for HTTP clients, and None for clients that
+/// send something else or nothing at all.
+fn peek_request(stream: &TcpStream) -> io::Result<Option<String>> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut buffer = [0; 4096];
+    let mut request = None;
+    loop {
+        let peeked = match stream.peek(&mut buffer) {
+            Ok(0) => break,
+            Ok(peeked) => peeked,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+
+        let text = String::from_utf8_lossy(&buffer[..peeked]);
+        if !text.starts_with("GET ") {
+            break;
+        }
+        if text.contains("\r\n\r\n") || peeked == buffer.len() {
+            request = Some(text.into_owned());
+            break;
+        }
+
+        // the rest of the request is still on its way.
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    stream.set_read_timeout(None)?;
+    Ok(request)
+}
+
+/// The length of the request head waiting on a stream, up to and
+/// including the blank line.
+fn request_length(stream: &TcpStream) -> io::Result<usize> {
+    let mut buffer = [0; 4096];
+    let peeked = stream.peek(&mut buffer)?;
+    let text = &buffer[..peeked];
+
+    Ok(text.windows(4).position(|window| window == b"\r\n\r\n").map_or(peeked, |end| end + 4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_pack_the_planes_of_every_pixel() {
+        let mut display = vec![0; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize];
+        display[..4].copy_from_slice(&[0, 1, 2, 3]);
+        display[6] = 1;
+
+        let frame = pack(&display);
+
+        assert_eq!(frame.len(), FRAME_SIZE);
+        assert_eq!(&frame[..2], &[0b00_01_10_11, 0b00_00_01_00]);
+    }
+
+    #[test]
+    fn spectators_get_the_current_frame() {
+        let server = StreamServer::start("127.0.0.1:0", Palette::default()).unwrap();
+        let display = vec![3; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize];
+        server.broadcast(&display);
+
+        let mut spectator = TcpStream::connect(server.address()).unwrap();
+        let mut frame = vec![0; FRAME_SIZE];
+        spectator.read_exact(&mut frame).unwrap();
+
+        assert_eq!(frame, pack(&display));
+    }
+
+    #[test]
+    fn websockets_get_the_palette_first() {
+        let server = StreamServer::start("127.0.0.1:0", Palette::named("octo").unwrap()).unwrap();
+        server.broadcast(&vec![1; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize]);
+
+        let (mut socket, _) = tungstenite::connect(format!("ws://{}/", server.address())).unwrap();
+        let palette: serde_json::Value = match socket.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("expected the palette, got {:?}", message),
+        };
+        assert_eq!(palette["fill"], "#ffcc00");
+        assert_eq!(palette["blend"], "#662200");
+        assert!(matches!(socket.read().unwrap(), Message::Binary(frame) if frame.len() == FRAME_SIZE));
+    }
+
+    #[test]
+    fn spectators_that_fall_behind_are_let_go() {
+        let server = StreamServer::start("127.0.0.1:0", Palette::default()).unwrap();
+        let (sender, _frames) = mpsc::sync_channel(FRAME_QUEUE);
+        server.spectators.lock().unwrap().clients.push(sender);
+
+        let mut display = vec![0; (VIDEO_WIDTH * VIDEO_HEIGHT) as usize];
+        for i in 0..=FRAME_QUEUE {
+            display[i] = 1;
+            server.broadcast(&display);
+        }
+
+        assert!(server.spectators.lock().unwrap().clients.is_empty());
+    }
+
+    #[test]
+    fn browsers_get_the_viewer() {
+        let server = StreamServer::start("127.0.0.1:0", Palette::default()).unwrap();
+
+        let mut browser = TcpStream::connect(server.address()).unwrap();
+        browser.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        browser.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with(VIEWER));
+    }
+}
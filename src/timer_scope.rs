@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+use chippy::chip8::Chip8;
+
+use crate::canvas::{ self, Canvas };
+
+/// Height of the timer panel, in frame pixels.
+pub const SCOPE_HEIGHT: u32 = 120;
+
+/// The smallest top of the scale, so timers that barely move don't fill
+/// the plot.
+const MIN_SCALE: u8 = 16;
+
+const TEXT_SCALE: u32 = 2;
+const LEGEND_HEIGHT: u32 = 20;
+const BUZZER_HEIGHT: u32 = 8;
+
+/// Where the XO-CHIP audio pattern is drawn in the legend, a bit a pixel.
+const PATTERN_X: i32 = 208;
+const PATTERN_BITS: usize = 128;
+const PATTERN_HEIGHT: u32 = 14;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const GRID: [u8; 4] = [0x30, 0x30, 0x40, 0xFF];
+const DELAY: [u8; 4] = [0x48, 0xB2, 0xE8, 0xFF];
+const SOUND: [u8; 4] = [0xE8, 0xA0, 0x48, 0xFF];
+const BUZZER: [u8; 4] = [0xE8, 0x48, 0x48, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+
+/// The values of the timers over the last frames, for the debugger.
+/// Delay is plotted in blue and sound in orange, a pixel a frame with
+/// the newest on the right, and the strip underneath is lit while the
+/// buzzer sounds. Once an XO-CHIP program loads an audio pattern, the
+/// legend shows its waveform and the pitch it plays at.
+pub struct TimerScope {
+    y: i32,
+    width: u32,
+    // delay and sound timer, oldest first.
+    history: VecDeque<(u8, u8)>,
+    // the audio pattern and pitch as the last frame left them.
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
+}
+
+impl TimerScope {
+    /// A scope drawn at `y`, as wide as `width`, with a frame a pixel.
+    pub fn new(y: i32, width: u32) -> Self {
+        Self { y, width, history: VecDeque::with_capacity(width as usize), pattern: None, pitch: 64 }
+    }
+
+    /// Moves the scope to `y`, as the panels move in and out of the window.
+    pub fn move_to(&mut self, y: i32) {
+        self.y = y;
+    }
+
+    /// Keeps the timers as a frame left them.
+    pub fn record(&mut self, chippy: &Chip8) {
+        if self.history.len() == self.width as usize {
+            self.history.pop_front();
+        }
+        self.history.push_back((chippy.delay_timer(), chippy.sound_timer()));
+        self.pattern = chippy.audio_pattern().copied();
+        self.pitch = chippy.pitch();
+    }
+
+    /// The value at the top of the plot: the largest one in view, rounded
+    /// up to a power of two so the scale doesn't jitter.
+    fn scale(&self) -> u8 {
+        let largest = self.history.iter().map(|&(delay, sound)| delay.max(sound)).max().unwrap_or(0);
+        largest.max(MIN_SCALE).checked_next_power_of_two().unwrap_or(u8::MAX)
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        canvas.fill_rect(0, self.y, self.width, SCOPE_HEIGHT, BACKGROUND);
+
+        let (delay, sound) = self.history.back().copied().unwrap_or_default();
+        let scale = self.scale();
+        canvas.draw_text(8, self.y + 4, &format!("DT {:3}", delay), TEXT_SCALE, DELAY);
+        canvas.draw_text(112, self.y + 4, &format!("ST {:3}", sound), TEXT_SCALE, SOUND);
+        let top = format!("top {}", scale);
+        let top_x = self.width - top.len() as u32 * canvas::text_advance(TEXT_SCALE) - 8;
+        canvas.draw_text(top_x as i32, self.y + 4, &top, TEXT_SCALE, TEXT);
+        if let Some(pattern) = &self.pattern {
+            self.draw_pattern(canvas, pattern);
+        }
+
+        let plot_top = self.y + LEGEND_HEIGHT as i32;
+        let plot_height = SCOPE_HEIGHT - LEGEND_HEIGHT - BUZZER_HEIGHT - 4;
+        let plot_bottom = plot_top + plot_height as i32 - 1;
+        canvas.draw_line((0, plot_top), (self.width as i32 - 1, plot_top), GRID);
+        canvas.draw_line((0, plot_bottom), (self.width as i32 - 1, plot_bottom), GRID);
+
+        let y_of = |value: u8| plot_bottom - (value as u32 * (plot_height - 1) / scale as u32) as i32;
+        let buzzer_y = plot_bottom + 3;
+        let x0 = (self.width as usize - self.history.len()) as i32;
+        let mut previous: Option<(u8, u8)> = None;
+        for (i, &(delay, sound)) in self.history.iter().enumerate() {
+            let x = x0 + i as i32;
+
+            // join each point to the last, so countdowns read as slopes
+            // and reloads as edges.
+            let (last_delay, last_sound) = previous.unwrap_or((delay, sound));
+            canvas.draw_line((x, y_of(last_delay)), (x, y_of(delay)), DELAY);
+            canvas.draw_line((x, y_of(last_sound)), (x, y_of(sound)), SOUND);
+            if sound > 0 {
+                canvas.fill_rect(x, buzzer_y, 1, BUZZER_HEIGHT, BUZZER);
+            }
+
+            previous = Some((delay, sound));
+        }
+    }
+
+    /// Draws the audio pattern as a square wave, high for set bits, with
+    /// its pitch after it.
+    fn draw_pattern(&self, canvas: &mut Canvas, pattern: &[u8; 16]) {
+        let high = self.y + 4;
+        let low = high + PATTERN_HEIGHT as i32 - 1;
+        let mut last = None;
+        for bit in 0..PATTERN_BITS {
+            let x = PATTERN_X + bit as i32;
+            let y = if pattern[bit / 8] & 0x80 >> (bit % 8) != 0 { high } else { low };
+            canvas.draw_line((x, last.unwrap_or(y)), (x, y), BUZZER);
+            last = Some(y);
+        }
+
+        let pitch_x = PATTERN_X + PATTERN_BITS as i32 + 8;
+        canvas.draw_text(pitch_x, self.y + 4, &format!("pitch {:3}", self.pitch), TEXT_SCALE, BUZZER);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_keeps_a_frame_a_pixel() {
+        let mut scope = TimerScope::new(0, 4);
+        for _ in 0..6 {
+            scope.record(&Chip8::new());
+        }
+
+        assert_eq!(scope.history.len(), 4);
+        assert_eq!(scope.scale(), MIN_SCALE);
+    }
+
+    #[test]
+    fn the_audio_pattern_is_plotted() {
+        // LD I 0x204, AUDIO, then a pattern with only its first bit set
+        let mut chippy = Chip8::initialize(&[0xA2, 0x04, 0xF0, 0x02, 0x80]).unwrap();
+        for _ in 0..2 {
+            chippy.cycle().unwrap();
+        }
+
+        let (width, height) = (640, SCOPE_HEIGHT);
+        let mut frame = vec![0; (width * height * 4) as usize];
+        let mut scope = TimerScope::new(0, width);
+        scope.record(&chippy);
+        scope.draw(&mut Canvas::new(&mut frame, width, height));
+
+        let pixel = |x: i32, y: i32| {
+            let at = ((y as u32 * width + x as u32) * 4) as usize;
+            [frame[at], frame[at + 1], frame[at + 2], frame[at + 3]]
+        };
+        // the first bit is high, the rest low.
+        assert_eq!(pixel(PATTERN_X, 4), BUZZER);
+        assert_eq!(pixel(PATTERN_X + 2, 4), BACKGROUND);
+        assert_eq!(pixel(PATTERN_X + 2, 4 + PATTERN_HEIGHT as i32 - 1), BUZZER);
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use std::time::{ Duration, Instant };
+
+use crate::canvas::{ self, Canvas };
+
+/// How long a toast stays up.
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+/// How many toasts are up at once, the oldest go first.
+const MAX_TOASTS: usize = 4;
+
+const TEXT_SCALE: u32 = 2;
+const PADDING: u32 = 6;
+const MARGIN: i32 = 8;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+
+/// Short messages drawn over the top of the display for a few seconds,
+/// newest at the bottom, to give feedback without a terminal.
+#[derive(Default)]
+pub struct Toasts {
+    // with when each one goes away, and what it's about.
+    toasts: VecDeque<(Instant, Option<&'static str>, String)>,
+}
+
+impl Toasts {
+    pub fn push(&mut self, now: Instant, message: &str) {
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back((now + TOAST_DURATION, None, message.to_string()));
+    }
+
+    /// Shows a message about `topic`, replacing the last one about it if
+    /// that's still up, so pressing a key again and again doesn't fill
+    /// the display.
+    pub fn show(&mut self, now: Instant, topic: &'static str, message: &str) {
+        let shown = self.toasts.iter_mut().find(|(until, about, _)| *about == Some(topic) && *until > now);
+        match shown {
+            Some(toast) => *toast = (now + TOAST_DURATION, Some(topic), message.to_string()),
+            None => {
+                self.push(now, message);
+                if let Some(toast) = self.toasts.back_mut() {
+                    toast.1 = Some(topic);
+                }
+            },
+        }
+    }
+
+    /// Whether any toasts are still up at `now`.
+    pub fn showing(&self, now: Instant) -> bool {
+        self.toasts.iter().any(|&(until, _, _)| until > now)
+    }
+
+    /// Draws the toasts still up at `now`, forgetting the rest.
+    pub fn draw(&mut self, canvas: &mut Canvas, now: Instant) {
+        self.toasts.retain(|&(until, _, _)| until > now);
+
+        // messages too long for the frame are cut short.
+        let room = canvas.width.saturating_sub(2 * (MARGIN as u32 + PADDING)) / canvas::text_advance(TEXT_SCALE);
+        let height = canvas::line_height(TEXT_SCALE) + 2 * PADDING;
+        for (i, (_, _, message)) in self.toasts.iter().enumerate() {
+            let message = shorten(message, room as usize);
+            let y = MARGIN + i as i32 * (height as i32 + MARGIN / 2);
+            let width = message.chars().count() as u32 * canvas::text_advance(TEXT_SCALE) + 2 * PADDING;
+
+            canvas.fill_rect(MARGIN, y, width, height, BACKGROUND);
+            canvas.draw_text(MARGIN + PADDING as i32, y + (PADDING + TEXT_SCALE) as i32, &message, TEXT_SCALE, TEXT);
+        }
+    }
+}
+
+/// A message cut down to `length` characters, ending in `...` if it had
+/// to be.
+fn shorten(message: &str, length: usize) -> String {
+    if message.chars().count() <= length {
+        return message.to_string();
+    }
+
+    let mut short: String = message.chars().take(length.saturating_sub(3)).collect();
+    short.push_str("...");
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toasts_about_the_same_thing_replace_each_other() {
+        let now = Instant::now();
+        let mut toasts = Toasts::default();
+        toasts.push(now, "Cleared 10 lines!");
+        toasts.show(now, "volume", "volume 50%");
+        toasts.show(now, "volume", "volume 60%");
+        toasts.show(now + TOAST_DURATION, "volume", "volume 70%");
+
+        let messages: Vec<&str> = toasts.toasts.iter().map(|(_, _, message)| message.as_str()).collect();
+        assert_eq!(messages, ["Cleared 10 lines!", "volume 60%", "volume 70%"]);
+        assert_eq!(shorten("saved state to slot 3", 10), "saved s...");
+    }
+}
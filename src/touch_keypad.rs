@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use crate::canvas::{ self, Canvas };
+
+/// Height of the touch keypad, in frame pixels.
+pub const KEYPAD_HEIGHT: u32 = 240;
+
+/// The keys in the order they sit on the keypad.
+const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+const TEXT_SCALE: u32 = 4;
+const KEY_GAP: u32 = 4;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const KEY: [u8; 4] = [0x3C, 0x3C, 0x64, 0xFF];
+const PRESSED: [u8; 4] = [0x5E, 0x48, 0xE8, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+
+/// An on-screen keypad for touch screens, drawn under the display. It
+/// follows every finger separately, so keys can be held together and
+/// sliding a finger moves it from key to key. The mouse works too.
+pub struct TouchKeypad {
+    y: i32,
+    width: u32,
+    // the key under every finger that's down.
+    touches: HashMap<u64, u8>,
+}
+
+impl TouchKeypad {
+    /// A keypad drawn at `y`, as wide as `width`.
+    pub fn new(y: i32, width: u32) -> Self {
+        Self { y, width, touches: HashMap::new() }
+    }
+
+    /// Moves the keypad down to `y`, to make room above it.
+    pub fn move_to(&mut self, y: i32) {
+        self.y = y;
+    }
+
+    fn key_size(&self) -> (u32, u32) {
+        (self.width / 4, KEYPAD_HEIGHT / 4)
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, keypad: &[bool; 16]) {
+        canvas.fill_rect(0, self.y, self.width, KEYPAD_HEIGHT, BACKGROUND);
+
+        let (key_width, key_height) = self.key_size();
+        for (row, keys) in LAYOUT.iter().enumerate() {
+            for (col, &key) in keys.iter().enumerate() {
+                let x = (col as u32 * key_width) as i32;
+                let y = self.y + (row as u32 * key_height) as i32;
+                let color = if keypad[key as usize] { PRESSED } else { KEY };
+                canvas.fill_rect(x + KEY_GAP as i32, y + KEY_GAP as i32,
+                    key_width - 2 * KEY_GAP, key_height - 2 * KEY_GAP, color);
+
+                // center the label on the key
+                let label_x = x + (key_width - canvas::text_advance(TEXT_SCALE)) as i32 / 2;
+                let label_y = y + (key_height - canvas::line_height(TEXT_SCALE)) as i32 / 2;
+                canvas.draw_text(label_x, label_y, &format!("{:X}", key), TEXT_SCALE, TEXT);
+            }
+        }
+    }
+
+    /// The key at a position in the frame, if any.
+    fn key_at(&self, x: usize, y: usize) -> Option<u8> {
+        if (y as i32) < self.y {
+            return None;
+        }
+
+        let (key_width, key_height) = self.key_size();
+        let row = (y - self.y as usize) / key_height as usize;
+        LAYOUT.get(row)?.get(x / key_width as usize).copied()
+    }
+
+    /// Follows a finger to a position in the frame, or lifts it with
+    /// None, pressing and releasing keys on the keypad as it goes.
+    pub fn touch(&mut self, finger: u64, position: Option<(usize, usize)>, keypad: &mut [bool; 16]) {
+        let key = position.and_then(|(x, y)| self.key_at(x, y));
+        let previous = match key {
+            Some(key) => self.touches.insert(finger, key),
+            None => self.touches.remove(&finger),
+        };
+        if previous == key {
+            return;
+        }
+
+        // a key stays down while any finger is on it.
+        if let Some(previous) = previous {
+            if !self.touches.values().any(|&key| key == previous) {
+                keypad[previous as usize] = false;
+            }
+        }
+        if let Some(key) = key {
+            keypad[key as usize] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_follow_fingers() {
+        let mut touch_keypad = TouchKeypad::new(320, 640);
+        let mut keypad = [false; 16];
+
+        // two fingers on 1, then one slides over to 2
+        touch_keypad.touch(0, Some((10, 330)), &mut keypad);
+        touch_keypad.touch(1, Some((20, 330)), &mut keypad);
+        touch_keypad.touch(1, Some((170, 330)), &mut keypad);
+        assert!(keypad[0x1] && keypad[0x2]);
+
+        touch_keypad.touch(0, None, &mut keypad);
+        touch_keypad.touch(1, Some((630, 550)), &mut keypad);
+        assert_eq!(keypad.iter().filter(|&&pressed| pressed).count(), 1);
+        assert!(keypad[0xF]);
+
+        // off the keypad lets go
+        touch_keypad.touch(1, Some((630, 100)), &mut keypad);
+        assert!(!keypad[0xF]);
+    }
+}
@@ -0,0 +1,48 @@
+use core::fmt;
+
+/// A condition that stops the program and hands control over to the
+/// debugger. Most are raised by the core, breakpoints by the debugger.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trap {
+    /// A write below 0x200, into the memory reserved for the interpreter.
+    ReservedWrite { pc: usize, address: usize },
+    /// A read or write past the end of memory.
+    OutOfBounds { pc: usize, address: usize },
+    /// A CALL with all 16 stack levels in use.
+    StackOverflow { pc: usize, stack: [u16; 16] },
+    /// A RET with nothing on the stack.
+    StackUnderflow { pc: usize },
+    /// An opcode the interpreter doesn't know.
+    InvalidOpcode { pc: usize, opcode: u16 },
+    /// The program is stuck in a loop it can never leave, usually a
+    /// jump to itself at the end of the program.
+    Halted { pc: usize },
+    /// A breakpoint set in the debugger was reached.
+    Breakpoint { pc: usize },
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trap::ReservedWrite { pc, address } => write!(f,
+                "write to reserved memory at {:#05x} (pc {:#05x})", address, pc),
+            Trap::OutOfBounds { pc, address } => write!(f,
+                "memory access out of bounds at {:#x} (pc {:#05x})", address, pc),
+            Trap::StackOverflow { pc, stack } => {
+                write!(f, "stack overflow (pc {:#05x}), stack:", pc)?;
+                for address in stack.iter() {
+                    write!(f, " {:#05x}", address)?;
+                }
+                Ok(())
+            },
+            Trap::StackUnderflow { pc } => write!(f,
+                "return with an empty stack (pc {:#05x})", pc),
+            Trap::InvalidOpcode { pc, opcode } => write!(f,
+                "invalid opcode {:#06x} (pc {:#05x})", opcode, pc),
+            Trap::Halted { pc } => write!(f,
+                "program halted (pc {:#05x})", pc),
+            Trap::Breakpoint { pc } => write!(f,
+                "breakpoint (pc {:#05x})", pc),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process;
+use std::time::{ Duration, Instant, SystemTime };
+
+use crate::canvas::{ self, Canvas };
+
+/// How often the source is checked for changes.
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+const TEXT_SCALE: u32 = 2;
+const MARGIN: i32 = 8;
+
+const BACKGROUND: [u8; 4] = [0x1E, 0x1E, 0x28, 0xFF];
+const HEADING: [u8; 4] = [0xE8, 0xA0, 0x48, 0xFF];
+const TEXT: [u8; 4] = [0xE0, 0xE0, 0xE0, 0xFF];
+
+/// Assembles a ROM from its source again whenever the source is saved,
+/// for `chippy dev watch`. Chippy has no assembler of its own, so it
+/// runs one as a command, like `octo {source} {rom}`, where `{source}`
+/// and `{rom}` stand for the paths. The ROM goes next to the source,
+/// with the extension `.ch8`.
+pub struct Watch {
+    source: PathBuf,
+    command: String,
+    rom: PathBuf,
+    // when the source was last assembled, as the file's modification time.
+    assembled: Option<SystemTime>,
+    last_check: Instant,
+    /// What the assembler said the last time it failed, until it works.
+    pub errors: Option<String>,
+}
+
+impl Watch {
+    pub fn new(source: &Path, command: &str) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            command: command.to_string(),
+            rom: source.with_extension("ch8"),
+            assembled: None,
+            last_check: Instant::now(),
+            errors: None,
+        }
+    }
+
+    /// Where the ROM is assembled to.
+    pub fn rom(&self) -> &Path {
+        &self.rom
+    }
+
+    /// Assembles the source again if it's been saved since last time,
+    /// checking no more often than every `CHECK_INTERVAL`. Returns true
+    /// when there's a new ROM.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.last_check) < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = now;
+
+        let modified = fs::metadata(&self.source).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_none() || modified == self.assembled {
+            return false;
+        }
+
+        self.assemble().is_ok()
+    }
+
+    /// Runs the assembler, keeping what it says if it fails.
+    pub fn assemble(&mut self) -> Result<(), String> {
+        self.assembled = fs::metadata(&self.source).and_then(|metadata| metadata.modified()).ok();
+
+        let result = self.run_assembler();
+        match &result {
+            Ok(()) => self.errors = None,
+            Err(errors) => {
+                eprintln!("chippy: {} didn't assemble:\n{}", self.source.display(), errors);
+                self.errors = Some(errors.clone());
+            },
+        }
+        result
+    }
+
+    fn run_assembler(&self) -> Result<(), String> {
+        let (source, rom) = (self.source.to_string_lossy(), self.rom.to_string_lossy());
+        let mut words = self.command.split_whitespace()
+            .map(|word| word.replace("{source}", &source).replace("{rom}", &rom));
+        let program = words.next().ok_or("the assembler command is empty")?;
+
+        let output = process::Command::new(&program).args(words).output()
+            .map_err(|e| format!("failed to run {}: {}", program, e))?;
+        if !output.status.success() {
+            let said = [output.stderr, output.stdout].concat();
+            let said = String::from_utf8_lossy(&said).trim().to_string();
+            return Err(if said.is_empty() { format!("{} failed, {}", program, output.status) } else { said });
+        }
+        if !self.rom.exists() {
+            return Err(format!("{} didn't write {}", program, rom));
+        }
+
+        Ok(())
+    }
+
+    /// Draws the assembler's errors over `width` by `height` pixels at
+    /// the top left of the frame, if the last try failed.
+    pub fn draw(&self, canvas: &mut Canvas, width: u32, height: u32) {
+        let errors = match &self.errors {
+            Some(errors) => errors,
+            None => return,
+        };
+
+        let line_height = canvas::line_height(TEXT_SCALE) as i32;
+        canvas.fill_rect(0, 0, width, height, BACKGROUND);
+        let heading = format!("{} didn't assemble, save it to try again", self.source.display());
+        canvas.draw_text(MARGIN, MARGIN, &heading, TEXT_SCALE, HEADING);
+
+        let columns = ((width - 2 * MARGIN as u32) / canvas::text_advance(TEXT_SCALE)) as usize;
+        let top = MARGIN + 2 * line_height;
+        let fits = ((height as i32 - top) / line_height).max(0) as usize;
+        for (i, line) in split(errors, columns).iter().take(fits).enumerate() {
+            canvas.draw_text(MARGIN, top + i as i32 * line_height, line, TEXT_SCALE, TEXT);
+        }
+    }
+}
+
+/// Breaks text into lines of at most `columns` characters, keeping the
+/// lines it already has.
+fn split(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        for part in chars.chunks(columns.max(1)) {
+            lines.push(part.iter().collect());
+        }
+    }
+    lines
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_assembler_is_run_on_the_source() {
+        let dir = std::env::temp_dir().join(format!("chippy-watch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("game.asm");
+        fs::write(&source, "00e0").unwrap();
+
+        let mut watch = Watch::new(&source, "cp {source} {rom}");
+        assert_eq!(watch.assemble(), Ok(()));
+        assert_eq!(fs::read(dir.join("game.ch8")).unwrap(), b"00e0");
+
+        let mut watch = Watch::new(&source, "sh -c exit");
+        watch.rom = dir.join("nowhere.ch8");
+        assert!(watch.assemble().is_err());
+        assert!(watch.errors.is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(split("ab\n\nabcde", 2), ["ab", "", "ab", "cd", "e"]);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::error::Error;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use serde::{ Deserialize, Serialize };
+use winit::event::VirtualKeyCode;
+
+use chippy::quirks::Quirks;
+use chippy::state::MachineState;
+
+use crate::command::{ self, Session };
+
+/// The extension of workspace files.
+pub const EXTENSION: &str = "chippy";
+
+/// A ROM and everything set up around it, in one JSON file that
+/// `chippy open` carries on from: the quirks, the second player's keys,
+/// the speed, the breakpoints, the save-state slots and the machine as
+/// it was left. States are kept as they're exported (see
+/// `MachineState::to_json`), so older ones are migrated like any other.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Workspace {
+    /// Where the workspace is kept.
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// A path relative to the workspace file, or an http(s) URL.
+    pub rom: String,
+    /// A quirk profile, built in or from chippy.toml, to use instead of
+    /// `quirks`, for workspaces written by hand.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub quirks: Option<Quirks>,
+    pub player_two: Option<HashMap<VirtualKeyCode, u8>>,
+    pub instructions_per_frame: Option<u32>,
+    pub breakpoints: Vec<u16>,
+    /// The slot F2 and F3 save to and load from.
+    pub slot: u32,
+    slots: BTreeMap<u32, serde_json::Value>,
+    machine: Option<serde_json::Value>,
+}
+
+impl Workspace {
+    /// A new workspace for a session, `<name>.chippy` in the current
+    /// directory, so the ROM's path works from there.
+    pub fn new(session: &Session, player_two: &HashMap<VirtualKeyCode, u8>) -> Self {
+        Self {
+            path: PathBuf::from(format!("{}.{}", command::source_name(&session.source), EXTENSION)),
+            rom: session.source.clone(),
+            player_two: Some(player_two.clone()),
+            ..Self::default()
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut workspace: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        workspace.path = path.to_path_buf();
+        Ok(workspace)
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        fs::write(&self.path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The ROM, found from where the workspace is.
+    pub fn rom_path(&self) -> String {
+        let is_url = self.rom.starts_with("http://") || self.rom.starts_with("https://");
+        match self.path.parent() {
+            Some(dir) if !is_url && self.rom != "-" => dir.join(&self.rom).to_string_lossy().into_owned(),
+            _ => self.rom.clone(),
+        }
+    }
+
+    /// The state saved to a slot.
+    pub fn state(&self, slot: u32) -> Result<MachineState, Box<dyn Error>> {
+        let state = self.slots.get(&slot).ok_or("nothing's been saved to it")?;
+        MachineState::from_json(&state.to_string())
+    }
+
+    pub fn set_state(&mut self, slot: u32, state: &MachineState) {
+        self.slots.insert(slot, embed(state));
+    }
+
+    /// The machine as it was when the workspace was last written, if it
+    /// was kept.
+    pub fn machine(&self) -> Option<Result<MachineState, Box<dyn Error>>> {
+        self.machine.as_ref().map(|state| MachineState::from_json(&state.to_string()))
+    }
+
+    /// Keeps how a session is now: its machine, quirks, speed and
+    /// breakpoints, and the slot in use.
+    pub fn update(&mut self, session: &Session, slot: u32) {
+        self.quirks = Some(session.chippy.quirks);
+        self.instructions_per_frame = Some(session.instructions_per_frame);
+        self.breakpoints = session.debugger.breakpoints().map(|address| address as u16).collect();
+        self.slot = slot;
+        self.machine = Some(embed(&session.chippy.save_state()));
+    }
+}
+
+/// A state as it's exported, to go in the workspace's JSON.
+fn embed(state: &MachineState) -> serde_json::Value {
+    serde_json::from_str(&state.to_json()).expect("exported states are JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use chippy::chip8::Chip8;
+
+    use super::*;
+
+    #[test]
+    fn workspaces_keep_the_setup_and_the_states() {
+        let mut session = Session::new(Chip8::initialize(&[0x63, 0x07, 0x12, 0x02]).unwrap(), "roms/game.ch8");
+        session.debugger.toggle_breakpoint(0x202);
+        session.chippy.cycle().unwrap();
+        let player_two: HashMap<VirtualKeyCode, u8> = [(VirtualKeyCode::Up, 0x2)].iter().copied().collect();
+        let mut workspace = Workspace::new(&session, &player_two);
+        workspace.update(&session, 2);
+        workspace.set_state(2, &session.chippy.save_state());
+
+        let mut loaded: Workspace = serde_json::from_str(&serde_json::to_string(&workspace).unwrap()).unwrap();
+        loaded.path = PathBuf::from("runs/game.chippy");
+        assert_eq!(loaded.rom_path(), "runs/roms/game.ch8");
+        assert_eq!(loaded.player_two, Some(player_two));
+        assert_eq!(loaded.breakpoints, vec![0x202]);
+        assert_eq!(loaded.slot, 2);
+        assert_eq!(loaded.state(2).unwrap().registers[3], 0x07);
+        assert_eq!(loaded.machine().unwrap().unwrap(), session.chippy.save_state());
+        assert!(loaded.state(0).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::Write;
+use std::time::SystemTime;
+
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+
+use crate::dump::Utc;
+
+const LOCAL_HEADER: u32 = 0x0403_4B50;
+const CENTRAL_HEADER: u32 = 0x0201_4B50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4B50;
+
+/// Zip 2.0, the first version with deflate.
+const VERSION: u16 = 20;
+const DEFLATED: u16 = 8;
+/// The flag for names in UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+
+/// Builds a zip archive in memory, every file deflated. Just enough of
+/// the format for bug reports: no directories, no zip64, so files and
+/// the whole archive have to stay under 4GiB.
+pub struct ZipWriter {
+    bytes: Vec<u8>,
+    central_directory: Vec<u8>,
+    files: u16,
+    // when the files were made, as an MS-DOS time and date.
+    time: u16,
+    date: u16,
+}
+
+impl ZipWriter {
+    pub fn new(time: SystemTime) -> Self {
+        let time = Utc::of(time);
+        // MS-DOS dates start in 1980.
+        let year = (time.year - 1980).clamp(0, 127) as u16;
+
+        Self {
+            bytes: Vec::new(),
+            central_directory: Vec::new(),
+            files: 0,
+            time: (time.hour as u16) << 11 | (time.minute as u16) << 5 | (time.second as u16 / 2),
+            date: year << 9 | (time.month as u16) << 5 | time.day as u16,
+        }
+    }
+
+    pub fn add(&mut self, name: &str, contents: &[u8]) {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents).expect("writing to a Vec can't fail");
+        let compressed = encoder.finish().expect("writing to a Vec can't fail");
+        let crc = crc32fast::hash(contents);
+        let offset = self.bytes.len() as u32;
+
+        // the fields the local header and the central directory share.
+        let mut common = Vec::new();
+        for field in [VERSION, UTF8_NAMES, DEFLATED, self.time, self.date] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [crc, compressed.len() as u32, contents.len() as u32] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes()); // no extra field
+
+        self.bytes.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        self.bytes.extend_from_slice(&common);
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes.extend_from_slice(&compressed);
+
+        self.central_directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        self.central_directory.extend_from_slice(&VERSION.to_le_bytes()); // made by
+        self.central_directory.extend_from_slice(&common);
+        // no comment, on the first disk, no attributes
+        self.central_directory.extend_from_slice(&[0; 10]);
+        self.central_directory.extend_from_slice(&offset.to_le_bytes());
+        self.central_directory.extend_from_slice(name.as_bytes());
+
+        self.files += 1;
+    }
+
+    /// The archive, with the central directory at the end.
+    pub fn finish(mut self) -> Vec<u8> {
+        let offset = self.bytes.len() as u32;
+        let size = self.central_directory.len() as u32;
+        self.bytes.append(&mut self.central_directory);
+
+        self.bytes.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        self.bytes.extend_from_slice(&[0; 4]); // the first and only disk
+        self.bytes.extend_from_slice(&self.files.to_le_bytes());
+        self.bytes.extend_from_slice(&self.files.to_le_bytes());
+        self.bytes.extend_from_slice(&size.to_le_bytes());
+        self.bytes.extend_from_slice(&offset.to_le_bytes());
+        self.bytes.extend_from_slice(&0u16.to_le_bytes()); // no comment
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::DeflateDecoder;
+
+    use super::*;
+
+    #[test]
+    fn files_are_deflated_behind_their_headers() {
+        let mut zip = ZipWriter::new(SystemTime::UNIX_EPOCH);
+        zip.add("report.txt", b"hello hello hello");
+        let bytes = zip.finish();
+
+        assert!(bytes.starts_with(&LOCAL_HEADER.to_le_bytes()));
+        assert_eq!(&bytes[30..40], b"report.txt");
+        let mut contents = String::new();
+        DeflateDecoder::new(&bytes[40..]).read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello hello hello");
+
+        // one file in the directory at the end
+        let end = &bytes[bytes.len() - 22..];
+        assert!(end.starts_with(&END_OF_CENTRAL_DIRECTORY.to_le_bytes()));
+        assert_eq!(&end[8..10], &1u16.to_le_bytes());
+    }
+}